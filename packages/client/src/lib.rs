@@ -1,3 +1,312 @@
 #![cfg_attr(feature = "fail-on-warnings", deny(warnings))]
 
-pub fn init() {}
+use std::{fmt, future::Future, pin::Pin, sync::Arc};
+
+use moosicbox_audio_zone::models::{ApiAudioZoneWithSession, ApiPlayer};
+use moosicbox_library::models::{ApiAlbum, ApiArtist, ApiTrack};
+use moosicbox_paging::Page;
+use moosicbox_session::models::{ApiSession, RegisterPlayer};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+pub mod models;
+
+use models::{AlbumsRequest, ApiProfile, ArtistsRequest, SearchResults};
+
+/// Page size used by `all_pages` callers that don't need a specific one
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error(transparent)]
+    Reqwest(#[from] reqwest::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error("Unauthorized ({0})")]
+    Unauthorized(u16),
+    #[error("Request failed with status {status}")]
+    Http { status: u16, body: String },
+    /// Returned by the sender set with `with_sender`
+    #[error(transparent)]
+    Sender(Box<dyn std::error::Error + Send + Sync>),
+}
+
+type SendFuture = Pin<Box<dyn Future<Output = Result<Response, ClientError>> + Send>>;
+type Sender = Arc<dyn Fn(RequestBuilder) -> SendFuture + Send + Sync>;
+
+/// Typed client for the MoosicBox server API.
+///
+/// Every request carries the configured profile, API token and client id, so callers never
+/// build auth headers or query strings themselves.
+#[derive(Clone)]
+pub struct MoosicBoxClient {
+    http: reqwest::Client,
+    sender: Option<Sender>,
+    api_url: String,
+    profile: Option<String>,
+    api_token: Option<String>,
+    client_id: Option<String>,
+}
+
+impl fmt::Debug for MoosicBoxClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MoosicBoxClient")
+            .field("api_url", &self.api_url)
+            .field("profile", &self.profile)
+            .field("client_id", &self.client_id)
+            .field("sender", &self.sender.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl MoosicBoxClient {
+    pub fn new(api_url: impl Into<String>) -> Self {
+        let mut api_url = api_url.into();
+        while api_url.ends_with('/') {
+            api_url.pop();
+        }
+
+        Self {
+            http: reqwest::Client::new(),
+            sender: None,
+            api_url,
+            profile: None,
+            api_token: None,
+            client_id: None,
+        }
+    }
+
+    /// Sends requests through `http`, e.g. to share its connection pool and TLS config
+    #[must_use]
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Sends requests with `sender` instead of `RequestBuilder::send`, e.g. to apply retries,
+    /// timeouts and cancellation
+    #[must_use]
+    pub fn with_sender<F, Fut>(mut self, sender: F) -> Self
+    where
+        F: Fn(RequestBuilder) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Response, ClientError>> + Send + 'static,
+    {
+        self.sender = Some(Arc::new(move |builder| Box::pin(sender(builder))));
+        self
+    }
+
+    /// Sent as the `moosicbox-profile` header, which library and session requests require
+    #[must_use]
+    pub fn with_profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    /// Sent as a bearer `Authorization` header
+    #[must_use]
+    pub fn with_api_token(mut self, api_token: impl Into<String>) -> Self {
+        self.api_token = Some(api_token.into());
+        self
+    }
+
+    /// Sent as the `clientId` query parameter, which routes requests through a tunnel server
+    #[must_use]
+    pub fn with_client_id(mut self, client_id: impl Into<String>) -> Self {
+        self.client_id = Some(client_id.into()).filter(|x: &String| !x.is_empty());
+        self
+    }
+
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    /// The profiles on the server. Unlike most endpoints this one doesn't depend on the profile.
+    pub async fn profiles(&self) -> Result<Vec<ApiProfile>, ClientError> {
+        self.send(self.request(Method::GET, "config/profiles"))
+            .await
+    }
+
+    pub async fn sessions(&self, offset: u32, limit: u32) -> Result<Page<ApiSession>, ClientError> {
+        self.get("session/sessions", &[("offset", offset), ("limit", limit)])
+            .await
+    }
+
+    pub async fn session(&self, session_id: u64) -> Result<ApiSession, ClientError> {
+        self.get("session/session", &[("sessionId", session_id)])
+            .await
+    }
+
+    /// Registers the audio outputs of the WS connection `connection_id` as players
+    pub async fn register_players(
+        &self,
+        connection_id: &str,
+        players: &[RegisterPlayer],
+    ) -> Result<Vec<ApiPlayer>, ClientError> {
+        let builder = self
+            .request(Method::POST, "session/register-players")
+            .query(&[("connectionId", connection_id)])
+            .json(players);
+
+        self.send(builder).await
+    }
+
+    pub async fn audio_zones_with_session(
+        &self,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Page<ApiAudioZoneWithSession>, ClientError> {
+        self.get(
+            "audio-zone/with-session",
+            &[("offset", offset), ("limit", limit)],
+        )
+        .await
+    }
+
+    pub async fn albums(&self, request: &AlbumsRequest) -> Result<Page<ApiAlbum>, ClientError> {
+        self.get("menu/albums", request).await
+    }
+
+    pub async fn album(&self, album_id: u64) -> Result<ApiAlbum, ClientError> {
+        self.get("menu/album", &[("albumId", album_id)]).await
+    }
+
+    pub async fn album_tracks(&self, album_id: u64) -> Result<Vec<ApiTrack>, ClientError> {
+        self.get("menu/album/tracks", &[("albumId", album_id)])
+            .await
+    }
+
+    pub async fn artists(&self, request: &ArtistsRequest) -> Result<Vec<ApiArtist>, ClientError> {
+        self.get("menu/artists", request).await
+    }
+
+    pub async fn artist(&self, artist_id: u64) -> Result<ApiArtist, ClientError> {
+        self.get("menu/artist", &[("artistId", artist_id)]).await
+    }
+
+    pub async fn artist_albums(
+        &self,
+        artist_id: u64,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Page<ApiAlbum>, ClientError> {
+        self.get(
+            "menu/artist/albums",
+            &[
+                ("artistId", artist_id),
+                ("offset", u64::from(offset)),
+                ("limit", u64::from(limit)),
+            ],
+        )
+        .await
+    }
+
+    pub async fn tracks(&self, track_ids: &[u64]) -> Result<Vec<ApiTrack>, ClientError> {
+        let track_ids = track_ids
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        self.get("menu/tracks", &[("trackIds", track_ids)]).await
+    }
+
+    pub async fn search(
+        &self,
+        query: &str,
+        offset: u32,
+        limit: u32,
+    ) -> Result<SearchResults, ClientError> {
+        self.get(
+            "search/global-search",
+            &[
+                ("query", query.to_string()),
+                ("offset", offset.to_string()),
+                ("limit", limit.to_string()),
+            ],
+        )
+        .await
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &(impl Serialize + ?Sized),
+    ) -> Result<T, ClientError> {
+        self.send(self.request(Method::GET, path).query(query))
+            .await
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let url = format!("{}/{path}", self.api_url);
+        log::debug!("MoosicBoxClient: {method} {url}");
+
+        let mut builder = self.http.request(method, url);
+
+        if let Some(client_id) = &self.client_id {
+            builder = builder.query(&[("clientId", client_id)]);
+        }
+        if let Some(api_token) = &self.api_token {
+            builder = builder.bearer_auth(api_token);
+        }
+        if let Some(profile) = &self.profile {
+            builder = builder.header("moosicbox-profile", profile);
+        }
+
+        builder
+    }
+
+    async fn send<T: DeserializeOwned>(&self, builder: RequestBuilder) -> Result<T, ClientError> {
+        let response = match &self.sender {
+            Some(sender) => sender(builder).await?,
+            None => builder.send().await?,
+        };
+
+        parse(response).await
+    }
+}
+
+async fn parse<T: DeserializeOwned>(response: Response) -> Result<T, ClientError> {
+    let status = response.status();
+
+    if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+        return Err(ClientError::Unauthorized(status.as_u16()));
+    }
+
+    let body = response.bytes().await?;
+
+    if !status.is_success() {
+        return Err(ClientError::Http {
+            status: status.as_u16(),
+            body: String::from_utf8_lossy(&body).into_owned(),
+        });
+    }
+
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// Fetches every item of a paged endpoint, `limit` items at a time, e.g.
+/// `all_pages(DEFAULT_PAGE_SIZE, |offset, limit| client.sessions(offset, limit))`
+pub async fn all_pages<T, F, Fut>(limit: u32, mut fetch: F) -> Result<Vec<T>, ClientError>
+where
+    F: FnMut(u32, u32) -> Fut,
+    Fut: Future<Output = Result<Page<T>, ClientError>>,
+{
+    let mut items = vec![];
+    let mut offset = 0;
+
+    loop {
+        let page = fetch(offset, limit).await?;
+        let has_more = page.has_more();
+        let page_items = page.items();
+        let count = u32::try_from(page_items.len()).unwrap_or(u32::MAX);
+
+        items.extend(page_items);
+
+        if !has_more || count == 0 {
+            return Ok(items);
+        }
+
+        offset += count;
+    }
+}
@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+/// Filters of `MoosicBoxClient::albums`. Unset fields use the server defaults.
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumsRequest {
+    /// Comma separated album sources, e.g. `LOCAL,TIDAL`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sources: Option<String>,
+    /// e.g. `Name-Asc` or `Release-Date-Desc`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+/// Filters of `MoosicBoxClient::artists`. Unset fields use the server defaults.
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistsRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sources: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search: Option<String>,
+}
+
+/// A server profile. Each has its own library and sessions.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiProfile {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResults {
+    /// Offset of the first result
+    pub position: u32,
+    pub results: Vec<SearchResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type")]
+pub enum SearchResult {
+    Artist(SearchArtist),
+    Album(SearchAlbum),
+    Track(SearchTrack),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchArtist {
+    pub artist_id: u64,
+    pub title: String,
+    #[serde(default)]
+    pub contains_cover: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchAlbum {
+    pub album_id: u64,
+    pub title: String,
+    pub artist_id: u64,
+    pub artist: String,
+    #[serde(default)]
+    pub contains_cover: bool,
+    pub date_released: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchTrack {
+    pub track_id: u64,
+    pub title: String,
+    pub album_id: u64,
+    pub album: String,
+    pub artist_id: u64,
+    pub artist: String,
+    #[serde(default)]
+    pub contains_cover: bool,
+}
@@ -0,0 +1,344 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use moosicbox_app_client::{
+    all_pages,
+    models::{AlbumsRequest, ApiProfile, SearchResult, SearchTrack},
+    ClientError, MoosicBoxClient,
+};
+use moosicbox_app_http_test_utils::{MockResponse, MockServer};
+use moosicbox_paging::Page;
+use moosicbox_session::models::RegisterPlayer;
+use serde_json::json;
+
+fn empty_page() -> serde_json::Value {
+    json!({"items": [], "offset": 0, "limit": 100, "total": 0})
+}
+
+fn client(server: &MockServer) -> MoosicBoxClient {
+    MoosicBoxClient::new(server.url())
+        .with_profile("master")
+        .with_api_token("token")
+        .with_client_id("client")
+}
+
+#[tokio::test]
+async fn sends_profile_auth_and_client_id() {
+    let server = MockServer::start().await;
+    server.route("GET", "/audio-zone/with-session", |_| {
+        MockResponse::json(&empty_page())
+    });
+
+    let zones = client(&server)
+        .audio_zones_with_session(0, 100)
+        .await
+        .unwrap();
+    assert!(zones.items().is_empty());
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    let request = &requests[0];
+    assert_eq!(request.header("moosicbox-profile"), Some("master"));
+    assert_eq!(request.header("authorization"), Some("Bearer token"));
+    assert_eq!(request.query("clientId"), Some("client"));
+    assert_eq!(request.query("offset"), Some("0"));
+    assert_eq!(request.query("limit"), Some("100"));
+}
+
+#[tokio::test]
+async fn omits_unset_credentials() {
+    let server = MockServer::start().await;
+    server.route("GET", "/session/sessions", |_| {
+        MockResponse::json(&empty_page())
+    });
+
+    MoosicBoxClient::new(server.url())
+        .with_client_id("")
+        .sessions(0, 10)
+        .await
+        .unwrap();
+
+    let request = &server.requests()[0];
+    assert_eq!(request.header("moosicbox-profile"), None);
+    assert_eq!(request.header("authorization"), None);
+    assert_eq!(request.query("clientId"), None);
+}
+
+#[tokio::test]
+async fn profiles_parses_the_profile_list() {
+    let server = MockServer::start().await;
+    server.route("GET", "/config/profiles", |_| {
+        MockResponse::json(&json!([{"name": "master"}, {"name": "other"}]))
+    });
+
+    let profiles = client(&server).profiles().await.unwrap();
+
+    assert_eq!(
+        profiles,
+        vec![
+            ApiProfile {
+                name: "master".to_string()
+            },
+            ApiProfile {
+                name: "other".to_string()
+            },
+        ]
+    );
+    assert_eq!(
+        server.requests()[0].header("authorization"),
+        Some("Bearer token")
+    );
+}
+
+#[tokio::test]
+async fn register_players_posts_players_as_json() {
+    let server = MockServer::start().await;
+    server.route("POST", "/session/register-players", |_| {
+        MockResponse::json(&json!([]))
+    });
+
+    let players = client(&server)
+        .register_players(
+            "connection-1",
+            &[RegisterPlayer {
+                audio_output_id: "output-1".to_string(),
+                name: "Speakers".to_string(),
+            }],
+        )
+        .await
+        .unwrap();
+    assert!(players.is_empty());
+
+    let request = &server.requests()[0];
+    assert_eq!(request.query("connectionId"), Some("connection-1"));
+    assert_eq!(request.query("clientId"), Some("client"));
+    assert_eq!(request.header("content-type"), Some("application/json"));
+    assert_eq!(
+        request.json(),
+        json!([{"audioOutputId": "output-1", "name": "Speakers"}])
+    );
+}
+
+#[tokio::test]
+async fn trims_trailing_slashes_from_api_url() {
+    let server = MockServer::start().await;
+    server.route("GET", "/menu/album/tracks", |_| {
+        MockResponse::json(&json!([]))
+    });
+
+    let client = MoosicBoxClient::new(format!("{}//", server.url()));
+    assert_eq!(client.api_url(), server.url());
+
+    client.album_tracks(5).await.unwrap();
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/menu/album/tracks");
+    assert_eq!(request.query("albumId"), Some("5"));
+}
+
+#[tokio::test]
+async fn albums_only_sends_set_filters() {
+    let server = MockServer::start().await;
+    server.route("GET", "/menu/albums", |_| MockResponse::json(&empty_page()));
+
+    client(&server)
+        .albums(&AlbumsRequest {
+            sort: Some("Name-Asc".to_string()),
+            search: Some("blue train".to_string()),
+            limit: Some(20),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let request = &server.requests()[0];
+    assert_eq!(request.query("sort"), Some("Name-Asc"));
+    assert_eq!(request.query("search"), Some("blue train"));
+    assert_eq!(request.query("limit"), Some("20"));
+    assert_eq!(request.query("offset"), None);
+    assert_eq!(request.query("sources"), None);
+    assert_eq!(request.query("clientId"), Some("client"));
+}
+
+#[tokio::test]
+async fn tracks_sends_comma_separated_ids() {
+    let server = MockServer::start().await;
+    server.route("GET", "/menu/tracks", |_| MockResponse::json(&json!([])));
+
+    client(&server).tracks(&[1, 2, 3]).await.unwrap();
+
+    assert_eq!(server.requests()[0].query("trackIds"), Some("1,2,3"));
+}
+
+#[tokio::test]
+async fn search_parses_typed_results() {
+    let server = MockServer::start().await;
+    server.route("GET", "/search/global-search", |_| {
+        MockResponse::json(&json!({
+            "position": 0,
+            "results": [
+                {"type": "Artist", "artistId": 1, "title": "John Coltrane", "containsCover": true},
+                {
+                    "type": "Track",
+                    "trackId": 3,
+                    "title": "Blue Train",
+                    "albumId": 2,
+                    "album": "Blue Train",
+                    "artistId": 1,
+                    "artist": "John Coltrane",
+                    "containsCover": false
+                }
+            ]
+        }))
+    });
+
+    let results = client(&server).search("coltrane", 0, 10).await.unwrap();
+
+    assert_eq!(results.position, 0);
+    assert_eq!(results.results.len(), 2);
+    assert!(matches!(&results.results[0], SearchResult::Artist(x) if x.artist_id == 1));
+    assert_eq!(
+        results.results[1],
+        SearchResult::Track(SearchTrack {
+            track_id: 3,
+            title: "Blue Train".to_string(),
+            album_id: 2,
+            album: "Blue Train".to_string(),
+            artist_id: 1,
+            artist: "John Coltrane".to_string(),
+            contains_cover: false,
+        })
+    );
+
+    let request = &server.requests()[0];
+    assert_eq!(request.query("query"), Some("coltrane"));
+    assert_eq!(request.query("limit"), Some("10"));
+}
+
+#[tokio::test]
+async fn rejected_credentials_are_unauthorized() {
+    let server = MockServer::start().await;
+    server.route("GET", "/session/session", |_| MockResponse::status(401, ""));
+    server.route("GET", "/menu/album", |_| MockResponse::status(403, ""));
+
+    let client = client(&server);
+
+    assert!(matches!(
+        client.session(1).await,
+        Err(ClientError::Unauthorized(401))
+    ));
+    assert!(matches!(
+        client.album(1).await,
+        Err(ClientError::Unauthorized(403))
+    ));
+}
+
+#[tokio::test]
+async fn error_status_includes_body() {
+    let server = MockServer::start().await;
+    server.route("GET", "/menu/artist", |_| {
+        MockResponse::status(500, "database is locked")
+    });
+
+    let result = client(&server).artist(1).await;
+
+    let Err(ClientError::Http { status, body }) = result else {
+        panic!("Expected an Http error, got {result:?}");
+    };
+    assert_eq!(status, 500);
+    assert_eq!(body, "database is locked");
+}
+
+#[tokio::test]
+async fn missing_route_is_http_error() {
+    let server = MockServer::start().await;
+
+    let result = client(&server).artist(1).await;
+
+    assert!(matches!(result, Err(ClientError::Http { status: 404, .. })));
+}
+
+#[tokio::test]
+async fn invalid_json_is_serde_error() {
+    let server = MockServer::start().await;
+    server.route("GET", "/menu/tracks", |_| {
+        MockResponse::status(200, "{\"items\":")
+    });
+
+    let result = client(&server).tracks(&[1]).await;
+
+    assert!(matches!(result, Err(ClientError::Serde(_))));
+}
+
+#[tokio::test]
+async fn requests_are_sent_with_the_sender() {
+    let server = MockServer::start().await;
+    server.route("GET", "/session/session", |_| MockResponse::status(401, ""));
+    let sent = Arc::new(AtomicUsize::new(0));
+
+    let client = client(&server).with_sender({
+        let sent = sent.clone();
+        move |builder| {
+            sent.fetch_add(1, Ordering::SeqCst);
+            let request = builder.header("x-sender", "app").send();
+            async move { Ok(request.await?) }
+        }
+    });
+    let result = client.session(1).await;
+
+    assert!(matches!(result, Err(ClientError::Unauthorized(401))));
+    assert_eq!(sent.load(Ordering::SeqCst), 1);
+    let request = &server.requests()[0];
+    assert_eq!(request.header("x-sender"), Some("app"));
+    assert_eq!(request.header("moosicbox-profile"), Some("master"));
+}
+
+#[tokio::test]
+async fn sender_errors_are_returned() {
+    let server = MockServer::start().await;
+
+    let result = client(&server)
+        .with_sender(|_| async {
+            Err(ClientError::Sender(Box::new(std::io::Error::other(
+                "cancelled",
+            ))))
+        })
+        .artist(1)
+        .await;
+
+    let Err(ClientError::Sender(e)) = result else {
+        panic!("Expected a Sender error, got {result:?}");
+    };
+    assert_eq!(e.to_string(), "cancelled");
+    assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn all_pages_follows_has_more() {
+    let mut offsets = vec![];
+
+    let items = all_pages(2, |offset, limit| {
+        offsets.push((offset, limit));
+        let page = match offset {
+            0 => json!({"items": [1, 2], "offset": 0, "limit": 2, "hasMore": true}),
+            2 => json!({"items": [3, 4], "offset": 2, "limit": 2, "total": 5}),
+            _ => json!({"items": [5], "offset": 4, "limit": 2, "total": 5}),
+        };
+        async move { Ok(serde_json::from_value::<Page<u64>>(page)?) }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(items, vec![1, 2, 3, 4, 5]);
+    assert_eq!(offsets, vec![(0, 2), (2, 2), (4, 2)]);
+}
+
+#[tokio::test]
+async fn all_pages_stops_on_error() {
+    let result =
+        all_pages::<u64, _, _>(2, |_, _| async { Err(ClientError::Unauthorized(401)) }).await;
+
+    assert!(matches!(result, Err(ClientError::Unauthorized(401))));
+}
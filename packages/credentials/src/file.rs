@@ -0,0 +1,194 @@
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    io::{ErrorKind, Read as _, Write as _},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    hkdf::{Salt, HKDF_SHA256},
+    rand::{SecureRandom as _, SystemRandom},
+};
+
+use crate::{CredentialBackend, CredentialError};
+
+const SECRET_FILE_NAME: &str = "credentials.key";
+const CREDENTIALS_FILE_NAME: &str = "credentials.enc";
+const SECRET_LEN: usize = 32;
+const FILE_VERSION: u8 = 1;
+const KEY_SALT: &[u8] = b"moosicbox-app-credentials";
+const KEY_INFO: &[u8] = b"moosicbox-app-credentials-v1";
+
+/// Stores credentials in a single AES-256-GCM encrypted file.
+///
+/// The key is derived with HKDF-SHA256 from a random per-install secret that is created next
+/// to the credentials file on first use. Both files are only readable by the current user.
+pub struct FileBackend {
+    path: PathBuf,
+    key: LessSafeKey,
+    rng: SystemRandom,
+    lock: Mutex<()>,
+}
+
+impl FileBackend {
+    /// Opens the store in `dir`, creating the directory and install secret if needed
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, CredentialError> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let rng = SystemRandom::new();
+        let secret = load_or_create_secret(&dir.join(SECRET_FILE_NAME), &rng)?;
+
+        let key: UnboundKey = Salt::new(HKDF_SHA256, KEY_SALT)
+            .extract(&secret)
+            .expand(&[KEY_INFO], &AES_256_GCM)
+            .map_err(|_| CredentialError::InvalidSecret)?
+            .into();
+
+        Ok(Self {
+            path: dir.join(CREDENTIALS_FILE_NAME),
+            key: LessSafeKey::new(key),
+            rng,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Path of the encrypted credentials file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self) -> Result<BTreeMap<String, String>, CredentialError> {
+        let mut contents = match std::fs::read(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        if contents.len() < 1 + NONCE_LEN {
+            return Err(CredentialError::Decrypt);
+        }
+
+        let version = contents[0];
+        if version != FILE_VERSION {
+            return Err(CredentialError::UnsupportedVersion(version));
+        }
+
+        let nonce = Nonce::try_assume_unique_for_key(&contents[1..1 + NONCE_LEN])
+            .map_err(|_| CredentialError::Decrypt)?;
+
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::from([version]), &mut contents[1 + NONCE_LEN..])
+            .map_err(|_| CredentialError::Decrypt)?;
+
+        Ok(serde_json::from_slice(plaintext)?)
+    }
+
+    fn write(&self, values: &BTreeMap<String, String>) -> Result<(), CredentialError> {
+        let mut nonce = [0; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| CredentialError::Encrypt)?;
+
+        let mut ciphertext = serde_json::to_vec(values)?;
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from([FILE_VERSION]),
+                &mut ciphertext,
+            )
+            .map_err(|_| CredentialError::Encrypt)?;
+
+        let mut contents = Vec::with_capacity(1 + NONCE_LEN + ciphertext.len());
+        contents.push(FILE_VERSION);
+        contents.extend_from_slice(&nonce);
+        contents.extend_from_slice(&ciphertext);
+
+        let tmp_path = self.path.with_extension("enc.tmp");
+        private_file(&tmp_path, true)?.write_all(&contents)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+
+        Ok(())
+    }
+
+    fn update(
+        &self,
+        f: impl FnOnce(&mut BTreeMap<String, String>) -> bool,
+    ) -> Result<(), CredentialError> {
+        let _lock = self.lock.lock().unwrap();
+        let mut values = self.read()?;
+
+        if f(&mut values) {
+            self.write(&values)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl CredentialBackend for FileBackend {
+    fn get(&self, key: &str) -> Result<Option<String>, CredentialError> {
+        let _lock = self.lock.lock().unwrap();
+        Ok(self.read()?.remove(key))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), CredentialError> {
+        self.update(|values| {
+            values.insert(key.to_string(), value.to_string()).as_deref() != Some(value)
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), CredentialError> {
+        self.update(|values| values.remove(key).is_some())
+    }
+}
+
+fn load_or_create_secret(
+    path: &Path,
+    rng: &SystemRandom,
+) -> Result<[u8; SECRET_LEN], CredentialError> {
+    let mut secret = [0; SECRET_LEN];
+
+    match OpenOptions::new().read(true).open(path) {
+        Ok(mut file) => {
+            let mut contents = vec![];
+            file.read_to_end(&mut contents)?;
+            if contents.len() != SECRET_LEN {
+                return Err(CredentialError::InvalidSecret);
+            }
+            secret.copy_from_slice(&contents);
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            log::debug!("Creating credentials install secret at {path:?}");
+            rng.fill(&mut secret)
+                .map_err(|_| CredentialError::InvalidSecret)?;
+            private_file(path, false)?.write_all(&secret)?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(secret)
+}
+
+/// Creates a file only the current user can read. Without `truncate`, fails if it exists.
+fn private_file(path: &Path, truncate: bool) -> std::io::Result<std::fs::File> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+
+    if truncate {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+        options.mode(0o600);
+    }
+
+    options.open(path)
+}
@@ -0,0 +1,96 @@
+#![cfg_attr(feature = "fail-on-warnings", deny(warnings))]
+
+use std::{collections::BTreeMap, sync::Mutex};
+
+use thiserror::Error;
+
+mod file;
+
+pub use file::FileBackend;
+
+#[derive(Debug, Error)]
+pub enum CredentialError {
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error("Invalid install secret")]
+    InvalidSecret,
+    #[error("Failed to encrypt credentials")]
+    Encrypt,
+    #[error("Failed to decrypt credentials")]
+    Decrypt,
+    #[error("Unsupported credentials file version {0}")]
+    UnsupportedVersion(u8),
+}
+
+/// Where credentials are kept. Implementations must keep the values secret at rest.
+pub trait CredentialBackend: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<String>, CredentialError>;
+
+    fn set(&self, key: &str, value: &str) -> Result<(), CredentialError>;
+
+    /// Removing a key that isn't stored is not an error
+    fn delete(&self, key: &str) -> Result<(), CredentialError>;
+}
+
+/// Keeps credentials in memory only, e.g. for tests or when no data dir is available
+#[derive(Default)]
+pub struct MemoryBackend {
+    values: Mutex<BTreeMap<String, String>>,
+}
+
+impl CredentialBackend for MemoryBackend {
+    fn get(&self, key: &str) -> Result<Option<String>, CredentialError> {
+        Ok(self.values.lock().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), CredentialError> {
+        self.values
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), CredentialError> {
+        self.values.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+pub struct CredentialStore {
+    backend: Box<dyn CredentialBackend>,
+}
+
+impl CredentialStore {
+    pub fn new(backend: impl CredentialBackend + 'static) -> Self {
+        Self {
+            backend: Box::new(backend),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>, CredentialError> {
+        self.backend.get(key)
+    }
+
+    pub fn set(&self, key: &str, value: &str) -> Result<(), CredentialError> {
+        self.backend.set(key, value)
+    }
+
+    pub fn delete(&self, key: &str) -> Result<(), CredentialError> {
+        self.backend.delete(key)
+    }
+
+    /// Stores `value`, or deletes the key when it is `None`
+    pub fn set_optional(&self, key: &str, value: Option<&str>) -> Result<(), CredentialError> {
+        match value {
+            Some(value) => self.set(key, value),
+            None => self.delete(key),
+        }
+    }
+
+    pub fn contains(&self, key: &str) -> Result<bool, CredentialError> {
+        Ok(self.get(key)?.is_some())
+    }
+}
@@ -0,0 +1,177 @@
+use moosicbox_app_credentials::{
+    CredentialBackend as _, CredentialError, CredentialStore, FileBackend,
+};
+
+#[test]
+fn get_returns_none_when_nothing_is_stored() {
+    let dir = tempfile::tempdir().unwrap();
+    let backend = FileBackend::open(dir.path()).unwrap();
+
+    assert_eq!(backend.get("api_token").unwrap(), None);
+    assert!(!backend.path().exists());
+}
+
+#[test]
+fn set_then_get_round_trips() {
+    let dir = tempfile::tempdir().unwrap();
+    let backend = FileBackend::open(dir.path()).unwrap();
+
+    backend.set("api_token", "secret-token").unwrap();
+    backend.set("client_id", "client").unwrap();
+
+    assert_eq!(
+        backend.get("api_token").unwrap().as_deref(),
+        Some("secret-token")
+    );
+    assert_eq!(backend.get("client_id").unwrap().as_deref(), Some("client"));
+}
+
+#[test]
+fn values_survive_reopening() {
+    let dir = tempfile::tempdir().unwrap();
+
+    FileBackend::open(dir.path())
+        .unwrap()
+        .set("api_token", "secret-token")
+        .unwrap();
+
+    let backend = FileBackend::open(dir.path()).unwrap();
+    assert_eq!(
+        backend.get("api_token").unwrap().as_deref(),
+        Some("secret-token")
+    );
+}
+
+#[test]
+fn delete_removes_only_that_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let backend = FileBackend::open(dir.path()).unwrap();
+
+    backend.set("api_token", "secret-token").unwrap();
+    backend.set("client_id", "client").unwrap();
+    backend.delete("api_token").unwrap();
+    backend.delete("missing").unwrap();
+
+    assert_eq!(backend.get("api_token").unwrap(), None);
+    assert_eq!(backend.get("client_id").unwrap().as_deref(), Some("client"));
+}
+
+#[test]
+fn file_does_not_contain_plaintext() {
+    let dir = tempfile::tempdir().unwrap();
+    let backend = FileBackend::open(dir.path()).unwrap();
+
+    backend.set("api_token", "very-secret-token").unwrap();
+
+    let contents = std::fs::read(backend.path()).unwrap();
+    let haystack = String::from_utf8_lossy(&contents);
+    assert!(!haystack.contains("very-secret-token"));
+    assert!(!haystack.contains("api_token"));
+}
+
+#[test]
+fn each_write_uses_a_fresh_nonce() {
+    let dir = tempfile::tempdir().unwrap();
+    let backend = FileBackend::open(dir.path()).unwrap();
+
+    backend.set("api_token", "a").unwrap();
+    let first = std::fs::read(backend.path()).unwrap();
+    backend.set("api_token", "b").unwrap();
+    backend.set("api_token", "a").unwrap();
+    let second = std::fs::read(backend.path()).unwrap();
+
+    assert_ne!(first, second);
+}
+
+#[test]
+fn different_install_secret_cannot_decrypt() {
+    let dir = tempfile::tempdir().unwrap();
+    FileBackend::open(dir.path())
+        .unwrap()
+        .set("api_token", "secret-token")
+        .unwrap();
+
+    std::fs::remove_file(dir.path().join("credentials.key")).unwrap();
+
+    let backend = FileBackend::open(dir.path()).unwrap();
+    assert!(matches!(
+        backend.get("api_token"),
+        Err(CredentialError::Decrypt)
+    ));
+}
+
+#[test]
+fn tampered_file_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let backend = FileBackend::open(dir.path()).unwrap();
+    backend.set("api_token", "secret-token").unwrap();
+
+    let mut contents = std::fs::read(backend.path()).unwrap();
+    let last = contents.len() - 1;
+    contents[last] ^= 0x01;
+    std::fs::write(backend.path(), contents).unwrap();
+
+    assert!(matches!(
+        backend.get("api_token"),
+        Err(CredentialError::Decrypt)
+    ));
+}
+
+#[test]
+fn unknown_file_version_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let backend = FileBackend::open(dir.path()).unwrap();
+    backend.set("api_token", "secret-token").unwrap();
+
+    let mut contents = std::fs::read(backend.path()).unwrap();
+    contents[0] = 99;
+    std::fs::write(backend.path(), contents).unwrap();
+
+    assert!(matches!(
+        backend.get("api_token"),
+        Err(CredentialError::UnsupportedVersion(99))
+    ));
+}
+
+#[test]
+fn truncated_install_secret_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("credentials.key"), [1, 2, 3]).unwrap();
+
+    assert!(matches!(
+        FileBackend::open(dir.path()),
+        Err(CredentialError::InvalidSecret)
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn files_are_only_readable_by_owner() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let dir = tempfile::tempdir().unwrap();
+    let backend = FileBackend::open(dir.path()).unwrap();
+    backend.set("api_token", "secret-token").unwrap();
+
+    for path in [
+        dir.path().join("credentials.key"),
+        backend.path().to_path_buf(),
+    ] {
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600, "{path:?}");
+    }
+}
+
+#[test]
+fn store_set_optional_deletes_on_none() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = CredentialStore::new(FileBackend::open(dir.path()).unwrap());
+
+    store
+        .set_optional("api_token", Some("secret-token"))
+        .unwrap();
+    assert!(store.contains("api_token").unwrap());
+
+    store.set_optional("api_token", None).unwrap();
+    assert!(!store.contains("api_token").unwrap());
+}
@@ -0,0 +1,57 @@
+/// The directives of a `Cache-Control` response header that affect the cache
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheControl {
+    pub no_store: bool,
+    /// The response may be stored, but must be revalidated before it is used
+    pub no_cache: bool,
+    pub max_age: Option<u64>,
+}
+
+impl CacheControl {
+    /// Parses a `Cache-Control` header value. Unknown and malformed directives are ignored.
+    pub fn parse(value: &str) -> Self {
+        let mut cache_control = Self::default();
+
+        for directive in value.split(',') {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name, Some(argument.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+
+            match name.trim().to_ascii_lowercase().as_str() {
+                "no-store" => cache_control.no_store = true,
+                "no-cache" => cache_control.no_cache = true,
+                "max-age" => {
+                    if let Some(max_age) = argument.and_then(|x| x.parse().ok()) {
+                        cache_control.max_age = Some(max_age);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        cache_control
+    }
+}
+
+/// The response headers used to decide whether and how long a response is cached
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CacheHeaders {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub cache_control: CacheControl,
+}
+
+impl CacheHeaders {
+    pub fn new(
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+        cache_control: Option<&str>,
+    ) -> Self {
+        Self {
+            etag: etag.map(ToString::to_string),
+            last_modified: last_modified.map(ToString::to_string),
+            cache_control: cache_control.map(CacheControl::parse).unwrap_or_default(),
+        }
+    }
+}
@@ -0,0 +1,413 @@
+#![cfg_attr(feature = "fail-on-warnings", deny(warnings))]
+
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+mod cache_control;
+
+pub use cache_control::{CacheControl, CacheHeaders};
+
+const INDEX_FILE_NAME: &str = "index.json";
+const INDEX_VERSION: u32 = 1;
+const BODY_EXTENSION: &str = "body";
+
+#[derive(Debug, Error)]
+pub enum HttpCacheError {
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+/// What is known about a cached response, without its body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheEntry {
+    pub partition: String,
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Unix time in seconds when the response was stored or last revalidated
+    pub stored_at: u64,
+    pub max_age: Option<u64>,
+    pub no_cache: bool,
+    pub size: u64,
+    last_used: u64,
+}
+
+impl CacheEntry {
+    /// Whether the response can be used at `now` without revalidating it. Responses without a
+    /// `max-age` are always revalidated.
+    pub fn is_fresh(&self, now: SystemTime) -> bool {
+        if self.no_cache {
+            return false;
+        }
+
+        self.max_age
+            .is_some_and(|max_age| unix_secs(now).saturating_sub(self.stored_at) < max_age)
+    }
+
+    pub fn stored_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.stored_at)
+    }
+
+    fn apply(&mut self, headers: &CacheHeaders, now: SystemTime) {
+        if headers.etag.is_some() {
+            self.etag.clone_from(&headers.etag);
+        }
+        if headers.last_modified.is_some() {
+            self.last_modified.clone_from(&headers.last_modified);
+        }
+        self.max_age = headers.cache_control.max_age;
+        self.no_cache = headers.cache_control.no_cache;
+        self.stored_at = unix_secs(now);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub entry: CacheEntry,
+    pub body: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Index {
+    version: u32,
+    /// Incremented on every access to order entries by recent use
+    clock: u64,
+    entries: BTreeMap<String, CacheEntry>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Self {
+            version: INDEX_VERSION,
+            clock: 0,
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl Index {
+    fn size(&self) -> u64 {
+        self.entries.values().map(|x| x.size).sum()
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+struct State {
+    index: Index,
+    max_size: u64,
+}
+
+/// Stores response bodies on disk, evicting the least recently used ones once their total size
+/// goes over the size cap.
+///
+/// Entries are keyed by a partition, e.g. the server and profile they were fetched for, and the
+/// URL. The order of use is only written to disk when the cache is modified, so it may be
+/// slightly behind after a restart.
+pub struct HttpCache {
+    dir: PathBuf,
+    state: Mutex<State>,
+}
+
+impl HttpCache {
+    /// Opens the cache in `dir`, creating it if needed. An unreadable index is discarded.
+    pub fn open(dir: impl AsRef<Path>, max_size: u64) -> Result<Self, HttpCacheError> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let mut index = read_index(&dir.join(INDEX_FILE_NAME)).unwrap_or_else(|e| {
+            log::warn!("Discarding unreadable HTTP cache index: {e:?}");
+            Index::default()
+        });
+
+        index.entries.retain(|key, _| body_path(dir, key).is_file());
+
+        for file in std::fs::read_dir(dir)? {
+            let path = file?.path();
+            if path.extension().is_some_and(|x| x == BODY_EXTENSION)
+                && path
+                    .file_stem()
+                    .and_then(|x| x.to_str())
+                    .is_none_or(|key| !index.entries.contains_key(key))
+            {
+                log::debug!("HttpCache: removing orphaned body {path:?}");
+                std::fs::remove_file(&path)?;
+            }
+        }
+
+        let cache = Self {
+            dir: dir.to_path_buf(),
+            state: Mutex::new(State { index, max_size }),
+        };
+
+        {
+            let mut state = cache.state.lock().unwrap();
+            cache.evict(&mut state)?;
+            cache.save_index(&state.index)?;
+        }
+
+        Ok(cache)
+    }
+
+    pub fn get(
+        &self,
+        partition: &str,
+        url: &str,
+    ) -> Result<Option<CachedResponse>, HttpCacheError> {
+        let key = key(partition, url);
+        let mut state = self.state.lock().unwrap();
+
+        if !state.index.entries.contains_key(&key) {
+            return Ok(None);
+        }
+
+        let body = match std::fs::read(body_path(&self.dir, &key)) {
+            Ok(body) => body,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                state.index.entries.remove(&key);
+                self.save_index(&state.index)?;
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let last_used = state.index.tick();
+        let entry = state.index.entries.get_mut(&key).unwrap();
+        entry.last_used = last_used;
+
+        Ok(Some(CachedResponse {
+            entry: entry.clone(),
+            body,
+        }))
+    }
+
+    /// Stores a successful response. Returns `false` if it can't be cached because of
+    /// `no-store` or its size, in which case any previously cached response is removed.
+    pub fn put(
+        &self,
+        partition: &str,
+        url: &str,
+        headers: &CacheHeaders,
+        body: &[u8],
+    ) -> Result<bool, HttpCacheError> {
+        let key = key(partition, url);
+        let mut state = self.state.lock().unwrap();
+
+        if headers.cache_control.no_store || body.len() as u64 > state.max_size {
+            self.remove_entry(&mut state, &key)?;
+            return Ok(false);
+        }
+
+        let path = body_path(&self.dir, &key);
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, body)?;
+        std::fs::rename(&tmp_path, &path)?;
+
+        let mut entry = CacheEntry {
+            partition: partition.to_string(),
+            url: url.to_string(),
+            etag: None,
+            last_modified: None,
+            stored_at: 0,
+            max_age: None,
+            no_cache: false,
+            size: body.len() as u64,
+            last_used: state.index.tick(),
+        };
+        entry.apply(headers, SystemTime::now());
+        state.index.entries.insert(key, entry);
+
+        self.evict(&mut state)?;
+        self.save_index(&state.index)?;
+
+        Ok(true)
+    }
+
+    /// Marks the cached response as fresh again after the server answered `304 Not Modified`
+    /// with `headers`, and returns it
+    pub fn revalidated(
+        &self,
+        partition: &str,
+        url: &str,
+        headers: &CacheHeaders,
+    ) -> Result<Option<CachedResponse>, HttpCacheError> {
+        let key = key(partition, url);
+
+        {
+            let mut state = self.state.lock().unwrap();
+
+            if headers.cache_control.no_store {
+                self.remove_entry(&mut state, &key)?;
+                return Ok(None);
+            }
+
+            let Some(entry) = state.index.entries.get_mut(&key) else {
+                return Ok(None);
+            };
+            entry.apply(headers, SystemTime::now());
+            self.save_index(&state.index)?;
+        }
+
+        self.get(partition, url)
+    }
+
+    /// Path of the cached body, for code that can only read files, e.g. a native media session.
+    /// The file is removed if the entry is evicted.
+    pub fn path(&self, partition: &str, url: &str) -> Option<PathBuf> {
+        let key = key(partition, url);
+        let state = self.state.lock().unwrap();
+
+        state
+            .index
+            .entries
+            .contains_key(&key)
+            .then(|| body_path(&self.dir, &key))
+    }
+
+    pub fn remove(&self, partition: &str, url: &str) -> Result<(), HttpCacheError> {
+        let mut state = self.state.lock().unwrap();
+        self.remove_entry(&mut state, &key(partition, url))
+    }
+
+    /// Removes every response in `partition`, or every response if `None`
+    pub fn clear(&self, partition: Option<&str>) -> Result<(), HttpCacheError> {
+        let mut state = self.state.lock().unwrap();
+
+        let keys = state
+            .index
+            .entries
+            .iter()
+            .filter(|(_, entry)| partition.is_none_or(|x| x == entry.partition))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        for key in &keys {
+            remove_body(&self.dir, key)?;
+            state.index.entries.remove(key);
+        }
+
+        self.save_index(&state.index)
+    }
+
+    /// Total size in bytes of the cached bodies
+    pub fn size(&self) -> u64 {
+        self.state.lock().unwrap().index.size()
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.state.lock().unwrap().max_size
+    }
+
+    /// Changes the size cap, evicting responses if the cache is now over it
+    pub fn set_max_size(&self, max_size: u64) -> Result<(), HttpCacheError> {
+        let mut state = self.state.lock().unwrap();
+        state.max_size = max_size;
+
+        if self.evict(&mut state)? {
+            self.save_index(&state.index)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the least recently used entries until the cache fits in its size cap. Returns
+    /// whether anything was removed.
+    fn evict(&self, state: &mut State) -> Result<bool, HttpCacheError> {
+        let mut size = state.index.size();
+
+        if size <= state.max_size {
+            return Ok(false);
+        }
+
+        let mut entries = state
+            .index
+            .entries
+            .iter()
+            .map(|(key, entry)| (entry.last_used, entry.size, key.clone()))
+            .collect::<Vec<_>>();
+        entries.sort_unstable();
+
+        for (_, entry_size, key) in entries {
+            if size <= state.max_size {
+                break;
+            }
+            log::debug!("HttpCache: evicting {key} size={entry_size}");
+            remove_body(&self.dir, &key)?;
+            state.index.entries.remove(&key);
+            size -= entry_size;
+        }
+
+        Ok(true)
+    }
+
+    fn remove_entry(&self, state: &mut State, key: &str) -> Result<(), HttpCacheError> {
+        if state.index.entries.remove(key).is_some() {
+            remove_body(&self.dir, key)?;
+            self.save_index(&state.index)?;
+        }
+
+        Ok(())
+    }
+
+    fn save_index(&self, index: &Index) -> Result<(), HttpCacheError> {
+        let path = self.dir.join(INDEX_FILE_NAME);
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(index)?)?;
+        std::fs::rename(&tmp_path, &path)?;
+
+        Ok(())
+    }
+}
+
+fn read_index(path: &Path) -> Result<Index, HttpCacheError> {
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Index::default()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let index: Index = serde_json::from_slice(&contents)?;
+
+    if index.version != INDEX_VERSION {
+        log::debug!("HttpCache: unsupported index version {}", index.version);
+        return Ok(Index::default());
+    }
+
+    Ok(index)
+}
+
+fn key(partition: &str, url: &str) -> String {
+    let hash = digest(&SHA256, format!("{partition}\n{url}").as_bytes());
+
+    hash.as_ref().iter().map(|x| format!("{x:02x}")).collect()
+}
+
+fn body_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(key).with_extension(BODY_EXTENSION)
+}
+
+fn remove_body(dir: &Path, key: &str) -> std::io::Result<()> {
+    match std::fs::remove_file(body_path(dir, key)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |x| x.as_secs())
+}
@@ -0,0 +1,300 @@
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use moosicbox_app_http_cache::{CacheHeaders, HttpCache};
+
+const PARTITION: &str = "http://server|profile";
+
+fn max_age(seconds: u64) -> CacheHeaders {
+    CacheHeaders::new(None, None, Some(&format!("max-age={seconds}")))
+}
+
+fn body_files(dir: &Path) -> usize {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .filter(|x| {
+            x.as_ref()
+                .unwrap()
+                .path()
+                .extension()
+                .is_some_and(|x| x == "body")
+        })
+        .count()
+}
+
+#[test]
+fn put_then_get_round_trips() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = HttpCache::open(dir.path(), 1024).unwrap();
+    let headers = CacheHeaders::new(Some("\"v1\""), Some("yesterday"), Some("max-age=60"));
+
+    assert!(cache.put(PARTITION, "albums", &headers, b"[1]").unwrap());
+
+    let cached = cache.get(PARTITION, "albums").unwrap().unwrap();
+    assert_eq!(cached.body, b"[1]");
+    assert_eq!(cached.entry.url, "albums");
+    assert_eq!(cached.entry.etag.as_deref(), Some("\"v1\""));
+    assert_eq!(cached.entry.last_modified.as_deref(), Some("yesterday"));
+    assert_eq!(cached.entry.max_age, Some(60));
+    assert_eq!(cache.size(), 3);
+    assert!(cache.get("other", "albums").unwrap().is_none());
+}
+
+#[test]
+fn entries_are_fresh_until_max_age() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = HttpCache::open(dir.path(), 1024).unwrap();
+    cache.put(PARTITION, "albums", &max_age(60), b"[]").unwrap();
+
+    let entry = cache.get(PARTITION, "albums").unwrap().unwrap().entry;
+    let stored_at = entry.stored_at();
+
+    assert!(entry.is_fresh(stored_at));
+    assert!(entry.is_fresh(stored_at + Duration::from_secs(59)));
+    assert!(!entry.is_fresh(stored_at + Duration::from_secs(60)));
+}
+
+#[test]
+fn entries_without_max_age_or_with_no_cache_are_never_fresh() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = HttpCache::open(dir.path(), 1024).unwrap();
+    let no_cache = CacheHeaders::new(None, None, Some("no-cache, max-age=60"));
+    cache
+        .put(PARTITION, "a", &CacheHeaders::default(), b"[]")
+        .unwrap();
+    cache.put(PARTITION, "b", &no_cache, b"[]").unwrap();
+
+    let now = SystemTime::now();
+    assert!(!cache
+        .get(PARTITION, "a")
+        .unwrap()
+        .unwrap()
+        .entry
+        .is_fresh(now));
+    assert!(!cache
+        .get(PARTITION, "b")
+        .unwrap()
+        .unwrap()
+        .entry
+        .is_fresh(now));
+}
+
+#[test]
+fn no_store_responses_are_not_cached_and_remove_the_old_copy() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = HttpCache::open(dir.path(), 1024).unwrap();
+    let no_store = CacheHeaders::new(None, None, Some("no-store"));
+    cache
+        .put(PARTITION, "albums", &max_age(60), b"[1]")
+        .unwrap();
+
+    assert!(!cache.put(PARTITION, "albums", &no_store, b"[2]").unwrap());
+
+    assert!(cache.get(PARTITION, "albums").unwrap().is_none());
+    assert_eq!(cache.size(), 0);
+    assert_eq!(body_files(dir.path()), 0);
+}
+
+#[test]
+fn responses_larger_than_the_cache_are_not_cached() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = HttpCache::open(dir.path(), 4).unwrap();
+
+    assert!(!cache
+        .put(PARTITION, "albums", &max_age(60), b"[1, 2]")
+        .unwrap());
+    assert!(cache.get(PARTITION, "albums").unwrap().is_none());
+}
+
+#[test]
+fn least_recently_used_entries_are_evicted_first() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = HttpCache::open(dir.path(), 30).unwrap();
+    let headers = max_age(60);
+    cache.put(PARTITION, "a", &headers, &[0; 10]).unwrap();
+    cache.put(PARTITION, "b", &headers, &[0; 10]).unwrap();
+    cache.put(PARTITION, "c", &headers, &[0; 10]).unwrap();
+
+    cache.get(PARTITION, "a").unwrap().unwrap();
+    cache.put(PARTITION, "d", &headers, &[0; 10]).unwrap();
+
+    assert!(cache.get(PARTITION, "b").unwrap().is_none());
+    for url in ["a", "c", "d"] {
+        assert!(cache.get(PARTITION, url).unwrap().is_some(), "{url}");
+    }
+    assert_eq!(cache.size(), 30);
+    assert_eq!(body_files(dir.path()), 3);
+}
+
+#[test]
+fn lowering_the_max_size_evicts() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = HttpCache::open(dir.path(), 30).unwrap();
+    cache.put(PARTITION, "a", &max_age(60), &[0; 10]).unwrap();
+    cache.put(PARTITION, "b", &max_age(60), &[0; 10]).unwrap();
+
+    cache.set_max_size(15).unwrap();
+
+    assert_eq!(cache.max_size(), 15);
+    assert!(cache.get(PARTITION, "a").unwrap().is_none());
+    assert!(cache.get(PARTITION, "b").unwrap().is_some());
+}
+
+#[test]
+fn revalidated_refreshes_the_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = HttpCache::open(dir.path(), 1024).unwrap();
+    let headers = CacheHeaders::new(Some("\"v1\""), Some("yesterday"), None);
+    cache.put(PARTITION, "albums", &headers, b"[1]").unwrap();
+
+    let revalidated = cache
+        .revalidated(PARTITION, "albums", &max_age(60))
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(revalidated.body, b"[1]");
+    assert_eq!(revalidated.entry.max_age, Some(60));
+    assert_eq!(revalidated.entry.etag.as_deref(), Some("\"v1\""));
+    assert_eq!(
+        revalidated.entry.last_modified.as_deref(),
+        Some("yesterday")
+    );
+    assert!(revalidated.entry.is_fresh(SystemTime::now()));
+}
+
+#[test]
+fn revalidated_with_no_store_removes_the_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = HttpCache::open(dir.path(), 1024).unwrap();
+    let no_store = CacheHeaders::new(None, None, Some("no-store"));
+    cache
+        .put(PARTITION, "albums", &max_age(60), b"[1]")
+        .unwrap();
+
+    assert!(cache
+        .revalidated(PARTITION, "albums", &no_store)
+        .unwrap()
+        .is_none());
+    assert!(cache.get(PARTITION, "albums").unwrap().is_none());
+    assert!(cache
+        .revalidated(PARTITION, "missing", &max_age(60))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn clear_only_removes_the_given_partition() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = HttpCache::open(dir.path(), 1024).unwrap();
+    cache
+        .put(PARTITION, "albums", &max_age(60), b"[1]")
+        .unwrap();
+    cache.put("other", "albums", &max_age(60), b"[2]").unwrap();
+
+    cache.clear(Some(PARTITION)).unwrap();
+
+    assert!(cache.get(PARTITION, "albums").unwrap().is_none());
+    assert_eq!(cache.get("other", "albums").unwrap().unwrap().body, b"[2]");
+    assert_eq!(body_files(dir.path()), 1);
+
+    cache.clear(None).unwrap();
+
+    assert!(cache.get("other", "albums").unwrap().is_none());
+    assert_eq!(cache.size(), 0);
+    assert_eq!(body_files(dir.path()), 0);
+}
+
+#[test]
+fn entries_survive_reopening() {
+    let dir = tempfile::tempdir().unwrap();
+    HttpCache::open(dir.path(), 1024)
+        .unwrap()
+        .put(PARTITION, "albums", &max_age(60), b"[1]")
+        .unwrap();
+
+    let cache = HttpCache::open(dir.path(), 1024).unwrap();
+
+    assert_eq!(
+        cache.get(PARTITION, "albums").unwrap().unwrap().body,
+        b"[1]"
+    );
+    assert_eq!(cache.size(), 3);
+}
+
+#[test]
+fn reopening_with_a_smaller_max_size_evicts() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let cache = HttpCache::open(dir.path(), 1024).unwrap();
+        cache.put(PARTITION, "a", &max_age(60), &[0; 10]).unwrap();
+        cache.put(PARTITION, "b", &max_age(60), &[0; 10]).unwrap();
+    }
+
+    let cache = HttpCache::open(dir.path(), 10).unwrap();
+
+    assert!(cache.get(PARTITION, "a").unwrap().is_none());
+    assert!(cache.get(PARTITION, "b").unwrap().is_some());
+}
+
+#[test]
+fn orphaned_bodies_and_entries_are_removed_on_open() {
+    let dir = tempfile::tempdir().unwrap();
+    HttpCache::open(dir.path(), 1024)
+        .unwrap()
+        .put(PARTITION, "albums", &max_age(60), b"[1]")
+        .unwrap();
+
+    for file in std::fs::read_dir(dir.path()).unwrap() {
+        let path = file.unwrap().path();
+        if path.extension().is_some_and(|x| x == "body") {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+    std::fs::write(dir.path().join("orphan.body"), b"[2]").unwrap();
+
+    let cache = HttpCache::open(dir.path(), 1024).unwrap();
+
+    assert!(cache.get(PARTITION, "albums").unwrap().is_none());
+    assert_eq!(cache.size(), 0);
+    assert!(!dir.path().join("orphan.body").exists());
+}
+
+#[test]
+fn corrupt_index_is_discarded_with_its_bodies() {
+    let dir = tempfile::tempdir().unwrap();
+    HttpCache::open(dir.path(), 1024)
+        .unwrap()
+        .put(PARTITION, "albums", &max_age(60), b"[1]")
+        .unwrap();
+    std::fs::write(dir.path().join("index.json"), b"{not json").unwrap();
+
+    let cache = HttpCache::open(dir.path(), 1024).unwrap();
+
+    assert!(cache.get(PARTITION, "albums").unwrap().is_none());
+    assert_eq!(body_files(dir.path()), 0);
+
+    cache
+        .put(PARTITION, "albums", &max_age(60), b"[2]")
+        .unwrap();
+    let cache = HttpCache::open(dir.path(), 1024).unwrap();
+    assert_eq!(
+        cache.get(PARTITION, "albums").unwrap().unwrap().body,
+        b"[2]"
+    );
+}
+
+#[test]
+fn path_points_at_the_cached_body() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = HttpCache::open(dir.path(), 1024).unwrap();
+
+    assert_eq!(cache.path(PARTITION, "cover"), None);
+
+    cache.put(PARTITION, "cover", &max_age(60), b"image").unwrap();
+
+    let path = cache.path(PARTITION, "cover").unwrap();
+    assert!(path.starts_with(dir.path()));
+    assert_eq!(std::fs::read(path).unwrap(), b"image");
+}
@@ -0,0 +1,44 @@
+use moosicbox_app_http_cache::{CacheControl, CacheHeaders};
+
+#[test]
+fn parses_directives() {
+    assert_eq!(
+        CacheControl::parse("no-cache, max-age=60"),
+        CacheControl {
+            no_store: false,
+            no_cache: true,
+            max_age: Some(60),
+        }
+    );
+    assert!(CacheControl::parse("no-store").no_store);
+}
+
+#[test]
+fn directives_are_case_insensitive_and_trimmed() {
+    assert_eq!(
+        CacheControl::parse(" Max-Age = \"30\" ,NO-STORE"),
+        CacheControl {
+            no_store: true,
+            no_cache: false,
+            max_age: Some(30),
+        }
+    );
+}
+
+#[test]
+fn unknown_and_malformed_directives_are_ignored() {
+    assert_eq!(
+        CacheControl::parse("public, max-age=abc, s-maxage=10, ,"),
+        CacheControl::default()
+    );
+    assert_eq!(CacheControl::parse(""), CacheControl::default());
+}
+
+#[test]
+fn headers_without_cache_control_use_the_defaults() {
+    let headers = CacheHeaders::new(Some("\"v1\""), None, None);
+
+    assert_eq!(headers.etag.as_deref(), Some("\"v1\""));
+    assert_eq!(headers.last_modified, None);
+    assert_eq!(headers.cache_control, CacheControl::default());
+}
@@ -0,0 +1,233 @@
+#![cfg_attr(feature = "fail-on-warnings", deny(warnings))]
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncReadExt as _, AsyncWriteExt as _, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+/// A request as seen by the server
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    /// Decoded query parameters
+    pub query: HashMap<String, String>,
+    /// Header values keyed by lowercase header name
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query.get(name).map(String::as_str)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).unwrap()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl MockResponse {
+    pub fn json(value: &serde_json::Value) -> Self {
+        Self::status(200, &value.to_string())
+    }
+
+    pub fn status(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: body.to_string(),
+        }
+    }
+
+    /// Adds a response header, e.g. `Cache-Control` or `ETag`
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+type Handler = Box<dyn Fn(&RecordedRequest) -> MockResponse + Send + Sync>;
+
+#[derive(Default)]
+struct Shared {
+    routes: Mutex<HashMap<(String, String), Handler>>,
+    requests: Mutex<Vec<RecordedRequest>>,
+}
+
+/// HTTP/1.1 server on a random local port that answers routed requests and records every
+/// request it receives. Unrouted requests get a 404. The server stops when dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    listener: JoinHandle<()>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shared = Arc::new(Shared::default());
+
+        let listener = tokio::spawn({
+            let shared = shared.clone();
+
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(handle_connection(stream, shared.clone()));
+                }
+            }
+        });
+
+        Self {
+            addr,
+            shared,
+            listener,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Answers `method` requests to `path` with `handler`
+    pub fn route(
+        &self,
+        method: &str,
+        path: &str,
+        handler: impl Fn(&RecordedRequest) -> MockResponse + Send + Sync + 'static,
+    ) {
+        self.shared
+            .routes
+            .lock()
+            .unwrap()
+            .insert((method.to_string(), path.to_string()), Box::new(handler));
+    }
+
+    /// Every request received so far
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.shared.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+async fn handle_connection(stream: TcpStream, shared: Arc<Shared>) {
+    let mut reader = BufReader::new(stream);
+
+    while let Some(request) = read_request(&mut reader).await {
+        let response = {
+            let routes = shared.routes.lock().unwrap();
+            routes
+                .get(&(request.method.clone(), request.path.clone()))
+                .map_or_else(|| MockResponse::status(404, ""), |x| x(&request))
+        };
+
+        shared.requests.lock().unwrap().push(request);
+
+        let headers = response
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}\r\n"))
+            .collect::<String>();
+        let head = format!(
+            "HTTP/1.1 {} Mock\r\ncontent-type: application/json\r\ncontent-length: {}\r\n{headers}\r\n",
+            response.status,
+            response.body.len()
+        );
+
+        let stream = reader.get_mut();
+        if stream.write_all(head.as_bytes()).await.is_err()
+            || stream.write_all(response.body.as_bytes()).await.is_err()
+        {
+            return;
+        }
+    }
+}
+
+async fn read_request(reader: &mut BufReader<TcpStream>) -> Option<RecordedRequest> {
+    let mut line = String::new();
+    reader.read_line(&mut line).await.ok()?;
+
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).await.ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':')?;
+        headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+    }
+
+    let length = headers
+        .get("content-length")
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await.ok()?;
+
+    Some(RecordedRequest {
+        method,
+        path: path.to_string(),
+        query: query
+            .split('&')
+            .filter(|x| !x.is_empty())
+            .map(|x| {
+                let (name, value) = x.split_once('=').unwrap_or((x, ""));
+                (decode(name), decode(value))
+            })
+            .collect(),
+        headers,
+        body,
+    })
+}
+
+fn decode(value: &str) -> String {
+    let mut bytes = vec![];
+    let mut chars = value.bytes();
+
+    while let Some(byte) = chars.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = [chars.next().unwrap_or(b'0'), chars.next().unwrap_or(b'0')];
+                let hex = std::str::from_utf8(&hex).unwrap_or("00");
+                bytes.push(u8::from_str_radix(hex, 16).unwrap_or(0));
+            }
+            _ => bytes.push(byte),
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,179 @@
+use std::{
+    cell::Cell,
+    fmt::Debug,
+    sync::{Arc, OnceLock},
+};
+
+use tracing::{
+    dispatcher::WeakDispatch,
+    field::{display, DisplayValue, Field, Value, Visit},
+    Dispatch, Event, Subscriber,
+};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::redact;
+
+/// The most fields an event can have, which is what the `tracing` macros accept
+const MAX_FIELDS: usize = 32;
+
+thread_local! {
+    /// Set while the redacted copy of an event is dispatched, so it passes through unchanged
+    static REDISPATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Drops every event that contains a secret and dispatches a redacted copy in its place, so the
+/// other layers, e.g. FreeLog's file and remote writers, only ever see redacted text. This
+/// covers `log` records bridged by `LogTracer` too, including those of dependencies and call
+/// sites that didn't wrap their values in `Redacted`.
+///
+/// Clones share the subscriber set with `attach`.
+#[derive(Debug, Default, Clone)]
+pub struct RedactingLayer {
+    dispatch: Arc<OnceLock<WeakDispatch>>,
+}
+
+impl RedactingLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Dispatches the redacted copies to `dispatch`, the subscriber this layer was added to.
+    /// Until then they go to the default dispatcher, which drops them while a scoped one is
+    /// handling an event.
+    pub fn attach(&self, dispatch: &Dispatch) {
+        let _ = self.dispatch.set(dispatch.downgrade());
+    }
+}
+
+impl<S: Subscriber> Layer<S> for RedactingLayer {
+    fn event_enabled(&self, event: &Event<'_>, _ctx: Context<'_, S>) -> bool {
+        if REDISPATCHING.get() {
+            return true;
+        }
+
+        let mut fields = RedactedFields::default();
+        event.record(&mut fields);
+
+        if !fields.redacted {
+            return true;
+        }
+
+        let _guard = RedispatchGuard::enter();
+        match self.dispatch.get().and_then(WeakDispatch::upgrade) {
+            Some(dispatch) => fields.dispatch(&dispatch, event),
+            None => tracing::dispatcher::get_default(|dispatch| fields.dispatch(dispatch, event)),
+        }
+
+        false
+    }
+}
+
+struct RedispatchGuard;
+
+impl RedispatchGuard {
+    fn enter() -> Self {
+        REDISPATCHING.set(true);
+        Self
+    }
+}
+
+impl Drop for RedispatchGuard {
+    fn drop(&mut self) {
+        REDISPATCHING.set(false);
+    }
+}
+
+enum FieldValue {
+    Str(String),
+    Debug(DisplayValue<String>),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Bool(bool),
+}
+
+impl FieldValue {
+    fn as_value(&self) -> &dyn Value {
+        match self {
+            Self::Str(x) => x,
+            Self::Debug(x) => x,
+            Self::I64(x) => x,
+            Self::U64(x) => x,
+            Self::F64(x) => x,
+            Self::Bool(x) => x,
+        }
+    }
+}
+
+/// The fields of an event with their text redacted
+#[derive(Default)]
+struct RedactedFields {
+    values: Vec<(Field, FieldValue)>,
+    /// Whether any field contained a secret
+    redacted: bool,
+}
+
+impl RedactedFields {
+    fn redact(&mut self, value: &str) -> String {
+        let redacted = redact(value);
+        if redacted != value {
+            self.redacted = true;
+        }
+        redacted.into_owned()
+    }
+
+    /// Dispatches an event with the same metadata and parent as `event` but these values
+    fn dispatch(&self, dispatch: &Dispatch, event: &Event<'_>) {
+        let Some((first, _)) = self.values.first() else {
+            return;
+        };
+
+        let values: [(&Field, Option<&dyn Value>); MAX_FIELDS] = std::array::from_fn(|i| {
+            self.values.get(i).map_or((first, None), |(field, value)| {
+                (field, Some(value.as_value()))
+            })
+        });
+
+        let metadata = event.metadata();
+        let values = metadata.fields().value_set(&values);
+
+        if event.is_contextual() {
+            dispatch.event(&Event::new(metadata, &values));
+        } else {
+            dispatch.event(&Event::new_child_of(
+                event.parent().cloned(),
+                metadata,
+                &values,
+            ));
+        }
+    }
+}
+
+impl Visit for RedactedFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        let value = self.redact(value);
+        self.values.push((field.clone(), FieldValue::Str(value)));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        let value = self.redact(&format!("{value:?}"));
+        self.values
+            .push((field.clone(), FieldValue::Debug(display(value))));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.values.push((field.clone(), FieldValue::I64(value)));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.values.push((field.clone(), FieldValue::U64(value)));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.values.push((field.clone(), FieldValue::F64(value)));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.values.push((field.clone(), FieldValue::Bool(value)));
+    }
+}
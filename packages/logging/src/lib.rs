@@ -0,0 +1,49 @@
+#![cfg_attr(feature = "fail-on-warnings", deny(warnings))]
+
+use moosicbox_logging::free_log_client::FreeLogLayer;
+
+mod layer;
+mod redact;
+mod rotate;
+
+pub use layer::RedactingLayer;
+pub use redact::{redact, register_secret, Redacted, MASK, MIN_SECRET_LEN};
+pub use rotate::{log_files, rotate, LogRotation};
+
+/// A log layer that attaches properties to every remote log entry
+pub trait LogProperties {
+    fn set_property(&self, id: &str, value: String);
+
+    fn remove_property(&self, id: &str);
+}
+
+impl LogProperties for FreeLogLayer {
+    fn set_property(&self, id: &str, value: String) {
+        Self::set_property(self, id, value.into());
+    }
+
+    fn remove_property(&self, id: &str) {
+        Self::remove_property(self, id);
+    }
+}
+
+/// Redacts property values before they reach the wrapped layer, so tokens in e.g. an API URL
+/// are never sent with remote logs
+#[derive(Debug)]
+pub struct RedactingLogLayer<L> {
+    inner: L,
+}
+
+impl<L: LogProperties> RedactingLogLayer<L> {
+    pub const fn new(inner: L) -> Self {
+        Self { inner }
+    }
+
+    pub fn set_property(&self, id: &str, value: String) {
+        self.inner.set_property(id, redact(&value).into_owned());
+    }
+
+    pub fn remove_property(&self, id: &str) {
+        self.inner.remove_property(id);
+    }
+}
@@ -0,0 +1,81 @@
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Size and count caps of a log file and its rotated copies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogRotation {
+    /// The log file is rotated once it grows past this many bytes
+    pub max_size: u64,
+    /// How many rotated copies (`{name}.1` being the newest) are kept
+    pub max_files: usize,
+}
+
+impl Default for LogRotation {
+    fn default() -> Self {
+        Self {
+            max_size: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+/// Rotates `path` if it is larger than `rotation.max_size`, returning whether it did.
+///
+/// The file is copied to `{path}.1` and truncated in place rather than renamed, since the log
+/// writer keeps it open in append mode. Older copies are shifted up and the oldest is removed.
+pub fn rotate(path: &Path, rotation: &LogRotation) -> io::Result<bool> {
+    let size = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    if size <= rotation.max_size {
+        return Ok(false);
+    }
+
+    if rotation.max_files > 0 {
+        remove_if_exists(&rotated_path(path, rotation.max_files))?;
+
+        for index in (1..rotation.max_files).rev() {
+            let from = rotated_path(path, index);
+            if from.exists() {
+                fs::rename(&from, rotated_path(path, index + 1))?;
+            }
+        }
+
+        fs::copy(path, rotated_path(path, 1))?;
+    }
+
+    OpenOptions::new().write(true).open(path)?.set_len(0)?;
+
+    Ok(true)
+}
+
+/// `path` and its rotated copies that exist, newest first
+pub fn log_files(path: &Path) -> Vec<PathBuf> {
+    std::iter::once(path.to_path_buf())
+        .chain(
+            (1..)
+                .map(|index| rotated_path(path, index))
+                .take_while(|x| x.exists()),
+        )
+        .filter(|x| x.exists())
+        .collect()
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{index}"));
+    path.with_file_name(name)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex, Once},
+};
+
+use moosicbox_app_logging::{register_secret, RedactingLayer};
+use tracing::Dispatch;
+use tracing_subscriber::layer::SubscriberExt as _;
+
+/// Writes events to a file, like the FreeLog file writer
+#[derive(Clone)]
+struct FileWriter {
+    file: Arc<Mutex<File>>,
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.lock().unwrap().flush()
+    }
+}
+
+/// Runs `f` with a subscriber that, like FreeLog's, bridges `log` records and writes every event
+/// to `path`, with the `RedactingLayer` installed
+fn with_log_file(path: &Path, f: impl FnOnce()) -> String {
+    static LOG_TRACER: Once = Once::new();
+    LOG_TRACER.call_once(|| tracing_log::LogTracer::init().unwrap());
+
+    let writer = FileWriter {
+        file: Arc::new(Mutex::new(File::create(path).unwrap())),
+    };
+    let redacting = RedactingLayer::new();
+    let subscriber = tracing_subscriber::registry().with(redacting.clone()).with(
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .without_time()
+            .with_writer(move || writer.clone()),
+    );
+
+    let dispatch = Dispatch::new(subscriber);
+    redacting.attach(&dispatch);
+    tracing::dispatcher::with_default(&dispatch, f);
+
+    std::fs::read_to_string(path).unwrap()
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+struct AppState {
+    api_url: Option<String>,
+    signature_token: Option<String>,
+    api_token: Option<String>,
+}
+
+#[test]
+fn no_token_value_reaches_the_log_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("moosicbox_app.log");
+
+    let api_token = "file-api-token-value";
+    let signature_token = "file-signature-token-value";
+    register_secret(api_token);
+    register_secret(signature_token);
+
+    let contents = with_log_file(&path, || {
+        let state = AppState {
+            api_url: Some("https://api.example.com".to_string()),
+            signature_token: Some(signature_token.to_string()),
+            api_token: Some(api_token.to_string()),
+        };
+
+        log::debug!("set_state: state={state:?}");
+        log::debug!(
+            "set_state: updating API_TOKEN from '{:?}' -> '{:?}'",
+            None::<&str>,
+            state.api_token
+        );
+        log::error!(
+            "got invalid message: {}",
+            r#"{"type":"SetSession","payload":{"apiToken":"unregistered-token"}}"#
+        );
+        log::info!(
+            target: "moosicbox_player",
+            "Fetching track: https://api.example.com/files/track?trackId=1&signature={signature_token}&clientId=abc"
+        );
+        log::warn!(target: "moosicbox_ws", "Authorization: Bearer unregistered-bearer");
+        tracing::info!(target: "hyper", url = "https://api.example.com/?signature=abc", "request");
+    });
+
+    assert_eq!(contents.lines().count(), 6, "{contents}");
+    assert!(contents.contains("https://api.example.com"));
+    assert!(contents.contains("moosicbox_player: Fetching track"));
+    assert!(contents.contains("hyper: request"));
+    for secret in [
+        api_token,
+        signature_token,
+        "unregistered-token",
+        "unregistered-bearer",
+        "clientId=abc",
+        "signature=abc",
+    ] {
+        assert!(
+            !contents.contains(secret),
+            "{secret} was logged: {contents}"
+        );
+    }
+}
+
+#[test]
+fn events_without_secrets_are_logged_as_is() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("moosicbox_app.log");
+
+    let contents = with_log_file(&path, || {
+        log::info!(target: "moosicbox_app", "connected to https://api.example.com");
+        tracing::debug!(target: "moosicbox_app", count = 3, ok = true, "players registered");
+    });
+
+    assert_eq!(
+        contents
+            .lines()
+            .map(str::trim_end)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        " INFO moosicbox_app: connected to https://api.example.com\n\
+         DEBUG moosicbox_app: players registered count=3 ok=true"
+    );
+}
@@ -0,0 +1,76 @@
+use std::{fs, path::Path};
+
+use moosicbox_app_logging::{log_files, rotate, LogRotation};
+
+const ROTATION: LogRotation = LogRotation {
+    max_size: 10,
+    max_files: 2,
+};
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn leaves_small_and_missing_files_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+
+    assert!(!rotate(&path, &ROTATION).unwrap());
+
+    fs::write(&path, "0123456789").unwrap();
+    assert!(!rotate(&path, &ROTATION).unwrap());
+    assert_eq!(read(&path), "0123456789");
+    assert_eq!(log_files(&path), vec![path.clone()]);
+}
+
+#[test]
+fn copies_and_truncates_large_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    fs::write(&path, "first file!").unwrap();
+
+    assert!(rotate(&path, &ROTATION).unwrap());
+
+    assert_eq!(read(&path), "");
+    assert_eq!(read(&dir.path().join("app.log.1")), "first file!");
+}
+
+#[test]
+fn keeps_at_most_max_files_copies() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+
+    for contents in ["first file!", "second file!", "third file!"] {
+        fs::write(&path, contents).unwrap();
+        assert!(rotate(&path, &ROTATION).unwrap());
+    }
+
+    assert_eq!(read(&dir.path().join("app.log.1")), "third file!");
+    assert_eq!(read(&dir.path().join("app.log.2")), "second file!");
+    assert!(!dir.path().join("app.log.3").exists());
+    assert_eq!(
+        log_files(&path),
+        vec![
+            path.clone(),
+            dir.path().join("app.log.1"),
+            dir.path().join("app.log.2"),
+        ]
+    );
+}
+
+#[test]
+fn without_copies_only_truncates() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    fs::write(&path, "too large to keep").unwrap();
+
+    let rotation = LogRotation {
+        max_files: 0,
+        ..ROTATION
+    };
+    assert!(rotate(&path, &rotation).unwrap());
+
+    assert_eq!(read(&path), "");
+    assert_eq!(log_files(&path), vec![path.clone()]);
+}
@@ -0,0 +1,247 @@
+#![cfg_attr(feature = "fail-on-warnings", deny(warnings))]
+
+use std::sync::Arc;
+
+use rustls::{
+    client::{
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        VerifierBuilderError, WebPkiServerVerifier,
+    },
+    crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use thiserror::Error;
+
+pub use rustls;
+
+/// TLS trust settings for a single server connection
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsConfig {
+    /// PEM encoded CA certificates to trust in addition to the bundled web PKI roots
+    #[serde(default)]
+    pub root_certificates: Vec<String>,
+    /// SHA-256 fingerprints of server certificates to trust regardless of who issued them
+    #[serde(default)]
+    pub pinned_certificates: Vec<String>,
+    /// Report unknown certificates through the `on_untrusted` callback so the user can decide
+    /// whether to pin them
+    #[serde(default)]
+    pub trust_on_first_use: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UntrustedCertificate {
+    pub server_name: String,
+    pub fingerprint: String,
+}
+
+pub type UntrustedCertificateCallback = Arc<dyn Fn(UntrustedCertificate) + Send + Sync>;
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("Invalid PEM certificate: {0}")]
+    Pem(#[from] std::io::Error),
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+    #[error(transparent)]
+    VerifierBuilder(#[from] VerifierBuilderError),
+}
+
+/// Lowercase hex SHA-256 fingerprint of a DER encoded certificate
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|x| format!("{x:02x}"))
+        .collect()
+}
+
+/// Normalizes a fingerprint copied from a browser or `openssl`, e.g. `AB:CD:...`
+pub fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint
+        .chars()
+        .filter(char::is_ascii_hexdigit)
+        .map(|x| x.to_ascii_lowercase())
+        .collect()
+}
+
+impl TlsConfig {
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub fn is_pinned(&self, fingerprint: &str) -> bool {
+        let fingerprint = normalize_fingerprint(fingerprint);
+        self.pinned_certificates
+            .iter()
+            .any(|x| normalize_fingerprint(x) == fingerprint)
+    }
+
+    /// Adds `fingerprint` to the pinned certificates if it isn't already pinned
+    pub fn pin(&mut self, fingerprint: &str) {
+        if !self.is_pinned(fingerprint) {
+            self.pinned_certificates
+                .push(normalize_fingerprint(fingerprint));
+        }
+    }
+
+    pub fn root_cert_store(&self) -> Result<RootCertStore, TlsError> {
+        let mut roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+
+        for pem in &self.root_certificates {
+            for cert in rustls_pemfile::certs(&mut pem.as_bytes()) {
+                roots.add(cert?)?;
+            }
+        }
+
+        Ok(roots)
+    }
+
+    /// Builds the certificate verifier used by [`TlsConfig::client_config`]
+    pub fn server_cert_verifier(
+        &self,
+        on_untrusted: Option<UntrustedCertificateCallback>,
+    ) -> Result<Arc<dyn ServerCertVerifier>, TlsError> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let webpki = WebPkiServerVerifier::builder_with_provider(
+            Arc::new(self.root_cert_store()?),
+            provider.clone(),
+        )
+        .build()?;
+
+        Ok(Arc::new(PinningVerifier {
+            webpki,
+            provider,
+            pinned: self
+                .pinned_certificates
+                .iter()
+                .map(|x| normalize_fingerprint(x))
+                .collect(),
+            on_untrusted: on_untrusted.filter(|_| self.trust_on_first_use),
+        }))
+    }
+
+    /// Builds a rustls client config that can be shared by the WebSocket and HTTP clients
+    pub fn client_config(
+        &self,
+        on_untrusted: Option<UntrustedCertificateCallback>,
+    ) -> Result<Arc<ClientConfig>, TlsError> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(self.server_cert_verifier(on_untrusted)?)
+            .with_no_client_auth();
+
+        Ok(Arc::new(config))
+    }
+}
+
+fn server_name_string(server_name: &ServerName<'_>) -> String {
+    match server_name {
+        ServerName::DnsName(name) => name.as_ref().to_string(),
+        ServerName::IpAddress(ip) => std::net::IpAddr::from(*ip).to_string(),
+        _ => format!("{server_name:?}"),
+    }
+}
+
+/// Accepts pinned certificates outright and falls back to regular web PKI validation, with the
+/// extra root certificates, for everything else.
+struct PinningVerifier {
+    webpki: Arc<WebPkiServerVerifier>,
+    provider: Arc<CryptoProvider>,
+    pinned: Vec<String>,
+    on_untrusted: Option<UntrustedCertificateCallback>,
+}
+
+impl std::fmt::Debug for PinningVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PinningVerifier")
+            .field("pinned", &self.pinned)
+            .field("trust_on_first_use", &self.on_untrusted.is_some())
+            .finish()
+    }
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = fingerprint(end_entity.as_ref());
+
+        if self.pinned.contains(&fingerprint) {
+            log::trace!("verify_server_cert: certificate is pinned fingerprint={fingerprint}");
+            return Ok(ServerCertVerified::assertion());
+        }
+
+        match self.webpki.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        ) {
+            Ok(verified) => Ok(verified),
+            Err(e) => {
+                let server_name = server_name_string(server_name);
+                log::debug!(
+                    "verify_server_cert: untrusted certificate server_name={server_name} fingerprint={fingerprint}: {e:?}"
+                );
+
+                if let Some(on_untrusted) = &self.on_untrusted {
+                    on_untrusted(UntrustedCertificate {
+                        server_name,
+                        fingerprint,
+                    });
+                }
+
+                Err(e)
+            }
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
@@ -0,0 +1,169 @@
+use std::sync::{Arc, Mutex};
+
+use moosicbox_app_tls::{
+    fingerprint, normalize_fingerprint,
+    rustls::pki_types::{CertificateDer, ServerName, UnixTime},
+    TlsConfig, UntrustedCertificate, UntrustedCertificateCallback,
+};
+
+const CERT: &[u8] = b"not a real certificate";
+
+fn verify(
+    config: &TlsConfig,
+    on_untrusted: Option<UntrustedCertificateCallback>,
+) -> Result<(), moosicbox_app_tls::rustls::Error> {
+    let verifier = config.server_cert_verifier(on_untrusted).unwrap();
+    let server_name = ServerName::try_from("moosicbox.example").unwrap();
+
+    verifier
+        .verify_server_cert(
+            &CertificateDer::from(CERT),
+            &[],
+            &server_name,
+            &[],
+            UnixTime::now(),
+        )
+        .map(|_| ())
+}
+
+fn recording_callback() -> (
+    UntrustedCertificateCallback,
+    Arc<Mutex<Vec<UntrustedCertificate>>>,
+) {
+    let reported = Arc::new(Mutex::new(vec![]));
+    let callback = {
+        let reported = reported.clone();
+        Arc::new(move |x| reported.lock().unwrap().push(x)) as UntrustedCertificateCallback
+    };
+    (callback, reported)
+}
+
+/// `AB:CD:..` formatted version of a lowercase hex fingerprint
+fn openssl_format(fingerprint: &str) -> String {
+    fingerprint
+        .as_bytes()
+        .chunks(2)
+        .map(|x| std::str::from_utf8(x).unwrap().to_ascii_uppercase())
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[test]
+fn fingerprint_is_lowercase_hex_sha256() {
+    assert_eq!(
+        fingerprint(b""),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+}
+
+#[test]
+fn normalize_fingerprint_strips_separators_and_case() {
+    assert_eq!(normalize_fingerprint("AB:cd:0F"), "abcd0f");
+    assert_eq!(normalize_fingerprint(" ab cd-0f "), "abcd0f");
+    assert_eq!(normalize_fingerprint("abcd0f"), "abcd0f");
+}
+
+#[test]
+fn is_pinned_ignores_fingerprint_format() {
+    let fingerprint = fingerprint(CERT);
+    let config = TlsConfig {
+        pinned_certificates: vec![openssl_format(&fingerprint)],
+        ..Default::default()
+    };
+
+    assert!(config.is_pinned(&fingerprint));
+    assert!(config.is_pinned(&fingerprint.to_ascii_uppercase()));
+    assert!(!config.is_pinned(&moosicbox_app_tls::fingerprint(b"other")));
+}
+
+#[test]
+fn pin_stores_normalized_fingerprint_once() {
+    let fingerprint = fingerprint(CERT);
+    let mut config = TlsConfig::default();
+
+    config.pin(&openssl_format(&fingerprint));
+    config.pin(&fingerprint);
+
+    assert_eq!(config.pinned_certificates, vec![fingerprint]);
+    assert!(!config.is_default());
+}
+
+#[test]
+fn config_fields_default_when_missing() {
+    let config: TlsConfig = serde_json::from_str("{}").unwrap();
+    assert!(config.is_default());
+
+    let config: TlsConfig =
+        serde_json::from_str(r#"{"pinnedCertificates":["ab"],"trustOnFirstUse":true}"#).unwrap();
+    assert_eq!(config.pinned_certificates, vec!["ab".to_string()]);
+    assert!(config.trust_on_first_use);
+}
+
+#[test]
+fn verifier_accepts_pinned_certificate() {
+    let config = TlsConfig {
+        pinned_certificates: vec![openssl_format(&fingerprint(CERT))],
+        ..Default::default()
+    };
+
+    assert!(verify(&config, None).is_ok());
+}
+
+#[test]
+fn verifier_rejects_unpinned_certificate() {
+    let config = TlsConfig {
+        pinned_certificates: vec![fingerprint(b"other")],
+        ..Default::default()
+    };
+
+    assert!(verify(&config, None).is_err());
+}
+
+#[test]
+fn verifier_reports_untrusted_certificate_on_first_use() {
+    let (callback, reported) = recording_callback();
+    let config = TlsConfig {
+        trust_on_first_use: true,
+        ..Default::default()
+    };
+
+    assert!(verify(&config, Some(callback)).is_err());
+    assert_eq!(
+        *reported.lock().unwrap(),
+        vec![UntrustedCertificate {
+            server_name: "moosicbox.example".to_string(),
+            fingerprint: fingerprint(CERT),
+        }]
+    );
+}
+
+#[test]
+fn verifier_does_not_report_without_trust_on_first_use() {
+    let (callback, reported) = recording_callback();
+
+    assert!(verify(&TlsConfig::default(), Some(callback)).is_err());
+    assert!(reported.lock().unwrap().is_empty());
+}
+
+#[test]
+fn verifier_does_not_report_pinned_certificate() {
+    let (callback, reported) = recording_callback();
+    let config = TlsConfig {
+        pinned_certificates: vec![fingerprint(CERT)],
+        trust_on_first_use: true,
+        ..Default::default()
+    };
+
+    assert!(verify(&config, Some(callback)).is_ok());
+    assert!(reported.lock().unwrap().is_empty());
+}
+
+#[test]
+fn client_config_builds_with_pins() {
+    let config = TlsConfig {
+        pinned_certificates: vec![fingerprint(CERT)],
+        ..Default::default()
+    };
+
+    assert!(config.client_config(None).is_ok());
+}
@@ -0,0 +1,758 @@
+//! permessage-deflate (RFC 7692) for inbound messages.
+//!
+//! tungstenite has no support for the extension and rejects frames with RSV1 set, so
+//! `DeflateStream` parses frames below it and inflates compressed ones. It only relies on the
+//! wire format, not on tungstenite internals, but it can't see tungstenite's configuration, e.g.
+//! a lowered max message size. Replace it once tungstenite supports the extension natively.
+
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use flate2::{Decompress, FlushDecompress};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Value of the `Sec-WebSocket-Extensions` header offered during the handshake
+pub(crate) const PERMESSAGE_DEFLATE_OFFER: &str = "permessage-deflate";
+
+/// Trailer stripped from each compressed message by the sender (RFC 7692 7.2.1)
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Upper bound for a single inflated message, matching tungstenite's default max message size
+const MAX_INFLATED_SIZE: usize = 64 << 20;
+
+/// Upper bound for a single frame payload, matching tungstenite's default max frame size.
+/// Larger frames are rejected before they are buffered.
+const MAX_FRAME_SIZE: u64 = 16 << 20;
+
+/// Handshake responses larger than this are passed through without looking for extensions
+const MAX_HANDSHAKE_SIZE: usize = 64 << 10;
+
+const READ_BUFFER_SIZE: usize = 8 << 10;
+
+/// Byte counts since the client was created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// Whether the server accepted permessage-deflate on the current connection
+    pub negotiated: bool,
+    /// Inbound text and binary message payload bytes as received over the wire
+    pub compressed_bytes: u64,
+    /// Inbound text and binary message payload bytes after inflating compressed messages
+    pub uncompressed_bytes: u64,
+    /// Bytes written after the handshake, frame headers included. Outgoing messages are never
+    /// compressed.
+    pub sent_bytes: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct CompressionCounters {
+    negotiated: AtomicBool,
+    failed: AtomicBool,
+    compressed_bytes: AtomicU64,
+    uncompressed_bytes: AtomicU64,
+    sent_bytes: AtomicU64,
+}
+
+impl CompressionCounters {
+    pub fn stats(&self) -> CompressionStats {
+        CompressionStats {
+            negotiated: self.negotiated.load(Ordering::SeqCst),
+            compressed_bytes: self.compressed_bytes.load(Ordering::SeqCst),
+            uncompressed_bytes: self.uncompressed_bytes.load(Ordering::SeqCst),
+            sent_bytes: self.sent_bytes.load(Ordering::SeqCst),
+        }
+    }
+
+    pub fn set_negotiated(&self, negotiated: bool) {
+        self.negotiated.store(negotiated, Ordering::SeqCst);
+    }
+
+    /// Returns `true` once if a compressed message failed to inflate since the last call
+    pub fn take_failed(&self) -> bool {
+        self.failed.swap(false, Ordering::SeqCst)
+    }
+
+    fn record(&self, compressed: usize, uncompressed: usize) {
+        self.compressed_bytes
+            .fetch_add(compressed as u64, Ordering::SeqCst);
+        self.uncompressed_bytes
+            .fetch_add(uncompressed as u64, Ordering::SeqCst);
+    }
+
+    fn record_sent(&self, sent: usize) {
+        self.sent_bytes.fetch_add(sent as u64, Ordering::SeqCst);
+    }
+}
+
+struct Inflater {
+    decompress: Decompress,
+    no_context_takeover: bool,
+}
+
+impl Inflater {
+    fn new(no_context_takeover: bool) -> Self {
+        Self {
+            decompress: Decompress::new(false),
+            no_context_takeover,
+        }
+    }
+
+    fn inflate(&mut self, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let mut input = Vec::with_capacity(payload.len() + DEFLATE_TAIL.len());
+        input.extend_from_slice(payload);
+        input.extend_from_slice(&DEFLATE_TAIL);
+
+        let mut output = Vec::with_capacity(payload.len() * 4);
+        let mut offset = 0;
+
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(output.capacity().max(1024));
+            }
+
+            let total_in = self.decompress.total_in();
+            let total_out = self.decompress.total_out();
+
+            self.decompress
+                .decompress_vec(&input[offset..], &mut output, FlushDecompress::Sync)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            let consumed = (self.decompress.total_in() - total_in) as usize;
+            let produced = self.decompress.total_out() - total_out;
+            offset += consumed;
+
+            if output.len() > MAX_INFLATED_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Inflated message exceeds the max message size",
+                ));
+            }
+
+            let has_spare_capacity = output.len() < output.capacity();
+
+            if offset >= input.len() && has_spare_capacity {
+                break;
+            }
+            if consumed == 0 && produced == 0 && has_spare_capacity {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Truncated compressed message",
+                ));
+            }
+        }
+
+        if self.no_context_takeover {
+            self.decompress.reset(false);
+        }
+
+        Ok(output)
+    }
+}
+
+/// Returns whether the handshake response accepted permessage-deflate, and whether the server
+/// resets its compression context between messages
+fn parse_handshake_response(response: &[u8]) -> Option<bool> {
+    let response = String::from_utf8_lossy(response);
+
+    response
+        .split("\r\n")
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("sec-websocket-extensions"))
+        .flat_map(|(_, value)| value.split(','))
+        .find_map(|extension| {
+            let mut params = extension.split(';').map(str::trim);
+
+            if !params
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case(PERMESSAGE_DEFLATE_OFFER))
+            {
+                return None;
+            }
+
+            Some(params.any(|param| param.eq_ignore_ascii_case("server_no_context_takeover")))
+        })
+}
+
+struct FrameHeader {
+    fin: bool,
+    rsv1: bool,
+    opcode: u8,
+    mask: Option<[u8; 4]>,
+    header_len: usize,
+    payload_len: u64,
+}
+
+impl FrameHeader {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        let (&b0, &b1) = (bytes.first()?, bytes.get(1)?);
+        let masked = b1 & 0x80 != 0;
+
+        let (payload_len, mut header_len) = match b1 & 0x7f {
+            126 => (
+                u64::from(u16::from_be_bytes(bytes.get(2..4)?.try_into().ok()?)),
+                4,
+            ),
+            127 => (u64::from_be_bytes(bytes.get(2..10)?.try_into().ok()?), 10),
+            len => (u64::from(len), 2),
+        };
+
+        let mask = if masked {
+            let mask = bytes.get(header_len..header_len + 4)?.try_into().ok()?;
+            header_len += 4;
+            Some(mask)
+        } else {
+            None
+        };
+
+        Some(Self {
+            fin: b0 & 0x80 != 0,
+            rsv1: b0 & 0x40 != 0,
+            opcode: b0 & 0x0f,
+            mask,
+            header_len,
+            payload_len,
+        })
+    }
+
+    fn is_control(&self) -> bool {
+        self.opcode & 0x08 != 0
+    }
+}
+
+fn write_frame(output: &mut VecDeque<u8>, opcode: u8, payload: &[u8]) {
+    output.push_back(0x80 | opcode);
+
+    match payload.len() {
+        len @ 0..=125 => output.push_back(len as u8),
+        len @ 126..=0xffff => {
+            output.push_back(126);
+            output.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            output.push_back(127);
+            output.extend((len as u64).to_be_bytes());
+        }
+    }
+
+    output.extend(payload);
+}
+
+struct CompressedMessage {
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+enum Phase {
+    Handshake,
+    Frames { inflater: Option<Inflater> },
+}
+
+/// Sits between the socket and tungstenite, which rejects frames with reserved bits set.
+///
+/// Once the handshake response accepts permessage-deflate, compressed messages coming from the
+/// server are inflated and handed to tungstenite as plain frames. Outgoing messages are sent
+/// uncompressed, which the extension allows.
+pub(crate) struct DeflateStream<S> {
+    inner: S,
+    offered: bool,
+    phase: Phase,
+    input: Vec<u8>,
+    output: VecDeque<u8>,
+    message: Option<CompressedMessage>,
+    uncompressed_message: bool,
+    counters: Arc<CompressionCounters>,
+    eof: bool,
+}
+
+impl<S> DeflateStream<S> {
+    pub fn new(inner: S, offered: bool, counters: Arc<CompressionCounters>) -> Self {
+        counters.set_negotiated(false);
+
+        Self {
+            inner,
+            offered,
+            phase: Phase::Handshake,
+            input: vec![],
+            output: VecDeque::new(),
+            message: None,
+            uncompressed_message: false,
+            counters,
+            eof: false,
+        }
+    }
+
+    fn pass_through(&mut self, len: usize) {
+        self.output.extend(self.input.drain(..len));
+    }
+
+    fn process_handshake(&mut self) {
+        let Some(end) = self
+            .input
+            .windows(4)
+            .position(|x| x == b"\r\n\r\n")
+            .map(|x| x + 4)
+        else {
+            if self.input.len() > MAX_HANDSHAKE_SIZE || self.eof {
+                self.phase = Phase::Frames { inflater: None };
+                let len = self.input.len();
+                self.pass_through(len);
+            }
+            return;
+        };
+
+        let inflater = self
+            .offered
+            .then(|| parse_handshake_response(&self.input[..end]))
+            .flatten()
+            .map(Inflater::new);
+
+        if self.offered {
+            if inflater.is_some() {
+                log::debug!("Server accepted permessage-deflate");
+            } else {
+                log::debug!("Server declined permessage-deflate");
+            }
+        }
+
+        self.counters.set_negotiated(inflater.is_some());
+        self.phase = Phase::Frames { inflater };
+        self.pass_through(end);
+    }
+
+    fn process_frame(&mut self) -> io::Result<bool> {
+        let Some(header) = FrameHeader::parse(&self.input) else {
+            return Ok(false);
+        };
+        let frame_len = (header.payload_len <= MAX_FRAME_SIZE)
+            .then(|| usize::try_from(header.payload_len).ok())
+            .flatten()
+            .and_then(|x| x.checked_add(header.header_len))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Frame payload of {} bytes exceeds the max frame size",
+                        header.payload_len
+                    ),
+                )
+            })?;
+        let payload_len = frame_len - header.header_len;
+
+        if self.input.len() < frame_len {
+            return Ok(false);
+        }
+
+        let negotiated = matches!(self.phase, Phase::Frames { inflater: Some(_) });
+
+        if header.is_control() {
+            self.pass_through(frame_len);
+            return Ok(true);
+        }
+
+        let compressed = match header.opcode {
+            0 => self.message.is_some(),
+            _ => header.rsv1 && negotiated,
+        };
+
+        if !compressed {
+            if header.opcode != 0 {
+                self.uncompressed_message = true;
+            }
+            if self.uncompressed_message {
+                self.counters.record(payload_len, payload_len);
+            }
+            if header.fin {
+                self.uncompressed_message = false;
+            }
+            self.pass_through(frame_len);
+            return Ok(true);
+        }
+
+        let mut payload = self.input[header.header_len..frame_len].to_vec();
+        self.input.drain(..frame_len);
+
+        if let Some(mask) = header.mask {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+
+        let message = self.message.get_or_insert_with(|| CompressedMessage {
+            opcode: header.opcode,
+            payload: vec![],
+        });
+        message.payload.extend_from_slice(&payload);
+
+        if message.payload.len() > MAX_INFLATED_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Compressed message exceeds the max message size",
+            ));
+        }
+
+        if !header.fin {
+            return Ok(true);
+        }
+
+        let Phase::Frames {
+            inflater: Some(inflater),
+        } = &mut self.phase
+        else {
+            unreachable!("compressed message without a negotiated inflater");
+        };
+
+        let message = self.message.take().unwrap();
+        let inflated = inflater.inflate(&message.payload).inspect_err(|e| {
+            log::error!("Failed to inflate message: {e:?}");
+            self.counters.failed.store(true, Ordering::SeqCst);
+        })?;
+
+        self.counters.record(message.payload.len(), inflated.len());
+        write_frame(&mut self.output, message.opcode, &inflated);
+
+        Ok(true)
+    }
+
+    fn process(&mut self) -> io::Result<()> {
+        if matches!(self.phase, Phase::Handshake) {
+            self.process_handshake();
+        }
+
+        if matches!(self.phase, Phase::Frames { .. }) {
+            while self.process_frame()? {}
+
+            if self.eof {
+                let len = self.input.len();
+                self.pass_through(len);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for DeflateStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            if !this.output.is_empty() {
+                let len = buf.remaining().min(this.output.len());
+                let (front, back) = this.output.as_slices();
+                let front_len = len.min(front.len());
+                buf.put_slice(&front[..front_len]);
+                buf.put_slice(&back[..len - front_len]);
+                this.output.drain(..len);
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.eof {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut chunk = [0_u8; READ_BUFFER_SIZE];
+            let mut chunk = ReadBuf::new(&mut chunk);
+
+            match Pin::new(&mut this.inner).poll_read(cx, &mut chunk) {
+                Poll::Ready(Ok(())) => {
+                    if chunk.filled().is_empty() {
+                        this.eof = true;
+                    } else {
+                        this.input.extend_from_slice(chunk.filled());
+                    }
+                    this.process()?;
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for DeflateStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+
+        if let (Poll::Ready(Ok(sent)), Phase::Frames { .. }) = (&poll, &this.phase) {
+            this.counters.record_sent(*sent);
+        }
+
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::{Compress, Compression, FlushCompress};
+
+    use super::*;
+
+    const ACCEPTED: &[u8] = b"HTTP/1.1 101 Switching Protocols\r\n\
+        Upgrade: websocket\r\n\
+        Sec-WebSocket-Extensions: permessage-deflate; server_no_context_takeover\r\n\r\n";
+    const DECLINED: &[u8] = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n";
+
+    const TEXT: u8 = 0x1;
+    const CONTINUATION: u8 = 0x0;
+    const PING: u8 = 0x9;
+
+    fn stream(response: &[u8]) -> DeflateStream<()> {
+        let mut stream = DeflateStream::new((), true, Arc::new(CompressionCounters::default()));
+        assert_eq!(feed(&mut stream, response).unwrap(), response);
+        stream
+    }
+
+    fn feed(stream: &mut DeflateStream<()>, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        stream.input.extend_from_slice(bytes);
+        stream.process()?;
+        Ok(stream.output.drain(..).collect())
+    }
+
+    fn frame(fin: bool, rsv1: bool, opcode: u8, payload: &[u8], mask: Option<[u8; 4]>) -> Vec<u8> {
+        let mut output = VecDeque::new();
+        write_frame(&mut output, opcode, payload);
+        let mut bytes = Vec::from(output);
+
+        if !fin {
+            bytes[0] &= 0x7f;
+        }
+        if rsv1 {
+            bytes[0] |= 0x40;
+        }
+        if let Some(mask) = mask {
+            let header_len = bytes.len() - payload.len();
+            bytes[1] |= 0x80;
+            let masked = payload.iter().enumerate().map(|(i, x)| x ^ mask[i % 4]);
+            bytes = bytes[..header_len]
+                .iter()
+                .copied()
+                .chain(mask)
+                .chain(masked)
+                .collect();
+        }
+
+        bytes
+    }
+
+    fn plain(payload: &[u8]) -> Vec<u8> {
+        frame(true, false, TEXT, payload, None)
+    }
+
+    fn deflate(compress: &mut Compress, payload: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(payload.len() + 64);
+        compress
+            .compress_vec(payload, &mut output, FlushCompress::Sync)
+            .unwrap();
+        assert!(output.ends_with(&DEFLATE_TAIL));
+        output.truncate(output.len() - DEFLATE_TAIL.len());
+        output
+    }
+
+    fn compressor() -> Compress {
+        Compress::new(Compression::default(), false)
+    }
+
+    #[test]
+    fn parse_handshake_response_finds_permessage_deflate() {
+        assert_eq!(parse_handshake_response(ACCEPTED), Some(true));
+        assert_eq!(
+            parse_handshake_response(
+                b"HTTP/1.1 101\r\nsec-websocket-extensions: x-other, PERMESSAGE-DEFLATE\r\n\r\n"
+            ),
+            Some(false)
+        );
+        assert_eq!(parse_handshake_response(DECLINED), None);
+    }
+
+    #[test]
+    fn handshake_negotiates_only_when_offered_and_accepted() {
+        let counters = Arc::new(CompressionCounters::default());
+
+        let mut offered = DeflateStream::new((), true, counters.clone());
+        feed(&mut offered, ACCEPTED).unwrap();
+        assert!(counters.stats().negotiated);
+
+        let mut declined = DeflateStream::new((), true, counters.clone());
+        feed(&mut declined, DECLINED).unwrap();
+        assert!(!counters.stats().negotiated);
+
+        let mut not_offered = DeflateStream::new((), false, counters.clone());
+        feed(&mut not_offered, ACCEPTED).unwrap();
+        assert!(!counters.stats().negotiated);
+    }
+
+    #[test]
+    fn compressed_frame_is_inflated_into_plain_frame() {
+        let mut stream = stream(ACCEPTED);
+        let payload = deflate(&mut compressor(), b"hello");
+
+        let output = feed(&mut stream, &frame(true, true, TEXT, &payload, None)).unwrap();
+
+        assert_eq!(output, plain(b"hello"));
+        assert_eq!(
+            stream.counters.stats(),
+            CompressionStats {
+                negotiated: true,
+                compressed_bytes: payload.len() as u64,
+                uncompressed_bytes: 5,
+                sent_bytes: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn masked_compressed_frame_is_unmasked_before_inflating() {
+        let mut stream = stream(ACCEPTED);
+        let payload = deflate(&mut compressor(), b"masked message");
+        let masked = frame(true, true, TEXT, &payload, Some([0x12, 0x34, 0x56, 0x78]));
+
+        assert_eq!(
+            feed(&mut stream, &masked).unwrap(),
+            plain(b"masked message")
+        );
+    }
+
+    #[test]
+    fn fragmented_compressed_message_is_inflated_once_complete() {
+        let mut stream = stream(ACCEPTED);
+        let message = "fragmented ".repeat(40);
+        let payload = deflate(&mut compressor(), message.as_bytes());
+        let (first, second) = payload.split_at(payload.len() / 2);
+
+        let output = feed(&mut stream, &frame(false, true, TEXT, first, None)).unwrap();
+        assert!(output.is_empty());
+
+        let output = feed(&mut stream, &frame(true, false, CONTINUATION, second, None)).unwrap();
+        assert_eq!(output, plain(message.as_bytes()));
+    }
+
+    #[test]
+    fn control_frames_pass_through_between_fragments() {
+        let mut stream = stream(ACCEPTED);
+        let payload = deflate(&mut compressor(), b"around a ping");
+        let (first, second) = payload.split_at(payload.len() / 2);
+        let ping = frame(true, false, PING, b"ping", None);
+
+        let bytes = [
+            frame(false, true, TEXT, first, None),
+            ping.clone(),
+            frame(true, false, CONTINUATION, second, None),
+        ]
+        .concat();
+
+        let output = feed(&mut stream, &bytes).unwrap();
+
+        assert_eq!(output, [ping, plain(b"around a ping")].concat());
+    }
+
+    #[test]
+    fn uncompressed_frames_pass_through_unchanged() {
+        let mut stream = stream(ACCEPTED);
+        let bytes = [
+            frame(false, false, TEXT, b"un", None),
+            frame(true, false, CONTINUATION, b"compressed", None),
+        ]
+        .concat();
+
+        assert_eq!(feed(&mut stream, &bytes).unwrap(), bytes);
+        assert_eq!(stream.counters.stats().compressed_bytes, 12);
+        assert_eq!(stream.counters.stats().uncompressed_bytes, 12);
+    }
+
+    #[test]
+    fn partial_frame_waits_for_remaining_bytes() {
+        let mut stream = stream(ACCEPTED);
+        let payload = deflate(&mut compressor(), b"split across reads");
+        let bytes = frame(true, true, TEXT, &payload, None);
+
+        for byte in &bytes[..bytes.len() - 1] {
+            assert!(feed(&mut stream, &[*byte]).unwrap().is_empty());
+        }
+
+        assert_eq!(
+            feed(&mut stream, &bytes[bytes.len() - 1..]).unwrap(),
+            plain(b"split across reads")
+        );
+    }
+
+    #[test]
+    fn shared_context_is_kept_between_messages() {
+        let response = b"HTTP/1.1 101 Switching Protocols\r\n\
+            Sec-WebSocket-Extensions: permessage-deflate\r\n\r\n";
+        let mut stream = stream(response);
+        let mut compress = compressor();
+
+        for _ in 0..3 {
+            let payload = deflate(&mut compress, b"repeated message");
+            let output = feed(&mut stream, &frame(true, true, TEXT, &payload, None)).unwrap();
+            assert_eq!(output, plain(b"repeated message"));
+        }
+    }
+
+    #[test]
+    fn long_inflated_message_uses_extended_length() {
+        let mut stream = stream(ACCEPTED);
+        let message = vec![b'a'; 70_000];
+        let payload = deflate(&mut compressor(), &message);
+
+        let output = feed(&mut stream, &frame(true, true, TEXT, &payload, None)).unwrap();
+
+        assert_eq!(output[1], 127);
+        assert_eq!(output, plain(&message));
+    }
+
+    #[test]
+    fn invalid_compressed_payload_fails_and_is_reported() {
+        let mut stream = stream(ACCEPTED);
+
+        let error = feed(&mut stream, &frame(true, true, TEXT, &[0xff; 16], None)).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(stream.counters.take_failed());
+        assert!(!stream.counters.take_failed());
+    }
+
+    #[test]
+    fn oversized_frame_is_rejected_before_buffering() {
+        let mut stream = stream(ACCEPTED);
+        let mut header = vec![0x80 | TEXT, 127];
+        header.extend((MAX_FRAME_SIZE + 1).to_be_bytes());
+
+        let error = feed(&mut stream, &header).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn frame_length_overflow_is_rejected() {
+        let mut stream = stream(DECLINED);
+        let mut header = vec![0x80 | TEXT, 0x80 | 127];
+        header.extend(u64::MAX.to_be_bytes());
+        header.extend([0; 4]);
+
+        let error = feed(&mut stream, &header).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use futures_util::FutureExt as _;
+use tokio::sync::{Barrier, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+type Job = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Runs jobs one at a time per key, in the order they were dispatched, while jobs for different
+/// keys run concurrently up to `max_concurrency` at once. A job dispatched with several keys
+/// waits for the earlier jobs of each of them and holds up the later ones.
+pub struct OrderedDispatcher<K> {
+    lanes: Arc<Mutex<HashMap<K, VecDeque<Job>>>>,
+    permits: Arc<Semaphore>,
+}
+
+impl<K> Clone for OrderedDispatcher<K> {
+    fn clone(&self) -> Self {
+        Self {
+            lanes: self.lanes.clone(),
+            permits: self.permits.clone(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone + Send + 'static> OrderedDispatcher<K> {
+    pub fn new(max_concurrency: usize) -> Self {
+        Self {
+            lanes: Arc::new(Mutex::new(HashMap::new())),
+            permits: Arc::new(Semaphore::new(max_concurrency.max(1))),
+        }
+    }
+
+    /// Queues `job` behind every job previously dispatched with the same `key`
+    pub fn dispatch(&self, key: K, job: impl Future<Output = ()> + Send + 'static) {
+        let job = self.with_permit(job);
+
+        self.enqueue([(key, job)]);
+    }
+
+    /// Queues `job` behind every job previously dispatched with any of `keys`. Jobs dispatched
+    /// later with one of `keys` wait for it, while other keys aren't held up. Without keys,
+    /// `job` runs right away.
+    pub fn dispatch_all(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+        job: impl Future<Output = ()> + Send + 'static,
+    ) {
+        let mut seen = HashSet::new();
+        let mut keys = keys
+            .into_iter()
+            .filter(|key| seen.insert(key.clone()))
+            .collect::<Vec<_>>();
+
+        if keys.len() <= 1 {
+            let job = self.with_permit(job);
+
+            match keys.pop() {
+                Some(key) => self.enqueue([(key, job)]),
+                None => {
+                    moosicbox_task::spawn("ws: ordered dispatch", job);
+                }
+            }
+            return;
+        }
+
+        // Each key's lane gets a job that waits at the barrier until the lanes of every key got
+        // to it. One of them then runs `job`, while the others wait for it to finish.
+        let job = Arc::new(Mutex::new(Some(self.with_permit(job))));
+        let barrier = Arc::new(Barrier::new(keys.len()));
+        let done = CancellationToken::new();
+
+        let jobs = keys
+            .into_iter()
+            .map(|key| {
+                let job = job.clone();
+                let barrier = barrier.clone();
+                let done = done.clone();
+
+                let fence: Job = Box::pin(async move {
+                    if barrier.wait().await.is_leader() {
+                        let _done = done.drop_guard();
+                        let job = job.lock().unwrap().take();
+                        if let Some(job) = job {
+                            job.await;
+                        }
+                    } else {
+                        done.cancelled().await;
+                    }
+                });
+
+                (key, fence)
+            })
+            .collect::<Vec<_>>();
+
+        self.enqueue(jobs);
+    }
+
+    /// Only holds a permit while `job` runs, so waiting at a fence doesn't take up one
+    fn with_permit(&self, job: impl Future<Output = ()> + Send + 'static) -> Job {
+        let permits = self.permits.clone();
+
+        Box::pin(async move {
+            let Ok(_permit) = permits.acquire().await else {
+                return;
+            };
+
+            job.await;
+        })
+    }
+
+    /// Appends each job to its key's lane under one lock, so jobs with several keys are in the
+    /// same order in every lane and can't wait on each other
+    fn enqueue(&self, jobs: impl IntoIterator<Item = (K, Job)>) {
+        let mut lanes = self.lanes.lock().unwrap();
+
+        for (key, job) in jobs {
+            if let Some(lane) = lanes.get_mut(&key) {
+                lane.push_back(job);
+                continue;
+            }
+
+            lanes.insert(key.clone(), VecDeque::from([job]));
+            self.spawn_lane(key);
+        }
+    }
+
+    fn spawn_lane(&self, key: K) {
+        let lanes = self.lanes.clone();
+
+        moosicbox_task::spawn("ws: ordered dispatch", async move {
+            loop {
+                let job = {
+                    let mut lanes = lanes.lock().unwrap();
+                    let Some(job) = lanes.get_mut(&key).and_then(VecDeque::pop_front) else {
+                        lanes.remove(&key);
+                        break;
+                    };
+                    job
+                };
+
+                if std::panic::AssertUnwindSafe(job)
+                    .catch_unwind()
+                    .await
+                    .is_err()
+                {
+                    log::error!("Dispatched job panicked");
+                }
+            }
+        });
+    }
+
+    /// Number of keys with jobs queued or running
+    pub fn active_keys(&self) -> usize {
+        self.lanes.lock().unwrap().len()
+    }
+}
@@ -1,24 +1,55 @@
 #![cfg_attr(feature = "fail-on-warnings", deny(warnings))]
 
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::future::Future;
-use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use bytes::Bytes;
-use futures_channel::mpsc::UnboundedSender;
-use futures_util::{future, pin_mut, StreamExt as _};
+use futures_util::{future, pin_mut, SinkExt as _, StreamExt as _};
+use moosicbox_app_logging::Redacted;
+use rand::Rng as _;
+use serde::Serialize;
+use serde_json::Value;
 use thiserror::Error;
+use tokio::net::TcpStream;
 use tokio::select;
 use tokio::sync::mpsc::error::SendError;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::watch;
 use tokio::time::sleep;
+use tokio_rustls::TlsConnector;
 use tokio_tungstenite::{
-    connect_async,
-    tungstenite::{Error, Message},
+    client_async_with_config,
+    tungstenite::{
+        client::IntoClientRequest as _,
+        error::{TlsError, UrlError},
+        handshake::client::{Request, Response},
+        http::{header, Error as HttpError, HeaderName, HeaderValue, StatusCode},
+        protocol::{frame::coding::CloseCode, CloseFrame},
+        Error, Message,
+    },
+    MaybeTlsStream, WebSocketStream,
 };
 use tokio_util::sync::CancellationToken;
 
+mod deflate;
+mod dispatch;
+mod queue;
+mod request;
+
+pub use deflate::CompressionStats;
+use deflate::{CompressionCounters, DeflateStream, PERMESSAGE_DEFLATE_OFFER};
+pub use dispatch::OrderedDispatcher;
+
+use queue::OutboundQueue;
+pub use queue::{DeliveryError, DeliveryReceipt, Priority, DEFAULT_QUEUE_CAPACITY};
+use request::PendingRequests;
+pub use request::{RequestError, REQUEST_ID_FIELD};
+pub use tokio_tungstenite::Connector;
+
 #[derive(Debug, Error)]
 pub enum SendBytesError {
     #[error("Unknown {0:?}")]
@@ -31,14 +62,20 @@ pub enum SendMessageError {
     Unknown(String),
 }
 
+#[derive(Debug, Clone)]
 pub enum WsMessage {
     TextMessage(String),
     Message(Bytes),
     Ping,
+    Close { code: Option<u16>, reason: String },
 }
 
 #[derive(Debug, Error)]
 pub enum WebsocketSendError {
+    #[error("Outbound queue is full")]
+    QueueFull,
+    #[error("WebSocket client is closed")]
+    Closed,
     #[error("Unknown: {0}")]
     Unknown(String),
 }
@@ -55,16 +92,89 @@ impl core::fmt::Debug for dyn WebsocketSender {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    /// A connection attempt is in progress
+    Connecting,
+    /// The handshake completed and the connection is open
+    Connected,
+    /// The connection was lost or could not be established
+    Disconnected { reason: Option<String> },
+    /// Waiting `next_in` before making reconnect `attempt`
+    Reconnecting { attempt: u32, next_in: Duration },
+    /// Stopped reconnecting because the reconnect policy ran out of attempts or the server
+    /// closed the connection with a code that shouldn't be retried
+    GaveUp,
+    /// The server rejected the credentials. The client stops, since reconnecting with the same
+    /// credentials can't succeed.
+    Unauthorized,
+    /// The client was closed or cancelled
+    Closed,
+}
+
 #[derive(Debug, Error)]
 pub enum CloseError {
     #[error("Unknown {0:?}")]
     Unknown(String),
 }
 
+const LATENCY_SAMPLE_COUNT: usize = 10;
+
+#[derive(Debug, Default)]
+struct LatencySamples {
+    samples: VecDeque<Duration>,
+}
+
+impl LatencySamples {
+    fn push(&mut self, rtt: Duration) {
+        if self.samples.len() >= LATENCY_SAMPLE_COUNT {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(rtt);
+    }
+
+    fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    fn average(&self) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        Some(self.samples.iter().sum::<Duration>() / self.samples.len() as u32)
+    }
+}
+
+/// When the reader last waited on a full message receiver. Pongs queued behind unread messages
+/// can't be read during that time, so it doesn't count towards the pong timeout.
+#[derive(Debug, Default, Clone, Copy)]
+struct ReaderStall {
+    since: Option<Instant>,
+    ended_at: Option<Instant>,
+}
+
+impl ReaderStall {
+    /// Whether `sent_at` is longer than `timeout` ago, not counting the time since the last stall
+    /// started
+    fn timed_out(&self, sent_at: Instant, timeout: Duration) -> bool {
+        if self.since.is_some() {
+            return false;
+        }
+
+        let waiting_since = self.ended_at.map_or(sent_at, |x| x.max(sent_at));
+        waiting_since.elapsed() > timeout
+    }
+}
+
 #[derive(Clone)]
 pub struct WsHandle {
-    sender: Arc<RwLock<Option<UnboundedSender<WsMessage>>>>,
+    queue: Arc<OutboundQueue>,
+    requests: Arc<PendingRequests>,
     cancellation_token: CancellationToken,
+    state: Arc<watch::Sender<ConnectionState>>,
+    latency: Arc<RwLock<LatencySamples>>,
+    compression: Arc<CompressionCounters>,
 }
 
 impl WsHandle {
@@ -73,60 +183,467 @@ impl WsHandle {
 
         Ok(())
     }
+
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    /// Rolling average round-trip time of the last few pings on the current connection
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency.read().unwrap().average()
+    }
+
+    /// Inbound byte counters, for comparing wire size to message size with permessage-deflate
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.compression.stats()
+    }
+
+    /// Queues a message, waiting for room in the queue if it is full
+    pub async fn send_message(
+        &self,
+        message: WsMessage,
+        priority: Priority,
+    ) -> Result<DeliveryReceipt, WebsocketSendError> {
+        self.queue.push(message, priority).await
+    }
+
+    /// Queues a message, failing with `WebsocketSendError::QueueFull` if the queue is full
+    pub fn try_send_message(
+        &self,
+        message: WsMessage,
+        priority: Priority,
+    ) -> Result<DeliveryReceipt, WebsocketSendError> {
+        self.queue.try_push(message, priority)
+    }
+
+    /// Number of messages waiting to be written to the socket
+    pub fn queued_messages(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Sends `payload` as a JSON text message tagged with a unique `requestId` and waits for the
+    /// inbound message that echoes it
+    pub async fn request(
+        &self,
+        payload: &impl Serialize,
+        timeout: Duration,
+    ) -> Result<Value, RequestError> {
+        self.request_or_match(payload, |_| false, timeout).await
+    }
+
+    /// Same as `request`, but a response without a `requestId` also resolves the request if
+    /// `is_response` accepts it, for servers that don't echo the `requestId`.
+    ///
+    /// The response is still passed on to the regular message receiver.
+    pub async fn request_or_match(
+        &self,
+        payload: &impl Serialize,
+        is_response: impl Fn(&Value) -> bool + Send + Sync + 'static,
+        timeout: Duration,
+    ) -> Result<Value, RequestError> {
+        let id = self.requests.next_id();
+
+        let mut message = serde_json::to_value(payload)?;
+        message
+            .as_object_mut()
+            .ok_or(RequestError::InvalidPayload)?
+            .insert(REQUEST_ID_FIELD.to_string(), id.into());
+
+        let response = self.requests.register(id, is_response);
+
+        let result = tokio::time::timeout(timeout, async {
+            self.send_message(WsMessage::TextMessage(message.to_string()), Priority::High)
+                .await?
+                .delivered()
+                .await?;
+
+            response.await.map_err(|_| RequestError::Closed)
+        })
+        .await
+        .unwrap_or(Err(RequestError::Timeout));
+
+        if result.is_err() {
+            self.requests.remove(id);
+        }
+
+        result
+    }
 }
 
 #[async_trait]
 impl WebsocketSender for WsHandle {
     async fn send(&self, data: &str) -> Result<(), WebsocketSendError> {
-        if let Some(sender) = self.sender.read().unwrap().as_ref() {
-            sender
-                .unbounded_send(WsMessage::TextMessage(data.to_string()))
-                .map_err(|e| WebsocketSendError::Unknown(e.to_string()))?;
-        }
+        self.send_message(WsMessage::TextMessage(data.to_string()), Priority::Normal)
+            .await?;
         Ok(())
     }
 
     async fn ping(&self) -> Result<(), WebsocketSendError> {
-        if let Some(sender) = self.sender.read().unwrap().as_ref() {
-            sender
-                .unbounded_send(WsMessage::Ping)
-                .map_err(|e| WebsocketSendError::Unknown(e.to_string()))?;
-        }
+        self.send_message(WsMessage::Ping, Priority::High).await?;
         Ok(())
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Delay before the first reconnect attempt
+    pub initial_delay: Duration,
+    /// Factor the delay is multiplied by after each failed attempt
+    pub multiplier: f64,
+    /// Upper bound for the delay between attempts
+    pub max_delay: Duration,
+    /// Fraction (0.0 to 1.0) of the delay to randomly add or subtract
+    pub jitter: f64,
+    /// Number of consecutive failed attempts before giving up. `None` retries forever
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            multiplier: 2.0,
+            max_delay: Duration::from_millis(30_000),
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn max_attempts(mut self, max_attempts: Option<u32>) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Returns `true` if the given 1-based attempt exceeds `max_attempts`
+    pub fn should_give_up(&self, attempt: u32) -> bool {
+        self.max_attempts.is_some_and(|max| attempt > max)
+    }
+
+    /// Returns the delay before the given 1-based reconnect attempt, without jitter
+    pub fn base_delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let millis = self.initial_delay.as_millis() as f64 * self.multiplier.powi(exponent);
+        let max_millis = self.max_delay.as_millis() as f64;
+
+        if !millis.is_finite() || millis >= max_millis {
+            self.max_delay
+        } else {
+            Duration::from_millis(millis.max(0.0) as u64)
+        }
+    }
+
+    /// Returns `false` if a server close with the given code means reconnecting won't help,
+    /// e.g. a policy close when the server rejected our credentials
+    pub fn should_reconnect_after_close(&self, code: u16) -> bool {
+        !matches!(CloseCode::from(code), CloseCode::Policy)
+    }
+
+    /// Returns the delay before the given 1-based reconnect attempt with jitter applied
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let base = self.base_delay(attempt);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+
+        if jitter == 0.0 || base.is_zero() {
+            return base;
+        }
+
+        let factor = rand::thread_rng().gen_range((1.0 - jitter)..=(1.0 + jitter));
+
+        base.mul_f64(factor).min(self.max_delay)
+    }
+}
+
+/// Builds the handshake request for `url`.
+///
+/// By default the profile is sent as the `moosicbox-profile` header, and only the `clientId`
+/// that routes the request through a tunnel server goes in the query string. The signature is
+/// never sent this way; authenticate with an `Authorization` handshake header instead. With
+/// `query_auth` the profile and signature are appended to the query string too, for servers that
+/// don't read handshake headers.
+#[allow(clippy::too_many_arguments, clippy::result_large_err)]
+fn handshake_request(
+    url: &str,
+    profile: &str,
+    client_id: Option<&str>,
+    signature_token: Option<&str>,
+    headers: &HashMap<String, String>,
+    protocol: Option<&str>,
+    query_auth: bool,
+    deflate: bool,
+) -> Result<Request, Error> {
+    let mut request = if query_auth {
+        let profile_param = format!("?moosicboxProfile={profile}");
+        let auth_params = match (client_id, signature_token) {
+            (Some(id), Some(token)) => format!("&clientId={id}&signature={token}"),
+            (Some(id), None) => format!("&clientId={id}"),
+            _ => String::new(),
+        };
+        format!("{url}{profile_param}{auth_params}").into_client_request()?
+    } else if let Some(id) = client_id {
+        format!("{url}?clientId={id}").into_client_request()?
+    } else {
+        url.into_client_request()?
+    };
+
+    let request_headers = request.headers_mut();
+
+    for (name, value) in headers {
+        request_headers.insert(
+            HeaderName::from_bytes(name.as_bytes()).map_err(HttpError::from)?,
+            HeaderValue::from_str(value).map_err(HttpError::from)?,
+        );
+    }
+
+    if !query_auth {
+        request_headers.insert(
+            "moosicbox-profile",
+            HeaderValue::from_str(profile).map_err(HttpError::from)?,
+        );
+    }
+
+    if let Some(protocol) = protocol {
+        request_headers.insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_str(protocol).map_err(HttpError::from)?,
+        );
+    }
+
+    if deflate {
+        request_headers.insert(
+            header::SEC_WEBSOCKET_EXTENSIONS,
+            HeaderValue::from_static(PERMESSAGE_DEFLATE_OFFER),
+        );
+    }
+
+    if let Some(value) = request_headers.get_mut(header::AUTHORIZATION) {
+        value.set_sensitive(true);
+    }
+
+    Ok(request)
+}
+
+type WsStream = WebSocketStream<DeflateStream<MaybeTlsStream<TcpStream>>>;
+
+fn default_tls_config() -> Arc<rustls::ClientConfig> {
+    let roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+
+    Arc::new(
+        rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default protocol versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth(),
+    )
+}
+
+/// Same as `connect_async_tls_with_config`, except that the handshake and every frame go through
+/// a `DeflateStream` so that compressed messages are inflated before tungstenite sees them
+#[allow(clippy::result_large_err)]
+async fn connect(
+    request: Request,
+    connector: Option<Connector>,
+    deflate: bool,
+    compression: Arc<CompressionCounters>,
+) -> Result<(WsStream, Response), Error> {
+    let uri = request.uri();
+    let host = uri
+        .host()
+        .ok_or(Error::Url(UrlError::NoHostName))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let secure = match uri.scheme_str() {
+        Some("wss") => true,
+        Some("ws") => false,
+        _ => return Err(Error::Url(UrlError::UnsupportedUrlScheme)),
+    };
+    let port = uri.port_u16().unwrap_or(if secure { 443 } else { 80 });
+
+    let socket = TcpStream::connect((host.as_str(), port)).await?;
+
+    let stream = match (secure, connector) {
+        (false, _) | (true, Some(Connector::Plain)) => MaybeTlsStream::Plain(socket),
+        (true, connector) => {
+            let config = match connector {
+                Some(Connector::Rustls(config)) => config,
+                _ => default_tls_config(),
+            };
+            let server_name = rustls::pki_types::ServerName::try_from(host)
+                .map_err(|_| Error::Tls(TlsError::InvalidDnsName))?;
+            let stream = TlsConnector::from(config)
+                .connect(server_name, socket)
+                .await?;
+            MaybeTlsStream::Rustls(stream)
+        }
+    };
+
+    client_async_with_config(
+        request,
+        DeflateStream::new(stream, deflate, compression),
+        None,
+    )
+    .await
+}
+
 #[derive(Clone)]
 pub struct WsClient {
     url: String,
-    sender: Arc<RwLock<Option<UnboundedSender<WsMessage>>>>,
+    queue: Arc<OutboundQueue>,
+    requests: Arc<PendingRequests>,
     cancellation_token: CancellationToken,
+    external_cancellation_token: Option<CancellationToken>,
+    reconnect_policy: ReconnectPolicy,
+    state: Arc<watch::Sender<ConnectionState>>,
+    latency: Arc<RwLock<LatencySamples>>,
+    ping_interval: Duration,
+    pong_timeout: Duration,
+    headers: HashMap<String, String>,
+    protocol: Option<String>,
+    query_auth_fallback: bool,
+    connector: Option<Connector>,
+    deflate: bool,
+    compression: Arc<CompressionCounters>,
 }
 
 impl WsClient {
     pub fn new(url: String) -> (Self, WsHandle) {
-        let sender = Arc::new(RwLock::new(None));
+        let queue = Arc::new(OutboundQueue::new(DEFAULT_QUEUE_CAPACITY));
+        let requests = Arc::new(PendingRequests::default());
         let cancellation_token = CancellationToken::new();
+        let (state, _) = watch::channel(ConnectionState::Disconnected { reason: None });
+        let state = Arc::new(state);
+        let latency = Arc::new(RwLock::new(LatencySamples::default()));
+        let compression = Arc::new(CompressionCounters::default());
         let handle = WsHandle {
-            sender: sender.clone(),
+            queue: queue.clone(),
+            requests: requests.clone(),
             cancellation_token: cancellation_token.clone(),
+            state: state.clone(),
+            latency: latency.clone(),
+            compression: compression.clone(),
         };
 
         (
             Self {
                 url,
-                sender: sender.clone(),
+                queue,
+                requests,
                 cancellation_token: cancellation_token.clone(),
+                external_cancellation_token: None,
+                reconnect_policy: ReconnectPolicy::default(),
+                state,
+                latency,
+                ping_interval: Duration::from_millis(5000),
+                pong_timeout: Duration::from_millis(15000),
+                headers: HashMap::new(),
+                protocol: None,
+                query_auth_fallback: false,
+                connector: None,
+                deflate: false,
+                compression,
             },
             handle,
         )
     }
 
+    /// Replaces the token that stops the client. Handles returned by `new` still cancel the
+    /// original token, so `WsHandle::close` no longer stops the client.
     pub fn with_cancellation_token(mut self, token: CancellationToken) -> Self {
         self.cancellation_token = token;
         self
     }
 
+    /// Also closes the client when `token` is cancelled. `WsHandle::close` keeps working.
+    pub fn with_linked_cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.external_cancellation_token = Some(token);
+        self
+    }
+
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = policy;
+        self
+    }
+
+    /// Maximum number of messages waiting in each priority lane of the outbound queue
+    pub fn with_queue_capacity(self, capacity: usize) -> Self {
+        self.queue.set_capacity(capacity);
+        self
+    }
+
+    /// Extra headers sent with the handshake request, e.g. `Authorization`
+    pub fn with_handshake_headers(mut self, headers: HashMap<String, String>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Value for the `Sec-WebSocket-Protocol` handshake header
+    pub fn with_protocol(mut self, protocol: Option<String>) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Whether to retry with the profile and signature in the query string when the server
+    /// rejects the header based handshake. Disabled by default, since a proxy answering 401
+    /// would otherwise get the signature put back in the URL.
+    pub fn with_query_auth_fallback(mut self, enabled: bool) -> Self {
+        self.query_auth_fallback = enabled;
+        self
+    }
+
+    /// TLS connector used for `wss` connections instead of the default web PKI roots
+    pub fn with_tls_connector(mut self, connector: Option<Connector>) -> Self {
+        self.connector = connector;
+        self
+    }
+
+    /// Offer permessage-deflate during the handshake. Servers that decline it, or send a message
+    /// that fails to inflate, get uncompressed connections from then on.
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.deflate = enabled;
+        self
+    }
+
+    pub fn with_ping_interval(mut self, interval: Duration) -> Self {
+        self.ping_interval = interval;
+        self
+    }
+
+    /// Forces a reconnect when a ping goes unanswered for longer than `timeout`
+    pub fn with_pong_timeout(mut self, timeout: Duration) -> Self {
+        self.pong_timeout = timeout;
+        self
+    }
+
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
     async fn message_handler(
         tx: Sender<WsMessage>,
         m: Message,
@@ -140,8 +657,20 @@ impl WsClient {
                 log::debug!("Received pong");
                 return Ok(());
             }
-            Message::Close(_m) => unimplemented!(),
-            Message::Frame(_m) => unimplemented!(),
+            Message::Close(frame) => match frame {
+                Some(frame) => WsMessage::Close {
+                    code: Some(frame.code.into()),
+                    reason: frame.reason.into_owned(),
+                },
+                None => WsMessage::Close {
+                    code: None,
+                    reason: String::new(),
+                },
+            },
+            Message::Frame(_m) => {
+                log::trace!("Received raw frame");
+                return Ok(());
+            }
         })
         .await
     }
@@ -177,37 +706,58 @@ impl WsClient {
         let (tx, rx) = channel(1024);
 
         let url = self.url.clone();
-        let sender_arc = self.sender.clone();
+        let queue = self.queue.clone();
+        let requests = self.requests.clone();
         let cancellation_token = self.cancellation_token.clone();
+        let reconnect_policy = self.reconnect_policy;
+        let state = self.state.clone();
+        let latency = self.latency.clone();
+        let ping_interval = self.ping_interval;
+        let pong_timeout = self.pong_timeout;
+        let headers = self.headers.clone();
+        let protocol = self.protocol.clone();
+        let query_auth_fallback = self.query_auth_fallback;
+        let connector = self.connector.clone();
+        let compression = self.compression.clone();
+        let mut deflate = self.deflate;
+
+        if let Some(external) = self.external_cancellation_token.clone() {
+            let cancellation_token = cancellation_token.clone();
+
+            moosicbox_task::spawn("ws: external cancellation", async move {
+                select!(
+                    _ = external.cancelled() => cancellation_token.cancel(),
+                    _ = cancellation_token.cancelled() => {}
+                );
+            });
+        }
 
         moosicbox_task::spawn("ws", async move {
-            let mut just_retried = false;
+            let mut attempt = 0_u32;
+            let mut query_auth = false;
 
             loop {
                 let close_token = CancellationToken::new();
 
-                let (txf, rxf) = futures_channel::mpsc::unbounded();
-
-                sender_arc.write().unwrap().replace(txf.clone());
-
-                let profile_param = format!("?moosicboxProfile={profile}");
-                let client_id_param = if let Some(id) = &client_id {
-                    format!("&clientId={id}")
-                } else {
-                    "".to_string()
-                };
-                let signature_token_param = if client_id.is_some() {
-                    if let Some(token) = &signature_token {
-                        format!("&signature={token}")
-                    } else {
-                        "".to_string()
-                    }
-                } else {
-                    "".to_string()
-                };
+                let request = handshake_request(
+                    &url,
+                    &profile,
+                    client_id.as_deref(),
+                    signature_token.as_deref(),
+                    &headers,
+                    protocol.as_deref(),
+                    query_auth,
+                    deflate,
+                );
                 log::debug!("Connecting to websocket...");
+                state.send_replace(ConnectionState::Connecting);
+                let connection = {
+                    let connector = connector.clone();
+                    let compression = compression.clone();
+                    async move { connect(request?, connector, deflate, compression).await }
+                };
                 match select!(
-                    resp = connect_async(format!("{url}{profile_param}{client_id_param}{signature_token_param}")) => resp,
+                    resp = connection => resp,
                     _ = cancellation_token.cancelled() => {
                         log::debug!("Cancelling connect");
                         break;
@@ -215,75 +765,208 @@ impl WsClient {
                 ) {
                     Ok((ws_stream, _)) => {
                         log::debug!("WebSocket handshake has been successfully completed");
+                        state.send_replace(ConnectionState::Connected);
                         on_start();
 
-                        if just_retried {
+                        if attempt > 0 {
                             log::info!("WebSocket successfully reconnected");
-                            just_retried = false;
+                            attempt = 0;
                         }
 
                         let (write, read) = ws_stream.split();
+                        let disconnect_reason = Arc::new(Mutex::new(None));
+                        let close_code = Mutex::new(None::<u16>);
+                        let ping_sequence = AtomicU64::new(0);
+                        let outstanding_pings =
+                            Arc::new(Mutex::new(BTreeMap::<u64, Instant>::new()));
+                        let reader_stall = Arc::new(Mutex::new(ReaderStall::default()));
+                        latency.write().unwrap().clear();
 
-                        let ws_writer = rxf
-                            .map(|message| match message {
-                                WsMessage::TextMessage(message) => {
-                                    moosicbox_logging::debug_or_trace!(
-                                        ("Sending text packet from request"),
-                                        ("Sending text packet from request message={message}")
-                                    );
-                                    Ok(Message::Text(message))
-                                }
-                                WsMessage::Message(bytes) => {
-                                    log::debug!("Sending packet from request",);
-                                    Ok(Message::Binary(bytes.to_vec()))
-                                }
-                                WsMessage::Ping => {
-                                    log::trace!("Sending ping");
-                                    Ok(Message::Ping(vec![]))
+                        let ws_writer = async {
+                            let mut write = write;
+
+                            while let Some(outbound) = queue.pop().await {
+                                let queue::Outbound { message, delivery } = outbound;
+
+                                let message = match message {
+                                    WsMessage::TextMessage(message) => {
+                                        moosicbox_logging::debug_or_trace!(
+                                            ("Sending text packet from request"),
+                                            (
+                                                "Sending text packet from request message={}",
+                                                Redacted(&message)
+                                            )
+                                        );
+                                        Message::Text(message)
+                                    }
+                                    WsMessage::Message(bytes) => {
+                                        log::debug!("Sending packet from request",);
+                                        Message::Binary(bytes.to_vec())
+                                    }
+                                    WsMessage::Ping => {
+                                        let sequence = ping_sequence.fetch_add(1, Ordering::SeqCst);
+                                        log::trace!("Sending ping sequence={sequence}");
+                                        outstanding_pings
+                                            .lock()
+                                            .unwrap()
+                                            .insert(sequence, Instant::now());
+                                        Message::Ping(sequence.to_be_bytes().to_vec())
+                                    }
+                                    WsMessage::Close { code, reason } => {
+                                        log::debug!("Sending close code={code:?} reason={reason}");
+                                        Message::Close(code.map(|code| CloseFrame {
+                                            code: code.into(),
+                                            reason: reason.into(),
+                                        }))
+                                    }
+                                };
+
+                                if let Err(e) = write.send(message).await {
+                                    delivery.complete(Err(DeliveryError::Send(e.to_string())));
+                                    return Err(e);
                                 }
-                            })
-                            .forward(write);
+
+                                delivery.complete(Ok(()));
+                            }
+
+                            Ok(())
+                        };
 
                         let ws_reader = read.for_each(|m| async {
                             let m = match m {
                                 Ok(m) => m,
                                 Err(e) => {
                                     log::error!("Send Loop error: {:?}", e);
+                                    disconnect_reason.lock().unwrap().replace(e.to_string());
                                     close_token.cancel();
                                     return;
                                 }
                             };
 
-                            moosicbox_task::spawn("ws: Process WS message", {
-                                let tx = tx.clone();
-                                let close_token = close_token.clone();
+                            if !requests.is_empty() {
+                                match &m {
+                                    Message::Text(text) => {
+                                        requests.resolve(text.as_bytes());
+                                    }
+                                    Message::Binary(bytes) => {
+                                        requests.resolve(bytes);
+                                    }
+                                    _ => {}
+                                }
+                            }
 
-                                async move {
-                                    if let Err(e) = handler(tx.clone(), m).await {
-                                        log::error!("Handler Send Loop error: {e:?}");
-                                        close_token.cancel();
+                            // Control frames are handled here without waiting on the receiver,
+                            // so that a slow consumer doesn't hold up pongs
+                            match &m {
+                                Message::Pong(payload) => {
+                                    let sent_at = <[u8; 8]>::try_from(payload.as_slice())
+                                        .ok()
+                                        .map(u64::from_be_bytes)
+                                        .and_then(|sequence| {
+                                            let mut outstanding = outstanding_pings.lock().unwrap();
+                                            let sent_at = outstanding.remove(&sequence);
+                                            // Pongs may be coalesced, so older pings are
+                                            // answered too
+                                            outstanding.retain(|x, _| *x > sequence);
+                                            sent_at
+                                        });
+
+                                    if let Some(sent_at) = sent_at {
+                                        let rtt = sent_at.elapsed();
+                                        log::trace!("Received pong rtt={rtt:?}");
+                                        latency.write().unwrap().push(rtt);
                                     }
+                                    return;
                                 }
-                            });
+                                Message::Frame(_) => {
+                                    log::trace!("Received raw frame");
+                                    return;
+                                }
+                                Message::Ping(_) if tx.capacity() == 0 => {
+                                    log::debug!("Message receiver is full, not forwarding ping");
+                                    return;
+                                }
+                                Message::Close(frame) => {
+                                    let reason = match frame {
+                                        Some(frame) => {
+                                            close_code.lock().unwrap().replace(frame.code.into());
+                                            format!(
+                                                "Closed by server with code {}: {}",
+                                                frame.code, frame.reason
+                                            )
+                                        }
+                                        None => "Closed by server".to_string(),
+                                    };
+                                    log::debug!("{reason}");
+                                    disconnect_reason.lock().unwrap().replace(reason);
+                                }
+                                _ => {}
+                            }
+
+                            let stalled = tx.capacity() == 0;
+                            if stalled {
+                                log::debug!("Message receiver is full, waiting for room");
+                                reader_stall.lock().unwrap().since = Some(Instant::now());
+                            }
+
+                            // Handled inline rather than in a spawned task so that messages
+                            // reach the receiver in the order they arrived
+                            if let Err(e) = handler(tx.clone(), m).await {
+                                log::error!("Handler Send Loop error: {e:?}");
+                                close_token.cancel();
+                            }
+
+                            if stalled {
+                                let mut stall = reader_stall.lock().unwrap();
+                                stall.since = None;
+                                stall.ended_at = Some(Instant::now());
+                            }
                         });
 
                         let pinger = moosicbox_task::spawn("ws: pinger", {
-                            let txf = txf.clone();
+                            let queue = queue.clone();
                             let close_token = close_token.clone();
                             let cancellation_token = cancellation_token.clone();
+                            let outstanding_pings = outstanding_pings.clone();
+                            let disconnect_reason = disconnect_reason.clone();
+                            let reader_stall = reader_stall.clone();
 
                             async move {
                                 loop {
                                     select!(
                                         _ = close_token.cancelled() => { break; }
                                         _ = cancellation_token.cancelled() => { break; }
-                                        _ = tokio::time::sleep(std::time::Duration::from_millis(5000)) => {
-                                            log::trace!("Sending ping to server");
-                                            if let Err(e) = txf.unbounded_send(WsMessage::Ping) {
-                                                log::error!("Pinger Send Loop error: {e:?}");
+                                        _ = tokio::time::sleep(ping_interval) => {
+                                            let oldest = {
+                                                outstanding_pings
+                                                    .lock()
+                                                    .unwrap()
+                                                    .values()
+                                                    .next()
+                                                    .copied()
+                                            };
+                                            let stall = *reader_stall.lock().unwrap();
+                                            if oldest.is_some_and(|x| stall.timed_out(x, pong_timeout)) {
+                                                log::error!("No pong received within {pong_timeout:?}, reconnecting");
+                                                disconnect_reason.lock().unwrap().replace(format!(
+                                                    "No pong received within {pong_timeout:?}"
+                                                ));
                                                 close_token.cancel();
                                                 break;
                                             }
+
+                                            log::trace!("Sending ping to server");
+                                            match queue.try_push(WsMessage::Ping, Priority::High) {
+                                                Ok(_) => {}
+                                                Err(WebsocketSendError::QueueFull) => {
+                                                    log::debug!("Outbound queue is full, skipping ping");
+                                                }
+                                                Err(e) => {
+                                                    log::error!("Pinger Send Loop error: {e:?}");
+                                                    close_token.cancel();
+                                                    break;
+                                                }
+                                            }
                                         }
                                     );
                                 }
@@ -294,7 +977,12 @@ impl WsClient {
                         select!(
                             _ = close_token.cancelled() => {}
                             _ = cancellation_token.cancelled() => {}
-                            _ = future::select(ws_writer, ws_reader) => {}
+                            resp = future::select(ws_writer, ws_reader) => {
+                                if let future::Either::Left((Err(e), _)) = resp {
+                                    log::error!("Write Loop error: {e:?}");
+                                    disconnect_reason.lock().unwrap().replace(e.to_string());
+                                }
+                            }
                         );
                         if !close_token.is_cancelled() {
                             close_token.cancel();
@@ -304,34 +992,130 @@ impl WsClient {
                             log::warn!("start_handler: Pinger failed to finish: {e:?}");
                         }
                         log::info!("WebSocket connection closed");
+
+                        if deflate && compression.take_failed() {
+                            log::warn!("Failed to inflate a message, disabling compression");
+                            deflate = false;
+                        }
+
+                        if !cancellation_token.is_cancelled() {
+                            let reason = disconnect_reason
+                                .lock()
+                                .unwrap()
+                                .take()
+                                .unwrap_or_else(|| "Connection closed".to_string());
+                            state.send_replace(ConnectionState::Disconnected {
+                                reason: Some(reason),
+                            });
+                        }
+
+                        let close_code = *close_code.lock().unwrap();
+                        if let Some(code) = close_code {
+                            if !reconnect_policy.should_reconnect_after_close(code) {
+                                log::error!(
+                                    "WebSocket closed by server with code {code}, not reconnecting"
+                                );
+                                state.send_replace(ConnectionState::GaveUp);
+                                break;
+                            }
+                        }
                     }
-                    Err(err) => match err {
-                        Error::Http(response) => {
-                            if let Ok(body) =
-                                std::str::from_utf8(response.body().as_ref().unwrap_or(&vec![]))
-                            {
-                                log::error!("body: {}", body);
-                            } else {
-                                log::error!("body: (unable to get body)");
+                    Err(err) => {
+                        let reason = match &err {
+                            Error::Http(response) => {
+                                let body = response
+                                    .body()
+                                    .as_ref()
+                                    .and_then(|body| std::str::from_utf8(body).ok());
+                                if let Some(body) = body {
+                                    log::error!("body: {}", body);
+                                } else {
+                                    log::error!("body: (unable to get body)");
+                                }
+                                format!(
+                                    "Handshake failed with status {}: {}",
+                                    response.status(),
+                                    body.unwrap_or_default()
+                                )
                             }
+                            _ => {
+                                log::error!("Failed to connect to websocket server: {err:?}");
+                                err.to_string()
+                            }
+                        };
+                        state.send_replace(ConnectionState::Disconnected {
+                            reason: Some(reason),
+                        });
+
+                        let auth_rejected = matches!(
+                            &err,
+                            Error::Http(response)
+                                if matches!(
+                                    response.status(),
+                                    StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+                                )
+                        );
+
+                        if auth_rejected && !query_auth && query_auth_fallback {
+                            log::warn!(
+                                "Server rejected handshake headers, falling back to query string authentication"
+                            );
+                            state.send_replace(ConnectionState::Disconnected {
+                                reason: Some(
+                                    "Server rejected handshake headers, retrying with query string authentication"
+                                        .to_string(),
+                                ),
+                            });
+                            query_auth = true;
+                            continue;
                         }
-                        _ => log::error!("Failed to connect to websocket server: {err:?}"),
-                    },
-                }
 
-                if just_retried {
-                    select!(
-                        _ = sleep(Duration::from_millis(5000)) => {}
-                        _ = cancellation_token.cancelled() => {
-                            log::debug!("Cancelling retry");
+                        if auth_rejected {
+                            log::error!("Server rejected the credentials, not reconnecting");
+                            state.send_replace(ConnectionState::Unauthorized);
                             break;
                         }
+                    }
+                }
+
+                if cancellation_token.is_cancelled() {
+                    break;
+                }
+
+                attempt = attempt.saturating_add(1);
+
+                if reconnect_policy.should_give_up(attempt) {
+                    log::error!(
+                        "WebSocket failed to reconnect after {} attempts, giving up",
+                        attempt - 1
                     );
-                } else {
-                    just_retried = true;
+                    state.send_replace(ConnectionState::GaveUp);
+                    break;
                 }
+
+                let delay = reconnect_policy.delay(attempt);
+                log::debug!("Reconnecting to websocket in {delay:?} (attempt {attempt})");
+                state.send_replace(ConnectionState::Reconnecting {
+                    attempt,
+                    next_in: delay,
+                });
+
+                select!(
+                    _ = sleep(delay) => {}
+                    _ = cancellation_token.cancelled() => {
+                        log::debug!("Cancelling retry");
+                        break;
+                    }
+                );
             }
 
+            if cancellation_token.is_cancelled() {
+                state.send_replace(ConnectionState::Closed);
+            }
+
+            queue.close();
+            requests.clear();
+
             log::debug!("Handler closed");
         });
 
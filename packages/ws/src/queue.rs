@@ -0,0 +1,201 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use thiserror::Error;
+use tokio::sync::{oneshot, Notify};
+
+use crate::{WebsocketSendError, WsMessage};
+
+pub const DEFAULT_QUEUE_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Priority {
+    /// Pings and control messages. Always sent before any `Normal` message
+    High,
+    /// Bulk traffic such as session updates
+    #[default]
+    Normal,
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum DeliveryError {
+    #[error("Message was dropped before it could be sent")]
+    Dropped,
+    #[error("Failed to send message: {0}")]
+    Send(String),
+}
+
+/// Resolves once the queued message was written to the socket, or failed to be
+#[derive(Debug)]
+pub struct DeliveryReceipt {
+    receiver: oneshot::Receiver<Result<(), DeliveryError>>,
+}
+
+impl DeliveryReceipt {
+    pub async fn delivered(self) -> Result<(), DeliveryError> {
+        self.receiver.await.unwrap_or(Err(DeliveryError::Dropped))
+    }
+}
+
+pub(crate) struct DeliveryNotifier(oneshot::Sender<Result<(), DeliveryError>>);
+
+impl DeliveryNotifier {
+    pub fn complete(self, result: Result<(), DeliveryError>) {
+        let _ = self.0.send(result);
+    }
+}
+
+pub(crate) struct Outbound {
+    pub message: WsMessage,
+    pub delivery: DeliveryNotifier,
+}
+
+#[derive(Default)]
+struct QueueState {
+    high: VecDeque<Outbound>,
+    normal: VecDeque<Outbound>,
+    closed: bool,
+}
+
+/// Outbound messages waiting to be written to the socket. Shared between the `WsHandle`s and
+/// the connection loop so that messages queued while reconnecting are sent on the next
+/// connection instead of being dropped.
+pub(crate) struct OutboundQueue {
+    state: Mutex<QueueState>,
+    capacity: AtomicUsize,
+    message_available: Notify,
+    space_available: Notify,
+}
+
+impl OutboundQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(QueueState::default()),
+            capacity: AtomicUsize::new(capacity),
+            message_available: Notify::new(),
+            space_available: Notify::new(),
+        }
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::SeqCst);
+        self.space_available.notify_waiters();
+    }
+
+    fn push_inner(
+        &self,
+        message: WsMessage,
+        priority: Priority,
+    ) -> Result<DeliveryReceipt, (WsMessage, WebsocketSendError)> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.closed {
+            return Err((message, WebsocketSendError::Closed));
+        }
+
+        let capacity = self.capacity.load(Ordering::SeqCst);
+        let lane = match priority {
+            Priority::High => &mut state.high,
+            Priority::Normal => &mut state.normal,
+        };
+
+        if lane.len() >= capacity {
+            return Err((message, WebsocketSendError::QueueFull));
+        }
+
+        let (tx, rx) = oneshot::channel();
+        lane.push_back(Outbound {
+            message,
+            delivery: DeliveryNotifier(tx),
+        });
+        drop(state);
+
+        self.message_available.notify_waiters();
+
+        Ok(DeliveryReceipt { receiver: rx })
+    }
+
+    pub fn try_push(
+        &self,
+        message: WsMessage,
+        priority: Priority,
+    ) -> Result<DeliveryReceipt, WebsocketSendError> {
+        self.push_inner(message, priority).map_err(|(_, e)| e)
+    }
+
+    /// Waits for room in the `priority` lane before queueing the message
+    pub async fn push(
+        &self,
+        mut message: WsMessage,
+        priority: Priority,
+    ) -> Result<DeliveryReceipt, WebsocketSendError> {
+        loop {
+            let notified = self.space_available.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            match self.push_inner(message, priority) {
+                Ok(receipt) => return Ok(receipt),
+                Err((returned, WebsocketSendError::QueueFull)) => {
+                    message = returned;
+                }
+                Err((_, e)) => return Err(e),
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Waits for the next message, `High` priority first. Returns `None` once the queue is closed
+    pub async fn pop(&self) -> Option<Outbound> {
+        loop {
+            let notified = self.message_available.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            {
+                let mut state = self.state.lock().unwrap();
+
+                if let Some(outbound) = state.high.pop_front().or_else(|| state.normal.pop_front())
+                {
+                    drop(state);
+                    self.space_available.notify_waiters();
+                    return Some(outbound);
+                }
+
+                if state.closed {
+                    return None;
+                }
+            }
+
+            notified.await;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.high.len() + state.normal.len()
+    }
+
+    /// Rejects any further messages and fails everything still waiting to be sent
+    pub fn close(&self) {
+        let pending = {
+            let mut guard = self.state.lock().unwrap();
+            let state = &mut *guard;
+            state.closed = true;
+            state
+                .high
+                .drain(..)
+                .chain(state.normal.drain(..))
+                .collect::<Vec<_>>()
+        };
+
+        for outbound in pending {
+            outbound.delivery.complete(Err(DeliveryError::Dropped));
+        }
+
+        self.message_available.notify_waiters();
+        self.space_available.notify_waiters();
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde_json::Value;
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+use crate::{DeliveryError, WebsocketSendError};
+
+/// Field `WsHandle::request` adds to outbound payloads and expects responses to echo
+pub const REQUEST_ID_FIELD: &str = "requestId";
+
+#[derive(Debug, Error)]
+pub enum RequestError {
+    #[error(transparent)]
+    Send(#[from] WebsocketSendError),
+    #[error(transparent)]
+    Delivery(#[from] DeliveryError),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error("Request payload is not a JSON object")]
+    InvalidPayload,
+    #[error("Timed out waiting for a response")]
+    Timeout,
+    #[error("Connection closed before a response was received")]
+    Closed,
+}
+
+type ResponseMatcher = Box<dyn Fn(&Value) -> bool + Send + Sync>;
+
+struct PendingRequest {
+    id: u64,
+    matcher: ResponseMatcher,
+    sender: oneshot::Sender<Value>,
+}
+
+/// Requests waiting for a response. Inbound JSON messages that carry a `requestId` resolve the
+/// request with that id. Others are offered to the pending requests in the order they were made,
+/// and the first one whose matcher accepts it is resolved with it.
+#[derive(Default)]
+pub(crate) struct PendingRequests {
+    next_id: AtomicU64,
+    requests: Mutex<Vec<PendingRequest>>,
+}
+
+impl PendingRequests {
+    pub fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    pub fn register(
+        &self,
+        id: u64,
+        matcher: impl Fn(&Value) -> bool + Send + Sync + 'static,
+    ) -> oneshot::Receiver<Value> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.requests.lock().unwrap().push(PendingRequest {
+            id,
+            matcher: Box::new(matcher),
+            sender,
+        });
+
+        receiver
+    }
+
+    pub fn remove(&self, id: u64) {
+        self.requests.lock().unwrap().retain(|x| x.id != id);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.lock().unwrap().is_empty()
+    }
+
+    /// Returns `true` if the inbound message was the response to a pending request
+    pub fn resolve(&self, message: &[u8]) -> bool {
+        let Ok(value) = serde_json::from_slice::<Value>(message) else {
+            return false;
+        };
+
+        let request = {
+            let mut requests = self.requests.lock().unwrap();
+            let index = match value.get(REQUEST_ID_FIELD) {
+                Some(id) => requests.iter().position(|x| id.as_u64() == Some(x.id)),
+                None => requests.iter().position(|x| (x.matcher)(&value)),
+            };
+            index.map(|index| requests.remove(index))
+        };
+
+        if let Some(request) = request {
+            log::trace!("Resolved pending request id={}", request.id);
+            let _ = request.sender.send(value);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Fails every pending request with `RequestError::Closed`
+    pub fn clear(&self) {
+        self.requests.lock().unwrap().clear();
+    }
+}
@@ -0,0 +1,712 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use moosicbox_app_ws::{
+    ConnectionState, Priority, ReconnectPolicy, RequestError, WebsocketSendError, WsClient,
+    WsHandle, WsMessage, REQUEST_ID_FIELD,
+};
+use moosicbox_app_ws_test_utils::{MockWsServer, StatusCode};
+use moosicbox_ws::models::{ConnectionIdPayload, EmptyPayload, InboundPayload, OutboundPayload};
+use tokio::sync::{mpsc::Receiver, watch};
+use tokio_util::sync::CancellationToken;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn fast_reconnect() -> ReconnectPolicy {
+    ReconnectPolicy::default()
+        .initial_delay(Duration::from_millis(10))
+        .max_delay(Duration::from_millis(50))
+        .jitter(0.0)
+}
+
+struct Started {
+    handle: WsHandle,
+    state: watch::Receiver<ConnectionState>,
+    messages: Receiver<WsMessage>,
+    starts: Arc<AtomicUsize>,
+}
+
+fn start(client: WsClient, handle: WsHandle) -> Started {
+    let mut client = client;
+    let state = client.connection_state();
+    let starts = Arc::new(AtomicUsize::new(0));
+
+    let messages = client.start(
+        Some("client".to_string()),
+        Some("signature".to_string()),
+        "master".to_string(),
+        {
+            let starts = starts.clone();
+            move || {
+                starts.fetch_add(1, Ordering::SeqCst);
+            }
+        },
+    );
+
+    Started {
+        handle,
+        state,
+        messages,
+        starts,
+    }
+}
+
+async fn wait_for_state(
+    state: &mut watch::Receiver<ConnectionState>,
+    predicate: impl FnMut(&ConnectionState) -> bool,
+) -> ConnectionState {
+    tokio::time::timeout(TIMEOUT, state.wait_for(predicate))
+        .await
+        .expect("Timed out waiting for connection state")
+        .expect("Connection state sender dropped")
+        .clone()
+}
+
+async fn wait_for_connected(state: &mut watch::Receiver<ConnectionState>) {
+    wait_for_state(state, |x| *x == ConnectionState::Connected).await;
+}
+
+fn text(payload: &InboundPayload) -> WsMessage {
+    WsMessage::TextMessage(serde_json::to_string(payload).unwrap())
+}
+
+#[tokio::test]
+async fn start_connects_with_handshake_headers() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client, handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    assert_eq!(started.starts.load(Ordering::SeqCst), 1);
+    assert_eq!(server.connection_count(), 1);
+
+    let handshakes = server.handshake_requests();
+    assert_eq!(handshakes.len(), 1);
+    assert_eq!(handshakes[0].header("moosicbox-profile"), Some("master"));
+    assert_eq!(handshakes[0].uri, "/ws?clientId=client");
+}
+
+#[tokio::test]
+async fn start_forwards_server_payloads() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client, handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    server.send(&OutboundPayload::ConnectionId(ConnectionIdPayload {
+        connection_id: "abc".to_string(),
+    }));
+
+    let message = tokio::time::timeout(TIMEOUT, started.messages.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let WsMessage::TextMessage(message) = message else {
+        panic!("Expected a text message, got {message:?}");
+    };
+
+    assert!(matches!(
+        serde_json::from_str(&message).unwrap(),
+        OutboundPayload::ConnectionId(ConnectionIdPayload { connection_id }) if connection_id == "abc"
+    ));
+}
+
+#[tokio::test]
+async fn server_messages_are_received_in_order() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client, handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    for i in 0..200 {
+        server.send_text(i.to_string());
+    }
+
+    for i in 0..200 {
+        let message = tokio::time::timeout(TIMEOUT, started.messages.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(
+            matches!(&message, WsMessage::TextMessage(x) if *x == i.to_string()),
+            "expected {i}, got {message:?}"
+        );
+    }
+}
+
+async fn recv_text(messages: &mut Receiver<WsMessage>) -> String {
+    let message = tokio::time::timeout(TIMEOUT, messages.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let WsMessage::TextMessage(message) = message else {
+        panic!("Expected a text message, got {message:?}");
+    };
+    message
+}
+
+#[tokio::test]
+async fn compressed_messages_are_inflated() {
+    let server = MockWsServer::start().await.unwrap();
+    server.accept_compression();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client.with_compression(true), handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    assert_eq!(
+        server.handshake_requests()[0].header("sec-websocket-extensions"),
+        Some("permessage-deflate")
+    );
+
+    let message = "compressed ".repeat(100);
+    server.send_compressed(message.clone());
+    server.send_text("plain");
+    server.send_compressed(message.clone());
+
+    assert_eq!(recv_text(&mut started.messages).await, message);
+    assert_eq!(recv_text(&mut started.messages).await, "plain");
+    assert_eq!(recv_text(&mut started.messages).await, message);
+
+    let stats = started.handle.compression_stats();
+    assert!(stats.negotiated);
+    assert_eq!(stats.uncompressed_bytes, (message.len() * 2 + 5) as u64);
+    assert!(stats.compressed_bytes < stats.uncompressed_bytes);
+}
+
+#[tokio::test]
+async fn sent_bytes_are_counted_after_the_handshake() {
+    let server = MockWsServer::start().await.unwrap();
+    server.accept_compression();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client.with_compression(true), handle);
+
+    wait_for_connected(&mut started.state).await;
+    assert_eq!(started.handle.compression_stats().sent_bytes, 0);
+
+    let receipt = started
+        .handle
+        .send_message(
+            WsMessage::TextMessage("hello".to_string()),
+            Priority::Normal,
+        )
+        .await
+        .unwrap();
+    receipt.delivered().await.unwrap();
+
+    // Masked client frames add a 2 byte header and a 4 byte mask to the payload
+    assert_eq!(started.handle.compression_stats().sent_bytes, 5 + 6);
+}
+
+#[tokio::test]
+async fn compression_is_not_negotiated_when_server_declines() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client.with_compression(true), handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    server.send_text("plain");
+
+    assert_eq!(recv_text(&mut started.messages).await, "plain");
+    assert!(!started.handle.compression_stats().negotiated);
+}
+
+#[tokio::test]
+async fn send_message_is_received_by_server() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client, handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    let receipt = started
+        .handle
+        .send_message(
+            text(&InboundPayload::GetConnectionId(EmptyPayload {})),
+            Priority::Normal,
+        )
+        .await
+        .unwrap();
+    receipt.delivered().await.unwrap();
+
+    assert!(matches!(
+        server.recv_inbound(TIMEOUT).await,
+        Some(InboundPayload::GetConnectionId(_))
+    ));
+}
+
+#[tokio::test]
+async fn messages_sent_before_connecting_are_delivered() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+
+    let receipt = handle
+        .send_message(
+            text(&InboundPayload::GetSessions(EmptyPayload {})),
+            Priority::Normal,
+        )
+        .await
+        .unwrap();
+
+    let _started = start(client, handle);
+    receipt.delivered().await.unwrap();
+
+    assert!(matches!(
+        server.recv_inbound(TIMEOUT).await,
+        Some(InboundPayload::GetSessions(_))
+    ));
+}
+
+#[tokio::test]
+async fn high_priority_messages_overtake_normal_ones() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+
+    for text in ["normal 1", "normal 2"] {
+        handle
+            .try_send_message(WsMessage::TextMessage(text.to_string()), Priority::Normal)
+            .unwrap();
+    }
+    handle
+        .try_send_message(WsMessage::TextMessage("high".to_string()), Priority::High)
+        .unwrap();
+
+    let _started = start(client, handle);
+
+    for expected in ["high", "normal 1", "normal 2"] {
+        assert_eq!(server.recv_text(TIMEOUT).await.as_deref(), Some(expected));
+    }
+}
+
+#[tokio::test]
+async fn try_send_message_fails_when_lane_is_full() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let _client = client.with_queue_capacity(2);
+
+    for _ in 0..2 {
+        handle
+            .try_send_message(
+                WsMessage::TextMessage("normal".to_string()),
+                Priority::Normal,
+            )
+            .unwrap();
+    }
+
+    assert!(matches!(
+        handle.try_send_message(
+            WsMessage::TextMessage("normal".to_string()),
+            Priority::Normal
+        ),
+        Err(WebsocketSendError::QueueFull)
+    ));
+    assert_eq!(handle.queued_messages(), 2);
+
+    // Each lane has its own capacity
+    handle
+        .try_send_message(WsMessage::TextMessage("high".to_string()), Priority::High)
+        .unwrap();
+    assert_eq!(handle.queued_messages(), 3);
+}
+
+#[tokio::test]
+async fn send_message_waits_for_room_in_full_lane() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let client = client.with_queue_capacity(1);
+
+    handle
+        .try_send_message(
+            WsMessage::TextMessage("first".to_string()),
+            Priority::Normal,
+        )
+        .unwrap();
+
+    let mut send = tokio::spawn({
+        let handle = handle.clone();
+        async move {
+            handle
+                .send_message(
+                    WsMessage::TextMessage("second".to_string()),
+                    Priority::Normal,
+                )
+                .await
+        }
+    });
+
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), &mut send)
+            .await
+            .is_err(),
+        "send_message returned while the lane was full"
+    );
+
+    let _started = start(client, handle);
+
+    let receipt = tokio::time::timeout(TIMEOUT, send)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    receipt.delivered().await.unwrap();
+
+    for expected in ["first", "second"] {
+        assert_eq!(server.recv_text(TIMEOUT).await.as_deref(), Some(expected));
+    }
+}
+
+#[tokio::test]
+async fn request_resolves_with_scripted_response() {
+    let server = MockWsServer::start().await.unwrap();
+    server.respond_with(|payload| match payload {
+        InboundPayload::GetConnectionId(_) => {
+            vec![OutboundPayload::ConnectionId(ConnectionIdPayload {
+                connection_id: "scripted".to_string(),
+            })]
+        }
+        _ => vec![],
+    });
+
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client, handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    let response = started
+        .handle
+        .request_or_match(
+            &InboundPayload::GetConnectionId(EmptyPayload {}),
+            |value| {
+                serde_json::from_value::<OutboundPayload>(value.clone())
+                    .is_ok_and(|x| matches!(x, OutboundPayload::ConnectionId(_)))
+            },
+            TIMEOUT,
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        serde_json::from_value(response).unwrap(),
+        OutboundPayload::ConnectionId(ConnectionIdPayload { connection_id }) if connection_id == "scripted"
+    ));
+}
+
+#[tokio::test]
+async fn request_resolves_with_echoed_request_id() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client, handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    let request = tokio::spawn({
+        let handle = started.handle.clone();
+        async move {
+            handle
+                .request(&InboundPayload::GetSessions(EmptyPayload {}), TIMEOUT)
+                .await
+        }
+    });
+
+    let sent: serde_json::Value =
+        serde_json::from_str(&server.recv_text(TIMEOUT).await.unwrap()).unwrap();
+    assert!(matches!(
+        serde_json::from_value(sent.clone()).unwrap(),
+        InboundPayload::GetSessions(_)
+    ));
+    let id = sent[REQUEST_ID_FIELD].as_u64().unwrap();
+
+    // Neither an untagged message nor a response to another request resolves it
+    server.send(&OutboundPayload::ConnectionId(ConnectionIdPayload {
+        connection_id: "untagged".to_string(),
+    }));
+    server.send_text(format!(r#"{{"requestId":{},"value":"other"}}"#, id + 1));
+    server.send_text(format!(r#"{{"requestId":{id},"value":"response"}}"#));
+
+    let response = tokio::time::timeout(TIMEOUT, request)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(response["value"], "response");
+}
+
+#[tokio::test]
+async fn request_times_out_without_response() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client, handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    let result = started
+        .handle
+        .request(
+            &InboundPayload::GetConnectionId(EmptyPayload {}),
+            Duration::from_millis(50),
+        )
+        .await;
+
+    assert!(matches!(result, Err(RequestError::Timeout)), "{result:?}");
+}
+
+#[tokio::test]
+async fn reconnects_after_server_drops_connection() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client.with_reconnect_policy(fast_reconnect()), handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    server.drop_connections();
+
+    wait_for_state(&mut started.state, |x| x != &ConnectionState::Connected).await;
+    assert!(server.wait_for_connections(2, TIMEOUT).await);
+    wait_for_connected(&mut started.state).await;
+
+    assert_eq!(started.starts.load(Ordering::SeqCst), 2);
+}
+
+/// Pings every 20ms and gives up on a connection after 200ms without a pong
+fn fast_pings(client: WsClient) -> WsClient {
+    client
+        .with_ping_interval(Duration::from_millis(20))
+        .with_pong_timeout(Duration::from_millis(200))
+        .with_reconnect_policy(fast_reconnect())
+}
+
+#[tokio::test]
+async fn latency_is_measured_from_pongs() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(fast_pings(client), handle);
+
+    wait_for_connected(&mut started.state).await;
+    assert_eq!(started.handle.latency(), None);
+
+    tokio::time::timeout(TIMEOUT, async {
+        while started.handle.latency().is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("No pong was received");
+}
+
+#[tokio::test]
+async fn missing_pongs_force_a_reconnect() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(fast_pings(client), handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    server.stop_reading();
+
+    wait_for_state(&mut started.state, |x| x != &ConnectionState::Connected).await;
+    assert!(server.wait_for_connections(2, TIMEOUT).await);
+
+    server.resume_reading();
+
+    assert!(server.wait_for_connections(2, TIMEOUT).await);
+    wait_for_connected(&mut started.state).await;
+}
+
+#[tokio::test]
+async fn slow_consumer_does_not_trigger_pong_timeout() {
+    const MESSAGES: usize = 1500;
+
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(fast_pings(client), handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    // More than the receiver holds, so the reader waits on it with pongs left unread
+    for i in 0..MESSAGES {
+        server.send_text(i.to_string());
+    }
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+
+    assert_eq!(*started.state.borrow(), ConnectionState::Connected);
+    assert_eq!(server.connection_count(), 1);
+
+    for i in 0..MESSAGES {
+        let message = tokio::time::timeout(TIMEOUT, started.messages.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(
+            matches!(&message, WsMessage::TextMessage(x) if *x == i.to_string()),
+            "expected {i}, got {message:?}"
+        );
+    }
+
+    assert_eq!(server.connection_count(), 1);
+}
+
+#[tokio::test]
+async fn rejected_handshake_gives_up_after_max_attempts() {
+    let server = MockWsServer::start().await.unwrap();
+    server.reject_handshakes(StatusCode::INTERNAL_SERVER_ERROR);
+
+    let (client, handle) = WsClient::new(server.url());
+    let client = client.with_reconnect_policy(fast_reconnect().max_attempts(Some(2)));
+    let mut started = start(client, handle);
+
+    wait_for_state(&mut started.state, |x| *x == ConnectionState::GaveUp).await;
+
+    assert_eq!(server.handshake_requests().len(), 3);
+    assert_eq!(server.connection_count(), 0);
+    assert_eq!(started.starts.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn unauthorized_handshake_falls_back_to_query_auth() {
+    let server = MockWsServer::start().await.unwrap();
+    server.reject_handshakes(StatusCode::UNAUTHORIZED);
+
+    let (client, handle) = WsClient::new(server.url());
+    let client = client
+        .with_reconnect_policy(fast_reconnect().max_attempts(Some(0)))
+        .with_query_auth_fallback(true);
+    let mut started = start(client, handle);
+
+    wait_for_state(&mut started.state, |x| *x == ConnectionState::Unauthorized).await;
+
+    let handshakes = server.handshake_requests();
+    assert_eq!(handshakes.len(), 2);
+    assert_eq!(handshakes[0].uri, "/ws?clientId=client");
+    assert_eq!(
+        handshakes[1].uri,
+        "/ws?moosicboxProfile=master&clientId=client&signature=signature"
+    );
+    assert_eq!(handshakes[1].header("moosicbox-signature"), None);
+}
+
+#[tokio::test]
+async fn unauthorized_handshake_stops_reconnecting() {
+    let server = MockWsServer::start().await.unwrap();
+    server.reject_handshakes(StatusCode::FORBIDDEN);
+
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client.with_reconnect_policy(fast_reconnect()), handle);
+
+    wait_for_state(&mut started.state, |x| *x == ConnectionState::Unauthorized).await;
+
+    assert_eq!(server.handshake_requests().len(), 1);
+    assert_eq!(started.starts.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn policy_close_stops_reconnecting() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client.with_reconnect_policy(fast_reconnect()), handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    server.close_connections(1008, "Invalid signature");
+
+    wait_for_state(&mut started.state, |x| *x == ConnectionState::GaveUp).await;
+    assert_eq!(server.connection_count(), 1);
+}
+
+#[tokio::test]
+async fn close_stops_client() {
+    let server = MockWsServer::start().await.unwrap();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client, handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    started.handle.close().await.unwrap();
+
+    wait_for_state(&mut started.state, |x| *x == ConnectionState::Closed).await;
+
+    while tokio::time::timeout(TIMEOUT, started.messages.recv())
+        .await
+        .expect("Message receiver was not closed")
+        .is_some()
+    {}
+
+    assert!(matches!(
+        started
+            .handle
+            .try_send_message(WsMessage::Ping, Priority::Normal),
+        Err(WebsocketSendError::Closed)
+    ));
+}
+
+#[tokio::test]
+async fn close_cancels_pending_reconnect() {
+    let server = MockWsServer::start().await.unwrap();
+    server.reject_handshakes(StatusCode::INTERNAL_SERVER_ERROR);
+
+    let (client, handle) = WsClient::new(server.url());
+    let client = client.with_reconnect_policy(
+        ReconnectPolicy::default()
+            .initial_delay(Duration::from_secs(60))
+            .jitter(0.0),
+    );
+    let mut started = start(client, handle);
+
+    wait_for_state(&mut started.state, |x| {
+        matches!(x, ConnectionState::Reconnecting { .. })
+    })
+    .await;
+
+    started.handle.close().await.unwrap();
+
+    wait_for_state(&mut started.state, |x| *x == ConnectionState::Closed).await;
+    assert_eq!(server.handshake_requests().len(), 1);
+}
+
+#[tokio::test]
+async fn with_cancellation_token_closes_client() {
+    let server = MockWsServer::start().await.unwrap();
+    let token = CancellationToken::new();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client.with_cancellation_token(token.clone()), handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    token.cancel();
+
+    wait_for_state(&mut started.state, |x| *x == ConnectionState::Closed).await;
+}
+
+#[tokio::test]
+async fn with_linked_cancellation_token_closes_client() {
+    let server = MockWsServer::start().await.unwrap();
+    let token = CancellationToken::new();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client.with_linked_cancellation_token(token.clone()), handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    token.cancel();
+
+    wait_for_state(&mut started.state, |x| *x == ConnectionState::Closed).await;
+}
+
+#[tokio::test]
+async fn handle_close_works_with_linked_cancellation_token() {
+    let server = MockWsServer::start().await.unwrap();
+    let token = CancellationToken::new();
+    let (client, handle) = WsClient::new(server.url());
+    let mut started = start(client.with_linked_cancellation_token(token.clone()), handle);
+
+    wait_for_connected(&mut started.state).await;
+
+    started.handle.close().await.unwrap();
+
+    wait_for_state(&mut started.state, |x| *x == ConnectionState::Closed).await;
+    assert!(!token.is_cancelled());
+}
@@ -0,0 +1,204 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use moosicbox_app_ws::OrderedDispatcher;
+use tokio::sync::{mpsc, oneshot};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn recv_all<T>(rx: &mut mpsc::UnboundedReceiver<T>, count: usize) -> Vec<T> {
+    let mut values = Vec::with_capacity(count);
+
+    while values.len() < count {
+        let value = tokio::time::timeout(TIMEOUT, rx.recv())
+            .await
+            .expect("Timed out waiting for dispatched jobs")
+            .expect("Job channel closed");
+        values.push(value);
+    }
+
+    values
+}
+
+#[tokio::test]
+async fn jobs_with_the_same_key_run_in_dispatch_order() {
+    let dispatcher = OrderedDispatcher::new(4);
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    for i in 0..50_u64 {
+        let tx = tx.clone();
+        dispatcher.dispatch(1_u64, async move {
+            // Earlier jobs sleep longer, so any reordering would show up
+            tokio::time::sleep(Duration::from_millis((50 - i) / 10)).await;
+            tx.send(i).unwrap();
+        });
+    }
+
+    assert_eq!(recv_all(&mut rx, 50).await, (0..50).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn jobs_are_ordered_per_key() {
+    let dispatcher = OrderedDispatcher::new(4);
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    for i in 0..30_u64 {
+        let key = i % 3;
+        let tx = tx.clone();
+        dispatcher.dispatch(key, async move {
+            tokio::time::sleep(Duration::from_millis((30 - i) / 10)).await;
+            tx.send((key, i)).unwrap();
+        });
+    }
+
+    let applied = recv_all(&mut rx, 30).await;
+
+    for key in 0..3 {
+        let updates = applied
+            .iter()
+            .filter(|(x, _)| *x == key)
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        let expected = (0..30).filter(|i| i % 3 == key).collect::<Vec<_>>();
+        assert_eq!(updates, expected, "key={key}");
+    }
+}
+
+#[tokio::test]
+async fn blocked_key_does_not_hold_up_other_keys() {
+    let dispatcher = OrderedDispatcher::new(2);
+    let (unblock_tx, unblock_rx) = oneshot::channel::<()>();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    dispatcher.dispatch("a", {
+        let tx = tx.clone();
+        async move {
+            unblock_rx.await.unwrap();
+            tx.send("a").unwrap();
+        }
+    });
+    dispatcher.dispatch("b", {
+        let tx = tx.clone();
+        async move {
+            tx.send("b").unwrap();
+        }
+    });
+
+    assert_eq!(recv_all(&mut rx, 1).await, vec!["b"]);
+
+    unblock_tx.send(()).unwrap();
+
+    assert_eq!(recv_all(&mut rx, 1).await, vec!["a"]);
+}
+
+#[tokio::test]
+async fn concurrency_across_keys_is_bounded() {
+    let dispatcher = OrderedDispatcher::new(2);
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(Mutex::new(0));
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    for key in 0..8_u64 {
+        let running = running.clone();
+        let max_running = max_running.clone();
+        let tx = tx.clone();
+
+        dispatcher.dispatch(key, async move {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            {
+                let mut max_running = max_running.lock().unwrap();
+                *max_running = (*max_running).max(now);
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+            tx.send(key).unwrap();
+        });
+    }
+
+    recv_all(&mut rx, 8).await;
+
+    assert_eq!(*max_running.lock().unwrap(), 2);
+}
+
+#[tokio::test]
+async fn panicking_job_does_not_stall_its_key() {
+    let dispatcher = OrderedDispatcher::new(1);
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    dispatcher.dispatch(1_u64, async { panic!("job failed") });
+    dispatcher.dispatch(1_u64, async move {
+        tx.send(()).unwrap();
+    });
+
+    recv_all(&mut rx, 1).await;
+}
+
+#[tokio::test]
+async fn job_with_several_keys_waits_for_each_key_and_holds_up_later_jobs() {
+    let dispatcher = OrderedDispatcher::new(4);
+    let (unblock_tx, unblock_rx) = oneshot::channel::<()>();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let send = |value: &'static str| {
+        let tx = tx.clone();
+        async move {
+            tx.send(value).unwrap();
+        }
+    };
+
+    dispatcher.dispatch(1_u64, {
+        let tx = tx.clone();
+        async move {
+            unblock_rx.await.unwrap();
+            tx.send("1").unwrap();
+        }
+    });
+    dispatcher.dispatch(2, send("2"));
+    dispatcher.dispatch_all([1, 2, 2], send("1 and 2"));
+    dispatcher.dispatch(2, send("2 after"));
+    dispatcher.dispatch(3, send("3"));
+
+    let mut unrelated = recv_all(&mut rx, 2).await;
+    unrelated.sort_unstable();
+    assert_eq!(unrelated, vec!["2", "3"]);
+
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), rx.recv())
+            .await
+            .is_err(),
+        "a job ran before the job with several keys"
+    );
+
+    unblock_tx.send(()).unwrap();
+
+    assert_eq!(recv_all(&mut rx, 3).await, vec!["1", "1 and 2", "2 after"]);
+}
+
+#[tokio::test]
+async fn jobs_with_several_keys_only_hold_a_permit_while_running() {
+    let dispatcher = OrderedDispatcher::new(1);
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    for i in 0..3_u64 {
+        let tx = tx.clone();
+        dispatcher.dispatch_all([i, i + 1, i + 2], async move {
+            tx.send(i).unwrap();
+        });
+    }
+
+    assert_eq!(recv_all(&mut rx, 3).await, vec![0, 1, 2]);
+}
+
+#[tokio::test]
+async fn job_without_keys_runs_right_away() {
+    let dispatcher = OrderedDispatcher::<u64>::new(1);
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    dispatcher.dispatch_all([], async move {
+        tx.send(()).unwrap();
+    });
+
+    recv_all(&mut rx, 1).await;
+    assert_eq!(dispatcher.active_keys(), 0);
+}
@@ -0,0 +1,366 @@
+#![cfg_attr(feature = "fail-on-warnings", deny(warnings))]
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use flate2::{Compress, Compression, FlushCompress};
+use futures_util::{SinkExt as _, StreamExt as _};
+use moosicbox_ws::models::{InboundPayload, OutboundPayload};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::select;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::{
+    accept_hdr_async,
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        http::{header, HeaderValue},
+        protocol::{
+            frame::{
+                coding::{CloseCode, Data, OpCode},
+                Frame,
+            },
+            CloseFrame,
+        },
+        Message,
+    },
+};
+
+pub use tokio_tungstenite::tungstenite::http::StatusCode;
+
+/// A handshake request as seen by the server
+#[derive(Debug, Clone)]
+pub struct HandshakeRequest {
+    /// Path and query string of the request
+    pub uri: String,
+    /// Header values keyed by lowercase header name
+    pub headers: HashMap<String, String>,
+}
+
+impl HandshakeRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+}
+
+type Responder = Box<dyn Fn(&InboundPayload) -> Vec<OutboundPayload> + Send + Sync>;
+
+#[derive(Clone)]
+enum Command {
+    Send(Message),
+    Close(CloseFrame<'static>),
+    Drop,
+}
+
+struct Shared {
+    reject_with: Mutex<Option<StatusCode>>,
+    handshakes: Mutex<Vec<HandshakeRequest>>,
+    connections: Mutex<Vec<mpsc::UnboundedSender<Command>>>,
+    accepted: watch::Sender<usize>,
+    responder: Mutex<Option<Responder>>,
+    unresponsive: watch::Sender<bool>,
+    compression: Mutex<bool>,
+}
+
+impl Shared {
+    fn broadcast(&self, command: Command) {
+        self.connections
+            .lock()
+            .unwrap()
+            .retain(|x| x.send(command.clone()).is_ok());
+    }
+}
+
+/// Scriptable MoosicBox WebSocket server listening on a random local port.
+///
+/// Text messages received from clients are queued for `recv_text`/`recv_inbound`, and anything
+/// sent through the server goes to every open connection. Ping frames are answered
+/// automatically until `stop_reading` is called. The server stops when dropped.
+pub struct MockWsServer {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    inbound: tokio::sync::Mutex<mpsc::UnboundedReceiver<String>>,
+    listener: JoinHandle<()>,
+}
+
+impl MockWsServer {
+    pub async fn start() -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (inbound_tx, inbound_rx) = mpsc::unbounded_channel();
+        let (accepted, _) = watch::channel(0);
+        let (unresponsive, _) = watch::channel(false);
+
+        let shared = Arc::new(Shared {
+            reject_with: Mutex::new(None),
+            handshakes: Mutex::new(vec![]),
+            connections: Mutex::new(vec![]),
+            accepted,
+            responder: Mutex::new(None),
+            unresponsive,
+            compression: Mutex::new(false),
+        });
+
+        let listener = tokio::spawn({
+            let shared = shared.clone();
+
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(handle_connection(
+                        stream,
+                        shared.clone(),
+                        inbound_tx.clone(),
+                    ));
+                }
+            }
+        });
+
+        Ok(Self {
+            addr,
+            shared,
+            inbound: tokio::sync::Mutex::new(inbound_rx),
+            listener,
+        })
+    }
+
+    /// `ws://` URL of the server's `/ws` endpoint
+    pub fn url(&self) -> String {
+        format!("ws://{}/ws", self.addr)
+    }
+
+    /// Fails every following handshake with `status` until `accept_handshakes` is called
+    pub fn reject_handshakes(&self, status: StatusCode) {
+        self.shared.reject_with.lock().unwrap().replace(status);
+    }
+
+    pub fn accept_handshakes(&self) {
+        self.shared.reject_with.lock().unwrap().take();
+    }
+
+    /// Answers every received `InboundPayload` with the payloads returned by `responder`
+    pub fn respond_with(
+        &self,
+        responder: impl Fn(&InboundPayload) -> Vec<OutboundPayload> + Send + Sync + 'static,
+    ) {
+        self.shared
+            .responder
+            .lock()
+            .unwrap()
+            .replace(Box::new(responder));
+    }
+
+    /// Stops reading from open and new connections until `resume_reading` is called, so pings
+    /// go unanswered and client messages are left unread, as with a hung server
+    pub fn stop_reading(&self) {
+        self.shared.unresponsive.send_replace(true);
+    }
+
+    pub fn resume_reading(&self) {
+        self.shared.unresponsive.send_replace(false);
+    }
+
+    /// Accepts permessage-deflate, without context takeover, in following handshakes that offer it
+    pub fn accept_compression(&self) {
+        *self.shared.compression.lock().unwrap() = true;
+    }
+
+    /// Every handshake request received so far, including rejected ones
+    pub fn handshake_requests(&self) -> Vec<HandshakeRequest> {
+        self.shared.handshakes.lock().unwrap().clone()
+    }
+
+    /// Number of connections that completed the handshake so far
+    pub fn connection_count(&self) -> usize {
+        *self.shared.accepted.borrow()
+    }
+
+    /// Returns `false` if fewer than `count` connections were accepted within `timeout`
+    pub async fn wait_for_connections(&self, count: usize, timeout: Duration) -> bool {
+        let mut accepted = self.shared.accepted.subscribe();
+
+        tokio::time::timeout(timeout, accepted.wait_for(|x| *x >= count))
+            .await
+            .is_ok_and(|x| x.is_ok())
+    }
+
+    /// Next text message received from any client
+    pub async fn recv_text(&self, timeout: Duration) -> Option<String> {
+        let mut inbound = self.inbound.lock().await;
+
+        tokio::time::timeout(timeout, inbound.recv())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// Next text message received from any client, parsed as an `InboundPayload`.
+    ///
+    /// # Panics
+    ///
+    /// * If the message is not a valid `InboundPayload`
+    pub async fn recv_inbound(&self, timeout: Duration) -> Option<InboundPayload> {
+        self.recv_text(timeout).await.map(|text| {
+            serde_json::from_str(&text)
+                .unwrap_or_else(|e| panic!("Invalid InboundPayload {text:?}: {e:?}"))
+        })
+    }
+
+    /// Sends `payload` to every open connection
+    pub fn send(&self, payload: &OutboundPayload) {
+        self.send_text(serde_json::to_string(payload).unwrap());
+    }
+
+    pub fn send_text(&self, text: impl Into<String>) {
+        self.shared
+            .broadcast(Command::Send(Message::Text(text.into())));
+    }
+
+    /// Sends `text` as a permessage-deflate compressed message to every open connection. Only
+    /// valid for connections that negotiated compression after `accept_compression`.
+    pub fn send_compressed(&self, text: impl Into<String>) {
+        let text = text.into();
+        let mut payload = Vec::with_capacity(text.len() + 64);
+        Compress::new(Compression::default(), false)
+            .compress_vec(text.as_bytes(), &mut payload, FlushCompress::Sync)
+            .unwrap();
+        payload.truncate(payload.len() - 4);
+
+        let mut frame = Frame::message(payload, OpCode::Data(Data::Text), true);
+        frame.header_mut().rsv1 = true;
+
+        self.shared.broadcast(Command::Send(Message::Frame(frame)));
+    }
+
+    /// Closes every open connection with a close frame
+    pub fn close_connections(&self, code: u16, reason: &str) {
+        self.shared.broadcast(Command::Close(CloseFrame {
+            code: CloseCode::from(code),
+            reason: reason.to_string().into(),
+        }));
+    }
+
+    /// Drops every open connection without a close frame, as if the network went away
+    pub fn drop_connections(&self) {
+        self.shared.broadcast(Command::Drop);
+    }
+}
+
+impl Drop for MockWsServer {
+    fn drop(&mut self) {
+        self.listener.abort();
+        self.drop_connections();
+    }
+}
+
+#[allow(clippy::result_large_err)]
+async fn handle_connection(
+    stream: TcpStream,
+    shared: Arc<Shared>,
+    inbound: mpsc::UnboundedSender<String>,
+) {
+    let callback = |request: &Request, mut response: Response| {
+        shared.handshakes.lock().unwrap().push(HandshakeRequest {
+            uri: request.uri().to_string(),
+            headers: request
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.as_str().to_string(),
+                        value.to_str().unwrap_or_default().to_string(),
+                    )
+                })
+                .collect(),
+        });
+
+        if let Some(status) = *shared.reject_with.lock().unwrap() {
+            let mut response = ErrorResponse::new(Some(format!("Rejected with {status}")));
+            *response.status_mut() = status;
+            return Err(response);
+        }
+
+        let offered = request
+            .headers()
+            .get(header::SEC_WEBSOCKET_EXTENSIONS)
+            .and_then(|x| x.to_str().ok())
+            .is_some_and(|x| x.contains("permessage-deflate"));
+
+        if offered && *shared.compression.lock().unwrap() {
+            response.headers_mut().insert(
+                header::SEC_WEBSOCKET_EXTENSIONS,
+                HeaderValue::from_static("permessage-deflate; server_no_context_takeover"),
+            );
+        }
+
+        Ok(response)
+    };
+
+    let ws_stream = match accept_hdr_async(stream, callback).await {
+        Ok(ws_stream) => ws_stream,
+        Err(e) => {
+            log::debug!("MockWsServer: handshake failed: {e:?}");
+            return;
+        }
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    shared.connections.lock().unwrap().push(tx);
+    shared.accepted.send_modify(|x| *x += 1);
+
+    let (mut write, mut read) = ws_stream.split();
+    let mut unresponsive = shared.unresponsive.subscribe();
+
+    loop {
+        let reading = !*unresponsive.borrow_and_update();
+
+        select! {
+            _ = unresponsive.changed() => {}
+            command = rx.recv() => match command {
+                Some(Command::Send(message)) => {
+                    if write.send(message).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Command::Close(frame)) => {
+                    let _ = write.send(Message::Close(Some(frame))).await;
+                    break;
+                }
+                Some(Command::Drop) | None => break,
+            },
+            message = read.next(), if reading => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let responses = serde_json::from_str::<InboundPayload>(&text)
+                        .ok()
+                        .and_then(|payload| {
+                            shared
+                                .responder
+                                .lock()
+                                .unwrap()
+                                .as_ref()
+                                .map(|responder| responder(&payload))
+                        })
+                        .unwrap_or_default();
+
+                    let _ = inbound.send(text);
+
+                    for response in responses {
+                        let text = serde_json::to_string(&response).unwrap();
+                        if write.send(Message::Text(text)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    log::debug!("MockWsServer: read failed: {e:?}");
+                    break;
+                }
+                None => break,
+            },
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use moosicbox_app_http_cache::CacheHeaders;
+use moosicbox_core::sqlite::models::ApiSource;
+use tauri::http::{
+    header::{CACHE_CONTROL, CONTENT_TYPE},
+    HeaderValue, Request, Response, StatusCode,
+};
+
+use crate::{auth, cache, current_secrets, http, AppCore, TauriPlayerError};
+
+/// Scheme of the URIs album covers are served to the webview from, so image URLs don't contain
+/// credentials
+pub const ART_SCHEME: &str = "moosicbox-art";
+
+/// Name of the cover cache directory inside the app cache dir
+pub const ART_CACHE_DIR: &str = "art";
+
+pub const ART_CACHE_MAX_SIZE: u64 = 200 * 1024 * 1024;
+
+/// Cover size used for the player plugin
+pub const DEFAULT_ART_SIZE: u16 = 300;
+
+/// Requested sizes are rounded up to one of these, so a handful of files are cached per cover
+const ART_SIZES: [u16; 5] = [80, 160, 300, 640, 1280];
+
+/// The `moosicbox-art` URI the webview loads the cover of `album_id` from. Windows and Android webviews only load
+/// custom schemes through `http://<scheme>.localhost`.
+pub fn album_art_uri(album_id: &str, source: ApiSource, size: u16) -> String {
+    let path = format!("albums/{source}/{album_id}/{size}");
+
+    if cfg!(any(windows, target_os = "android")) {
+        format!("http://{ART_SCHEME}.localhost/{path}")
+    } else {
+        format!("{ART_SCHEME}://localhost/{path}")
+    }
+}
+
+struct AlbumArt<'a> {
+    source: &'a str,
+    album_id: &'a str,
+    size: u16,
+}
+
+impl<'a> AlbumArt<'a> {
+    /// `size` is rounded up to one of the cached sizes
+    fn new(source: &'a str, album_id: &'a str, size: u16) -> Self {
+        let size = ART_SIZES
+            .into_iter()
+            .find(|x| *x >= size)
+            .unwrap_or(ART_SIZES[ART_SIZES.len() - 1]);
+
+        Self {
+            source,
+            album_id,
+            size,
+        }
+    }
+
+    /// Parses `/albums/{source}/{album_id}/{size}`
+    fn parse(path: &'a str) -> Option<Self> {
+        let mut segments = path.trim_start_matches('/').split('/');
+
+        let ("albums", Some(source), Some(album_id), Some(size), None) = (
+            segments.next()?,
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        ) else {
+            return None;
+        };
+
+        Some(Self::new(source, album_id, size.parse().ok()?))
+    }
+
+    fn cache_key(&self) -> String {
+        format!("albums/{}/{}/{}", self.source, self.album_id, self.size)
+    }
+}
+
+/// Local path of the cover of `album_id`, fetching it into the cover cache if needed. The player
+/// plugin gets this instead of a `moosicbox-art` URI, which only resolves inside the webview.
+pub(crate) async fn album_art_path(
+    core: &AppCore,
+    album_id: &str,
+    source: ApiSource,
+    size: u16,
+) -> Option<String> {
+    let cache = core.art_cache()?;
+    let source = source.to_string();
+    let art = AlbumArt::new(&source, album_id, size);
+    let key = art.cache_key();
+    let partition = cache::partition(core).await.ok()?;
+
+    if cache.path(&partition, &key).is_none() {
+        if let Err(e) = album_art(core, &art).await {
+            log::error!("art: failed to load cover {key}: {e:?}");
+            return None;
+        }
+    }
+
+    cache
+        .path(&partition, &key)
+        .map(|x| x.display().to_string())
+}
+
+/// Serves a `moosicbox-art` request from the cover cache, fetching the cover from the API on a
+/// miss
+pub(crate) async fn handle(core: &AppCore, request: Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let path = request.uri().path();
+
+    let Some(art) = AlbumArt::parse(path) else {
+        log::debug!("art: invalid path {path}");
+        return response(StatusCode::NOT_FOUND, vec![]);
+    };
+
+    match album_art(core, &art).await {
+        Ok(body) => {
+            let mut response = response(StatusCode::OK, body);
+            let content_type = content_type(response.body());
+            let headers = response.headers_mut();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+            headers.insert(
+                CACHE_CONTROL,
+                HeaderValue::from_static("private, max-age=86400"),
+            );
+            response
+        }
+        Err(e) => {
+            log::error!("art: failed to load {path}: {e:?}");
+            let status = match e {
+                TauriPlayerError::Unauthorized(status) | TauriPlayerError::Http { status, .. } => {
+                    StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY)
+                }
+                TauriPlayerError::ApiUrlNotSet => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::BAD_GATEWAY,
+            };
+            response(status, vec![])
+        }
+    }
+}
+
+async fn album_art(core: &AppCore, art: &AlbumArt<'_>) -> Result<Vec<u8>, TauriPlayerError> {
+    let partition = cache::partition(core).await?;
+    let key = art.cache_key();
+
+    if let Some(cache) = core.art_cache() {
+        let cached = cache::blocking(cache, {
+            let partition = partition.clone();
+            let key = key.clone();
+            move |cache| cache.get(&partition, &key)
+        })
+        .await;
+
+        match cached {
+            Ok(Some(cached)) => return Ok(cached.body),
+            Ok(None) => {}
+            Err(e) => log::error!("art: failed to read cached cover {key}: {e:?}"),
+        }
+    }
+
+    let secrets = current_secrets(core).await;
+
+    let body = match fetch(core, art).await {
+        Err(TauriPlayerError::Unauthorized(_))
+            if auth::refresh_credentials(core, &secrets).await =>
+        {
+            fetch(core, art).await
+        }
+        resp => resp,
+    }?;
+
+    if let Some(cache) = core.art_cache() {
+        let stored = cache::blocking(cache, {
+            let body = body.clone();
+            move |cache| cache.put(&partition, &key, &CacheHeaders::default(), &body)
+        })
+        .await;
+
+        if let Err(e) = stored {
+            log::error!("art: failed to cache cover {}: {e:?}", art.cache_key());
+        }
+    }
+
+    Ok(body)
+}
+
+/// Requests the cover with the current credentials. This request stays in the app, so unlike
+/// the URI given to the webview it carries `clientId` and `signature`, which tunnel servers
+/// route by.
+async fn fetch(core: &AppCore, art: &AlbumArt<'_>) -> Result<Vec<u8>, TauriPlayerError> {
+    let api_url = core
+        .api_url
+        .read()
+        .await
+        .clone()
+        .ok_or(TauriPlayerError::ApiUrlNotSet)?;
+
+    let url = format!(
+        "{api_url}/files/albums/{}/{size}x{size}",
+        art.album_id,
+        size = art.size
+    );
+    log::debug!("art: fetching {url}");
+
+    let secrets = current_secrets(core).await;
+    let client = http::client(core).await?;
+    let mut builder = client.get(url).query(&[("source", art.source)]);
+
+    if let Some(client_id) = &secrets.client_id {
+        builder = builder.query(&[("clientId", client_id)]);
+    }
+    if let Some(signature) = &secrets.signature_token {
+        builder = builder.query(&[("signature", signature)]);
+    }
+    if let Some(api_token) = &secrets.api_token {
+        builder = builder.bearer_auth(api_token);
+    }
+    if let Some(profile) = core.profile.read().await.as_deref() {
+        builder = builder.header("moosicbox-profile", profile);
+    }
+
+    let response = http::send(core, builder).await?;
+    let status = response.status();
+
+    if auth::is_unauthorized_status(status) {
+        return Err(TauriPlayerError::Unauthorized(status.as_u16()));
+    }
+    if !status.is_success() {
+        return Err(TauriPlayerError::Http {
+            status: status.as_u16(),
+            body: response.text().await.unwrap_or_default(),
+        });
+    }
+
+    Ok(response.bytes().await?.to_vec())
+}
+
+fn response(status: StatusCode, body: Vec<u8>) -> Response<Vec<u8>> {
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    response
+}
+
+fn content_type(body: &[u8]) -> &'static str {
+    match body {
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        _ => "image/jpeg",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use moosicbox_app_http_test_utils::{MockResponse, MockServer};
+
+    use super::*;
+
+    #[test]
+    fn parses_album_art_paths() {
+        let art = AlbumArt::parse("/albums/LIBRARY/12/300").unwrap();
+
+        assert_eq!(art.source, "LIBRARY");
+        assert_eq!(art.album_id, "12");
+        assert_eq!(art.size, 300);
+        assert_eq!(art.cache_key(), "albums/LIBRARY/12/300");
+    }
+
+    #[test]
+    fn sizes_are_rounded_up_to_a_cached_size() {
+        assert_eq!(AlbumArt::parse("/albums/LIBRARY/12/1").unwrap().size, 80);
+        assert_eq!(AlbumArt::parse("/albums/LIBRARY/12/161").unwrap().size, 300);
+        assert_eq!(
+            AlbumArt::parse("/albums/LIBRARY/12/5000").unwrap().size,
+            1280
+        );
+    }
+
+    #[test]
+    fn invalid_album_art_paths_are_rejected() {
+        for path in [
+            "",
+            "/albums/LIBRARY/12",
+            "/albums/LIBRARY/12/300/extra",
+            "/artists/LIBRARY/12/300",
+            "/albums/LIBRARY/12/large",
+            "/albums/LIBRARY/12/70000",
+        ] {
+            assert!(AlbumArt::parse(path).is_none(), "{path}");
+        }
+    }
+
+    #[test]
+    fn content_type_is_sniffed_from_the_body() {
+        assert_eq!(content_type(b"\x89PNG\r\n\x1a\n"), "image/png");
+        assert_eq!(content_type(b"GIF89a"), "image/gif");
+        assert_eq!(content_type(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(content_type(b"RIFF\0\0\0\0WAVE"), "image/jpeg");
+        assert_eq!(content_type(&[0xff, 0xd8, 0xff]), "image/jpeg");
+        assert_eq!(content_type(&[]), "image/jpeg");
+    }
+
+    #[test]
+    fn covers_are_fetched_with_credentials() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            server.route("GET", "/files/albums/12/300x300", |_| {
+                MockResponse::status(200, "GIF89a")
+            });
+            let core = AppCore::new();
+            core.api_url.write().await.replace(server.url());
+            core.profile.write().await.replace("master".to_string());
+            core.client_id.write().await.replace("client".to_string());
+            core.api_token.write().await.replace("token".to_string());
+            core.signature_token
+                .write()
+                .await
+                .replace("signature".to_string());
+
+            let art = AlbumArt::new("LIBRARY", "12", 300);
+            assert_eq!(album_art(&core, &art).await.unwrap(), b"GIF89a");
+
+            let request = &server.requests()[0];
+            assert_eq!(request.query("source"), Some("LIBRARY"));
+            assert_eq!(request.query("clientId"), Some("client"));
+            assert_eq!(request.query("signature"), Some("signature"));
+            assert_eq!(request.header("authorization"), Some("Bearer token"));
+            assert_eq!(request.header("moosicbox-profile"), Some("master"));
+        });
+    }
+}
@@ -0,0 +1,313 @@
+use moosicbox_app_logging::register_secret;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    current_secrets, http, init_ws_connection, persist_settings, sync_active_connection, AppCore,
+    ConnectionSecrets, TauriPlayerError,
+};
+
+#[derive(Debug, Error)]
+pub enum RefreshError {
+    #[error("API_URL not set")]
+    MissingApiUrl,
+    #[error("Missing client id or API token")]
+    MissingCredentials,
+    #[error("Refreshed credentials were rejected too")]
+    RefreshedCredentialsRejected,
+    #[error("Server rejected the refresh with status {0}")]
+    Rejected(StatusCode),
+    #[error(transparent)]
+    Reqwest(#[from] reqwest::Error),
+    #[error(transparent)]
+    TauriPlayer(#[from] TauriPlayerError),
+}
+
+/// Payload of the `auth-required` event, emitted when the server rejects the credentials and
+/// they can't be refreshed
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthRequired {
+    pub api_url: Option<String>,
+    pub connection_name: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+struct SignatureTokenResponse {
+    token: String,
+}
+
+pub fn is_unauthorized_status(status: StatusCode) -> bool {
+    matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN)
+}
+
+/// The 401 or 403 status of `error`, or of any error that caused it
+pub fn unauthorized_status(error: &(dyn std::error::Error + 'static)) -> Option<StatusCode> {
+    let mut error = Some(error);
+
+    while let Some(e) = error {
+        if let Some(status) = e
+            .downcast_ref::<reqwest::Error>()
+            .and_then(reqwest::Error::status)
+        {
+            if is_unauthorized_status(status) {
+                return Some(status);
+            }
+        }
+        error = e.source();
+    }
+
+    None
+}
+
+/// Handles the server rejecting the `rejected` credentials by requesting a new signature token.
+///
+/// Returns whether the request that was rejected should be replayed. On success the new token
+/// is used by the next API request and the WS reconnects with it. Players keep playing and are
+/// rebuilt only when their own stream is rejected. Emits `auth-required` when the credentials
+/// can't be refreshed.
+pub(crate) async fn refresh_credentials(core: &AppCore, rejected: &ConnectionSecrets) -> bool {
+    let mut last_refreshed = core.auth_refresh.lock().await;
+
+    if current_secrets(core).await != *rejected {
+        log::debug!("refresh_credentials: credentials changed since the request was made");
+        return true;
+    }
+
+    let result = if last_refreshed.as_ref() == Some(rejected) {
+        Err(RefreshError::RefreshedCredentialsRejected)
+    } else {
+        refresh_signature_token(core, rejected).await
+    };
+
+    match result {
+        Ok(()) => {
+            log::debug!("refresh_credentials: refreshed signature token");
+            last_refreshed.replace(current_secrets(core).await);
+            drop(last_refreshed);
+
+            sync_active_connection(core).await;
+            persist_settings(core).await;
+
+            if let Err(e) = init_ws_connection(core).await {
+                log::error!("Failed to reconnect the ws after refreshing credentials: {e:?}");
+            }
+
+            true
+        }
+        Err(e) => {
+            log::error!("Failed to refresh credentials: {e:?}");
+
+            let payload = AuthRequired {
+                api_url: core.api_url.read().await.clone(),
+                connection_name: core.connection_name.read().await.clone(),
+                reason: e.to_string(),
+            };
+
+            if let Err(e) = core.emit("auth-required", payload) {
+                log::error!("Failed to emit auth-required: {e:?}");
+            }
+
+            false
+        }
+    }
+}
+
+async fn refresh_signature_token(
+    core: &AppCore,
+    rejected: &ConnectionSecrets,
+) -> Result<(), RefreshError> {
+    let api_url = core
+        .api_url
+        .read()
+        .await
+        .clone()
+        .ok_or(RefreshError::MissingApiUrl)?;
+
+    let (Some(client_id), Some(api_token)) = (&rejected.client_id, &rejected.api_token) else {
+        return Err(RefreshError::MissingCredentials);
+    };
+
+    log::debug!("refresh_signature_token: requesting new signature token");
+
+    let builder = http::client(core)
+        .await?
+        .post(format!("{api_url}/auth/signature-token"))
+        .query(&[("clientId", client_id)])
+        .header("Authorization", format!("bearer {api_token}"));
+    let response = http::send(core, builder).await?;
+
+    if !response.status().is_success() {
+        return Err(RefreshError::Rejected(response.status()));
+    }
+
+    let SignatureTokenResponse { token } = response.json().await?;
+    register_secret(&token);
+
+    core.signature_token.write().await.replace(token);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use moosicbox_app_http_test_utils::{MockResponse, MockServer};
+    use serde_json::json;
+
+    use super::*;
+
+    const SIGNATURE_TOKEN_PATH: &str = "/auth/signature-token";
+
+    async fn connected_core(server: &MockServer) -> AppCore {
+        let core = AppCore::new();
+        core.api_url.write().await.replace(server.url());
+        core.client_id.write().await.replace("client".to_string());
+        core.api_token.write().await.replace("token".to_string());
+        core.signature_token
+            .write()
+            .await
+            .replace("old".to_string());
+        core
+    }
+
+    fn route_signature_token(server: &MockServer, status: u16) {
+        server.route("POST", SIGNATURE_TOKEN_PATH, move |_| {
+            MockResponse::status(status, &json!({"token": "new"}).to_string())
+        });
+    }
+
+    fn requests_to(server: &MockServer, path: &str) -> usize {
+        server.requests().iter().filter(|x| x.path == path).count()
+    }
+
+    fn auth_required(core: &AppCore) -> Vec<serde_json::Value> {
+        core.emitted
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(event, _)| event == "auth-required")
+            .map(|(_, payload)| payload.clone())
+            .collect()
+    }
+
+    #[test]
+    fn refresh_requests_new_signature_token() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            route_signature_token(&server, 200);
+            let core = connected_core(&server).await;
+            let rejected = current_secrets(&core).await;
+
+            assert!(refresh_credentials(&core, &rejected).await);
+
+            assert_eq!(core.signature_token.read().await.as_deref(), Some("new"));
+            let request = &server.requests()[0];
+            assert_eq!(request.query("clientId"), Some("client"));
+            assert_eq!(request.header("authorization"), Some("bearer token"));
+            assert!(auth_required(&core).is_empty());
+        });
+    }
+
+    #[test]
+    fn concurrent_refreshes_request_one_token() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            route_signature_token(&server, 200);
+            let core = connected_core(&server).await;
+            let rejected = current_secrets(&core).await;
+
+            let (first, second) = tokio::join!(
+                refresh_credentials(&core, &rejected),
+                refresh_credentials(&core, &rejected),
+            );
+
+            assert!(first);
+            assert!(second);
+            assert_eq!(requests_to(&server, SIGNATURE_TOKEN_PATH), 1);
+        });
+    }
+
+    #[test]
+    fn failed_refresh_emits_auth_required() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            route_signature_token(&server, 401);
+            let core = connected_core(&server).await;
+            core.connection_name
+                .write()
+                .await
+                .replace("Home".to_string());
+            let rejected = current_secrets(&core).await;
+
+            assert!(!refresh_credentials(&core, &rejected).await);
+
+            assert_eq!(core.signature_token.read().await.as_deref(), Some("old"));
+            let events = auth_required(&core);
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0]["apiUrl"], json!(server.url()));
+            assert_eq!(events[0]["connectionName"], json!("Home"));
+        });
+    }
+
+    #[test]
+    fn rejected_refreshed_credentials_are_not_refreshed_again() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            route_signature_token(&server, 200);
+            let core = connected_core(&server).await;
+
+            let rejected = current_secrets(&core).await;
+            assert!(refresh_credentials(&core, &rejected).await);
+
+            let refreshed = current_secrets(&core).await;
+            assert!(!refresh_credentials(&core, &refreshed).await);
+
+            assert_eq!(requests_to(&server, SIGNATURE_TOKEN_PATH), 1);
+            assert_eq!(auth_required(&core).len(), 1);
+        });
+    }
+
+    #[test]
+    fn rejected_request_is_replayed_after_refresh() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            route_signature_token(&server, 200);
+            let calls = AtomicUsize::new(0);
+            server.route("GET", "/albums", move |_| {
+                if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    MockResponse::status(401, "")
+                } else {
+                    MockResponse::json(&json!({"items": []}))
+                }
+            });
+            let core = connected_core(&server).await;
+
+            let response = crate::api_get(&core, "albums".to_string(), None).await;
+
+            assert_eq!(response.unwrap(), json!({"items": []}));
+            assert_eq!(requests_to(&server, "/albums"), 2);
+            assert_eq!(requests_to(&server, SIGNATURE_TOKEN_PATH), 1);
+        });
+    }
+
+    #[test]
+    fn rejected_request_is_replayed_only_once() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            route_signature_token(&server, 200);
+            server.route("GET", "/albums", |_| MockResponse::status(401, ""));
+            let core = connected_core(&server).await;
+
+            let response = crate::api_get(&core, "albums".to_string(), None).await;
+
+            assert!(matches!(response, Err(TauriPlayerError::Unauthorized(401))));
+            assert_eq!(requests_to(&server, "/albums"), 2);
+            assert_eq!(requests_to(&server, SIGNATURE_TOKEN_PATH), 1);
+            assert!(auth_required(&core).is_empty());
+        });
+    }
+}
@@ -0,0 +1,293 @@
+use std::{path::PathBuf, sync::Arc, time::SystemTime};
+
+use moosicbox_app_http_cache::{CacheHeaders, CachedResponse, HttpCache, HttpCacheError};
+use reqwest::{
+    header::{
+        HeaderMap, HeaderName, CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+    },
+    Response, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_get, auth, current_secrets, http, json_headers, with_proxy_headers, AppCore,
+    TauriPlayerError,
+};
+
+/// Name of the cache directory inside the app cache dir
+pub const HTTP_CACHE_DIR: &str = "http";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HttpCacheMode {
+    /// Revalidates stale responses before returning them. The cached copy is only used when the
+    /// server can't be reached.
+    #[default]
+    Network,
+    /// Returns cached copies right away and revalidates stale ones in the background
+    StaleWhileRevalidate,
+    /// Only returns cached copies, without contacting the server
+    Offline,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HttpCacheConfig {
+    pub enabled: bool,
+    pub max_size_bytes: u64,
+    pub mode: HttpCacheMode,
+}
+
+impl Default for HttpCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_size_bytes: 100 * 1024 * 1024,
+            mode: HttpCacheMode::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedProxyResponse {
+    pub body: serde_json::Value,
+    /// The body is a cached copy that the server hasn't confirmed is still current
+    pub stale: bool,
+    /// Unix time in seconds when a cached body was fetched or last revalidated
+    pub cached_at: Option<u64>,
+}
+
+impl CachedProxyResponse {
+    fn cached(cached: &CachedResponse, stale: bool) -> Result<Self, TauriPlayerError> {
+        Ok(Self {
+            body: serde_json::from_slice(&cached.body)?,
+            stale,
+            cached_at: Some(cached.entry.stored_at),
+        })
+    }
+}
+
+/// GETs `url` from the API through the HTTP cache, following the configured `HttpCacheMode`
+pub(crate) async fn get(
+    core: &AppCore,
+    url: String,
+    headers: Option<serde_json::Value>,
+) -> Result<CachedProxyResponse, TauriPlayerError> {
+    let config = core.http_cache_config.read().await.clone();
+
+    let (Some(cache), true) = (core.http_cache(), config.enabled) else {
+        return Ok(CachedProxyResponse {
+            body: api_get(core, url, headers).await?,
+            stale: false,
+            cached_at: None,
+        });
+    };
+
+    let partition = partition(core).await?;
+    let cached = blocking(cache, {
+        let partition = partition.clone();
+        let url = url.clone();
+        move |cache| cache.get(&partition, &url)
+    })
+    .await
+    .unwrap_or_else(|e| {
+        log::error!("Failed to read cached response for {url}: {e:?}");
+        None
+    });
+    let fresh = cached
+        .as_ref()
+        .is_some_and(|x| x.entry.is_fresh(SystemTime::now()));
+
+    match (config.mode, cached) {
+        (HttpCacheMode::Offline, None) => Err(TauriPlayerError::NotCached),
+        (HttpCacheMode::Offline, Some(cached)) => CachedProxyResponse::cached(&cached, !fresh),
+        (_, Some(cached)) if fresh => CachedProxyResponse::cached(&cached, false),
+        (HttpCacheMode::StaleWhileRevalidate, Some(cached)) => {
+            let response = CachedProxyResponse::cached(&cached, true)?;
+
+            moosicbox_task::spawn("api_proxy_get: revalidate", {
+                let core = core.clone();
+                async move {
+                    if let Err(e) =
+                        fetch(&core, &partition, &url, headers.as_ref(), Some(&cached)).await
+                    {
+                        log::debug!("Failed to revalidate {url}: {e:?}");
+                    }
+                }
+            });
+
+            Ok(response)
+        }
+        (HttpCacheMode::Network | HttpCacheMode::StaleWhileRevalidate, cached) => {
+            match fetch(core, &partition, &url, headers.as_ref(), cached.as_ref()).await {
+                Err(e) if e.retryable() => {
+                    let Some(cached) = cached else {
+                        return Err(e);
+                    };
+                    log::warn!("Serving stale cached response for {url}: {e:?}");
+                    CachedProxyResponse::cached(&cached, true)
+                }
+                resp => resp,
+            }
+        }
+    }
+}
+
+/// Requests `url`, conditionally if there is a `cached` copy, and stores the response
+async fn fetch(
+    core: &AppCore,
+    partition: &str,
+    url: &str,
+    headers: Option<&serde_json::Value>,
+    cached: Option<&CachedResponse>,
+) -> Result<CachedProxyResponse, TauriPlayerError> {
+    let secrets = current_secrets(core).await;
+
+    let response = match send(core, url, headers, cached).await {
+        Err(TauriPlayerError::Unauthorized(_))
+            if auth::refresh_credentials(core, &secrets).await =>
+        {
+            send(core, url, headers, cached).await
+        }
+        resp => resp,
+    }?;
+
+    let cache_headers = cache_headers(response.headers());
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        let Some(cached) = cached else {
+            return Err(TauriPlayerError::InvalidResponse(
+                "Not Modified without a cached response".to_string(),
+            ));
+        };
+
+        let revalidated = match core.http_cache() {
+            Some(cache) => blocking(cache, {
+                let partition = partition.to_string();
+                let url = url.to_string();
+                move |cache| cache.revalidated(&partition, &url, &cache_headers)
+            })
+            .await
+            .unwrap_or_else(|e| {
+                log::error!("Failed to update cached response for {url}: {e:?}");
+                None
+            }),
+            None => None,
+        };
+
+        return CachedProxyResponse::cached(revalidated.as_ref().unwrap_or(cached), false);
+    }
+
+    let bytes = response.bytes().await?;
+    let body = serde_json::from_slice(&bytes)?;
+
+    if let Some(cache) = core.http_cache() {
+        let stored = blocking(cache, {
+            let partition = partition.to_string();
+            let url = url.to_string();
+            move |cache| cache.put(&partition, &url, &cache_headers, &bytes)
+        })
+        .await;
+
+        if let Err(e) = stored {
+            log::error!("Failed to cache response for {url}: {e:?}");
+        }
+    }
+
+    Ok(CachedProxyResponse {
+        body,
+        stale: false,
+        cached_at: None,
+    })
+}
+
+async fn send(
+    core: &AppCore,
+    url: &str,
+    headers: Option<&serde_json::Value>,
+    cached: Option<&CachedResponse>,
+) -> Result<Response, TauriPlayerError> {
+    let api_url = core
+        .api_url
+        .read()
+        .await
+        .clone()
+        .ok_or(TauriPlayerError::ApiUrlNotSet)?;
+
+    let client = http::client(core).await?;
+    let mut builder = with_proxy_headers(
+        core,
+        client.get(format!("{api_url}/{url}")),
+        json_headers(headers),
+    )
+    .await;
+
+    if let Some(cached) = cached {
+        if let Some(etag) = &cached.entry.etag {
+            builder = builder.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.entry.last_modified {
+            builder = builder.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = http::send(core, builder).await?;
+    let status = response.status();
+
+    if status.is_success() || status == StatusCode::NOT_MODIFIED {
+        return Ok(response);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    log::error!("Failure response: status={status} ({body:?})");
+
+    if auth::is_unauthorized_status(status) {
+        return Err(TauriPlayerError::Unauthorized(status.as_u16()));
+    }
+
+    Err(TauriPlayerError::Http {
+        status: status.as_u16(),
+        body,
+    })
+}
+
+/// Opens the HTTP cache in `dir` with the configured size cap. Called once the settings are
+/// restored, so that a larger configured cache isn't evicted down to the default size.
+pub(crate) async fn open(core: &AppCore, dir: PathBuf) {
+    let max_size = core.http_cache_config.read().await.max_size_bytes;
+
+    match tokio::task::spawn_blocking(move || HttpCache::open(dir, max_size)).await {
+        Ok(Ok(cache)) => core.set_http_cache(cache),
+        Ok(Err(e)) => log::error!("Failed to open HTTP cache: {e:?}"),
+        Err(e) => log::error!("Failed to open HTTP cache: {e:?}"),
+    }
+}
+
+/// Runs `f` on the blocking thread pool, since the cache reads and writes files while holding
+/// its lock
+pub(crate) async fn blocking<T: Send + 'static>(
+    cache: Arc<HttpCache>,
+    f: impl FnOnce(&HttpCache) -> Result<T, HttpCacheError> + Send + 'static,
+) -> Result<T, TauriPlayerError> {
+    Ok(tokio::task::spawn_blocking(move || f(&cache)).await??)
+}
+
+/// Responses are kept separately for each server and profile
+pub(crate) async fn partition(core: &AppCore) -> Result<String, TauriPlayerError> {
+    let api_url = core
+        .api_url
+        .read()
+        .await
+        .clone()
+        .ok_or(TauriPlayerError::ApiUrlNotSet)?;
+    let profile = core.profile.read().await.clone().unwrap_or_default();
+
+    Ok(format!("{api_url}|{profile}"))
+}
+
+fn cache_headers(headers: &HeaderMap) -> CacheHeaders {
+    let value = |name: HeaderName| headers.get(name).and_then(|x| x.to_str().ok());
+
+    CacheHeaders::new(value(ETAG), value(LAST_MODIFIED), value(CACHE_CONTROL))
+}
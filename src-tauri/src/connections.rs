@@ -0,0 +1,175 @@
+use moosicbox_app_tls::TlsConfig;
+use moosicbox_session::models::PlaybackTarget;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{credentials::ConnectionSecrets, AppState};
+
+#[derive(Debug, Error)]
+pub enum ConnectionError {
+    #[error("Connection {0} not found")]
+    NotFound(u64),
+}
+
+/// A server connection saved on this device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedConnection {
+    pub id: u64,
+    pub name: String,
+    pub api_url: String,
+    /// Connection id the server assigned to this device
+    pub connection_id: Option<String>,
+    /// Kept in the credential store and never serialized
+    #[serde(default, skip_serializing)]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub signature_token: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub api_token: Option<String>,
+    pub profile: Option<String>,
+    pub playback_target: Option<PlaybackTarget>,
+    pub tls: Option<TlsConfig>,
+    /// Retry the WS handshake with the signature in the query string when the server rejects
+    /// the handshake headers. Only for servers that can't read them.
+    #[serde(default)]
+    pub ws_query_auth: bool,
+}
+
+impl SavedConnection {
+    pub fn secrets(&self) -> ConnectionSecrets {
+        ConnectionSecrets {
+            client_id: self.client_id.clone(),
+            signature_token: self.signature_token.clone(),
+            api_token: self.api_token.clone(),
+        }
+    }
+
+    pub fn set_secrets(&mut self, secrets: ConnectionSecrets) {
+        self.client_id = secrets.client_id;
+        self.signature_token = secrets.signature_token;
+        self.api_token = secrets.api_token;
+    }
+}
+
+/// A connection to save. The registry assigns its id.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewConnection {
+    pub name: String,
+    pub api_url: String,
+    pub connection_id: Option<String>,
+    pub client_id: Option<String>,
+    pub signature_token: Option<String>,
+    pub api_token: Option<String>,
+    pub profile: Option<String>,
+    pub playback_target: Option<PlaybackTarget>,
+    pub tls: Option<TlsConfig>,
+    #[serde(default)]
+    pub ws_query_auth: bool,
+}
+
+impl From<&SavedConnection> for AppState {
+    fn from(value: &SavedConnection) -> Self {
+        Self {
+            connection_id: value.connection_id.clone(),
+            connection_name: Some(value.name.clone()),
+            api_url: Some(value.api_url.clone()),
+            client_id: value.client_id.clone(),
+            signature_token: value.signature_token.clone(),
+            api_token: value.api_token.clone(),
+            profile: value.profile.clone(),
+            playback_target: value.playback_target.clone(),
+            current_session_id: None,
+            tls: value.tls.clone(),
+            ws_query_auth: value.ws_query_auth,
+        }
+    }
+}
+
+/// The saved connections and which of them is active
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionRegistry {
+    connections: Vec<SavedConnection>,
+    active_id: Option<u64>,
+}
+
+impl ConnectionRegistry {
+    pub fn list(&self) -> &[SavedConnection] {
+        &self.connections
+    }
+
+    pub fn list_mut(&mut self) -> &mut [SavedConnection] {
+        &mut self.connections
+    }
+
+    pub fn active(&self) -> Option<&SavedConnection> {
+        self.active_id.and_then(|id| self.get(id))
+    }
+
+    pub fn active_mut(&mut self) -> Option<&mut SavedConnection> {
+        let id = self.active_id?;
+        self.connections.iter_mut().find(|x| x.id == id)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&SavedConnection> {
+        self.connections.iter().find(|x| x.id == id)
+    }
+
+    pub fn add(&mut self, connection: NewConnection) -> SavedConnection {
+        let id = self.connections.iter().map(|x| x.id).max().unwrap_or(0) + 1;
+
+        let connection = SavedConnection {
+            id,
+            name: connection.name,
+            api_url: connection.api_url,
+            connection_id: connection.connection_id,
+            client_id: connection.client_id,
+            signature_token: connection.signature_token,
+            api_token: connection.api_token,
+            profile: connection.profile,
+            playback_target: connection.playback_target,
+            tls: connection.tls,
+            ws_query_auth: connection.ws_query_auth,
+        };
+
+        self.connections.push(connection.clone());
+
+        connection
+    }
+
+    pub fn rename(&mut self, id: u64, name: String) -> Result<SavedConnection, ConnectionError> {
+        let connection = self
+            .connections
+            .iter_mut()
+            .find(|x| x.id == id)
+            .ok_or(ConnectionError::NotFound(id))?;
+
+        connection.name = name;
+
+        Ok(connection.clone())
+    }
+
+    /// Removes the connection, deactivating it first if it is the active one
+    pub fn remove(&mut self, id: u64) -> Result<SavedConnection, ConnectionError> {
+        let index = self
+            .connections
+            .iter()
+            .position(|x| x.id == id)
+            .ok_or(ConnectionError::NotFound(id))?;
+
+        if self.active_id == Some(id) {
+            self.active_id = None;
+        }
+
+        Ok(self.connections.remove(index))
+    }
+
+    pub fn activate(&mut self, id: u64) -> Result<SavedConnection, ConnectionError> {
+        let connection = self.get(id).ok_or(ConnectionError::NotFound(id))?.clone();
+        self.active_id = Some(id);
+
+        Ok(connection)
+    }
+}
@@ -0,0 +1,66 @@
+use moosicbox_app_credentials::{CredentialError, CredentialStore};
+use serde::Serialize;
+
+/// Scope of the secrets belonging to the current connection state
+pub const CURRENT_SCOPE: &str = "current";
+
+/// Scope of the secrets belonging to the saved connection `id`
+pub fn connection_scope(id: u64) -> String {
+    format!("connection.{id}")
+}
+
+/// The tokens of a connection. These are only ever written to the credential store, never to the
+/// settings file or back to the webview.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ConnectionSecrets {
+    pub client_id: Option<String>,
+    pub signature_token: Option<String>,
+    pub api_token: Option<String>,
+}
+
+impl ConnectionSecrets {
+    pub fn load(store: &CredentialStore, scope: &str) -> Result<Self, CredentialError> {
+        Ok(Self {
+            client_id: store.get(&key(scope, "client_id"))?,
+            signature_token: store.get(&key(scope, "signature_token"))?,
+            api_token: store.get(&key(scope, "api_token"))?,
+        })
+    }
+
+    /// Stores the secrets under `scope`, deleting the ones that are `None`
+    pub fn save(&self, store: &CredentialStore, scope: &str) -> Result<(), CredentialError> {
+        store.set_optional(&key(scope, "client_id"), self.client_id.as_deref())?;
+        store.set_optional(
+            &key(scope, "signature_token"),
+            self.signature_token.as_deref(),
+        )?;
+        store.set_optional(&key(scope, "api_token"), self.api_token.as_deref())?;
+
+        Ok(())
+    }
+
+    pub fn delete(store: &CredentialStore, scope: &str) -> Result<(), CredentialError> {
+        Self::default().save(store, scope)
+    }
+
+    pub fn status(&self) -> CredentialStatus {
+        CredentialStatus {
+            client_id: self.client_id.is_some(),
+            signature_token: self.signature_token.is_some(),
+            api_token: self.api_token.is_some(),
+        }
+    }
+}
+
+/// Which secrets are set, without exposing their values
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialStatus {
+    pub client_id: bool,
+    pub signature_token: bool,
+    pub api_token: bool,
+}
+
+fn key(scope: &str, name: &str) -> String {
+    format!("{scope}.{name}")
+}
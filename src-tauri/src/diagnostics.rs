@@ -0,0 +1,303 @@
+use std::{
+    fs::File,
+    io::{BufRead as _, BufReader, Write as _},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use moosicbox_app_logging::{log_files, redact, rotate, LogRotation};
+use moosicbox_audio_zone::models::{ApiAudioZoneWithSession, ApiPlayer};
+use moosicbox_session::models::{ApiPlaybackTarget, ApiSession};
+use serde::Serialize;
+use tauri::AppHandle;
+use tauri_plugin_dialog::DialogExt as _;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{mdns, AppCore, AppState, TauriPlayerError, WsStatusMessage};
+
+/// Name of the log file `moosicbox_logging` writes on desktop
+pub const LOG_FILENAME: &str = "moosicbox_app.log";
+
+pub const LOG_ROTATION: LogRotation = LogRotation {
+    max_size: 10 * 1024 * 1024,
+    max_files: 5,
+};
+
+const LOG_ROTATION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How many WS status changes are kept for diagnostics
+const WS_HISTORY_LEN: usize = 100;
+
+#[derive(Debug, Error)]
+pub enum DiagnosticsError {
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Zip(#[from] zip::result::ZipError),
+}
+
+/// Where `moosicbox_logging` writes `LOG_FILENAME`
+pub fn log_path() -> Option<PathBuf> {
+    moosicbox_config::get_config_dir_path().map(|x| x.join("logs").join(LOG_FILENAME))
+}
+
+/// Rotates the log file if it grew past `LOG_ROTATION.max_size`
+pub fn rotate_logs() {
+    let Some(path) = log_path() else {
+        return;
+    };
+
+    match rotate(&path, &LOG_ROTATION) {
+        Ok(true) => log::debug!("rotate_logs: rotated {}", path.display()),
+        Ok(false) => {}
+        Err(e) => log::error!("Failed to rotate {}: {e:?}", path.display()),
+    }
+}
+
+/// Checks the log file size every `LOG_ROTATION_INTERVAL`
+pub fn spawn_log_rotation(handle: &tokio::runtime::Handle) -> JoinHandle<()> {
+    moosicbox_task::spawn_on("diagnostics: log rotation", handle, async move {
+        let mut interval = tokio::time::interval(LOG_ROTATION_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = tokio::task::spawn_blocking(rotate_logs).await {
+                log::error!("Log rotation task failed: {e:?}");
+            }
+        }
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WsHistoryEntry {
+    /// Milliseconds since the Unix epoch
+    pub at: u64,
+    pub ws_url: Option<String>,
+    pub status: WsStatusMessage,
+}
+
+/// Appends `status` to the WS connection history, dropping the oldest entries past
+/// `WS_HISTORY_LEN`
+pub(crate) async fn record_ws_status(core: &AppCore, status: &WsStatusMessage) {
+    let entry = WsHistoryEntry {
+        at: now_ms(),
+        ws_url: core.ws_url.read().await.clone(),
+        status: status.clone(),
+    };
+
+    let mut history = core.ws_history.write().await;
+    if history.len() >= WS_HISTORY_LEN {
+        history.pop_front();
+    }
+    history.push_back(entry);
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    version: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    created_at: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ActivePlayerSummary {
+    session_id: u64,
+    playback_target: ApiPlaybackTarget,
+    player_type: String,
+    playing: Option<bool>,
+    position: Option<u16>,
+    progress: Option<f64>,
+    track_count: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CurrentPlayerSummary {
+    player: ApiPlayer,
+    player_type: String,
+    audio_output_id: String,
+    audio_output_name: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UpnpDeviceSummary {
+    device: String,
+    service: String,
+}
+
+/// Writes a zip of the recent logs and the connection, player, session and discovery state to a
+/// path chosen in a save dialog. Returns the path written to, or `None` if the dialog was
+/// cancelled.
+#[tauri::command]
+pub async fn export_diagnostics(
+    app: AppHandle,
+    core: tauri::State<'_, AppCore>,
+) -> Result<Option<String>, TauriPlayerError> {
+    let Some(path) = pick_path(&app).await else {
+        log::debug!("export_diagnostics: cancelled");
+        return Ok(None);
+    };
+
+    log::debug!("export_diagnostics: writing {}", path.display());
+
+    let entries = collect(&core).await?;
+
+    tokio::task::spawn_blocking({
+        let path = path.clone();
+        move || write_bundle(&path, &entries)
+    })
+    .await??;
+
+    Ok(Some(path.display().to_string()))
+}
+
+async fn pick_path(app: &AppHandle) -> Option<PathBuf> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    app.dialog()
+        .file()
+        .set_file_name(format!("moosicbox-diagnostics-{}.zip", now_ms() / 1000))
+        .add_filter("Zip", &["zip"])
+        .save_file(move |path| {
+            let _ = tx.send(path);
+        });
+
+    rx.await
+        .ok()
+        .flatten()
+        .and_then(|x| x.as_path().map(Path::to_path_buf))
+}
+
+/// The JSON files of the bundle as `(name, contents)`
+async fn collect(core: &AppCore) -> Result<Vec<(&'static str, Vec<u8>)>, DiagnosticsError> {
+    let manifest = Manifest {
+        version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        created_at: now_ms(),
+    };
+
+    // Tokens are never serialized, and `redact` masks anything secret left in e.g. the API URL
+    let state = AppState {
+        connection_id: core.connection_id.read().await.clone(),
+        connection_name: core.connection_name.read().await.clone(),
+        api_url: core.api_url.read().await.clone(),
+        client_id: None,
+        signature_token: None,
+        api_token: None,
+        profile: core.profile.read().await.clone(),
+        playback_target: core.current_playback_target.read().await.clone(),
+        current_session_id: *core.current_session_id.read().await,
+        tls: core.tls_config.read().await.clone(),
+        ws_query_auth: *core.ws_query_auth.read().await,
+    };
+
+    let active_players = core
+        .active_players
+        .read()
+        .await
+        .iter()
+        .map(|player| {
+            let playback = player.player.playback.read().unwrap().clone();
+            ActivePlayerSummary {
+                session_id: player.session_id,
+                playback_target: player.playback_target.clone(),
+                player_type: format!("{:?}", player.player_type),
+                playing: playback.as_ref().map(|x| x.playing),
+                position: playback.as_ref().map(|x| x.position),
+                progress: playback.as_ref().map(|x| x.progress),
+                track_count: playback.as_ref().map(|x| x.tracks.len()),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let current_players = core
+        .current_players
+        .read()
+        .await
+        .iter()
+        .map(|(player, player_type, output)| CurrentPlayerSummary {
+            player: player.clone(),
+            player_type: format!("{player_type:?}"),
+            audio_output_id: output.id.clone(),
+            audio_output_name: output.name.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    let audio_zones: Vec<ApiAudioZoneWithSession> = core.current_audio_zones.read().await.clone();
+    let sessions: Vec<ApiSession> = core.current_sessions.read().await.clone();
+
+    let upnp_devices = core
+        .upnp_av_transport_services
+        .read()
+        .await
+        .iter()
+        .map(|x| UpnpDeviceSummary {
+            device: format!("{:?}", x.device),
+            service: format!("{:?}", x.service),
+        })
+        .collect::<Vec<_>>();
+
+    Ok(vec![
+        ("manifest.json", to_json(&manifest)?),
+        ("state.json", to_json(&state)?),
+        ("active_players.json", to_json(&active_players)?),
+        ("current_players.json", to_json(&current_players)?),
+        ("audio_zones.json", to_json(&audio_zones)?),
+        ("sessions.json", to_json(&sessions)?),
+        ("mdns_servers.json", to_json(&mdns::servers(core).await)?),
+        ("upnp_devices.json", to_json(&upnp_devices)?),
+        ("ws_history.json", to_json(&*core.ws_history.read().await)?),
+    ])
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>, serde_json::Error> {
+    let json = serde_json::to_string_pretty(value)?;
+    Ok(redact(&json).into_owned().into_bytes())
+}
+
+fn write_bundle(path: &Path, entries: &[(&str, Vec<u8>)]) -> Result<(), DiagnosticsError> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(File::create(path)?);
+
+    for (name, contents) in entries {
+        zip.start_file(*name, options)?;
+        zip.write_all(contents)?;
+    }
+
+    // Logs written before redaction was added may still contain tokens
+    for log in log_path().as_deref().map(log_files).unwrap_or_default() {
+        let Some(name) = log.file_name() else {
+            continue;
+        };
+
+        zip.start_file(format!("logs/{}", name.to_string_lossy()), options)?;
+
+        for line in BufReader::new(File::open(&log)?).split(b'\n') {
+            let line = line?;
+            zip.write_all(redact(&String::from_utf8_lossy(&line)).as_bytes())?;
+            zip.write_all(b"\n")?;
+        }
+    }
+
+    zip.finish()?;
+
+    Ok(())
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| u64::try_from(x.as_millis()).unwrap_or(u64::MAX))
+        .unwrap_or_default()
+}
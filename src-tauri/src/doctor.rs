@@ -0,0 +1,795 @@
+use std::{
+    collections::HashMap,
+    future::{ready, Future},
+    time::{Duration, Instant},
+};
+
+use moosicbox_app_client::{models::ApiProfile, MoosicBoxClient};
+use moosicbox_app_logging::redact;
+use moosicbox_app_ws::{ConnectionState, Connector, ReconnectPolicy, WsClient, WsHandle};
+use moosicbox_ws::models::{EmptyPayload, InboundPayload, OutboundPayload};
+use reqwest::{
+    url::{Host, Url},
+    Client,
+};
+use serde::Serialize;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    credentials::ConnectionSecrets, current_secrets, error::ErrorCode, http, tls_client_config,
+    ws_compression_enabled, ws_request, ws_url, AppCore, TauriPlayerError, WS_REQUEST_TIMEOUT,
+};
+
+/// Backstop for stages that don't time out on their own
+const STAGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long the WS probe waits for the handshake to complete
+const WS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A step of setting up a connection, in the order they are checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Stage {
+    UrlParse,
+    Dns,
+    Http,
+    Auth,
+    Profile,
+    WsHandshake,
+    /// Registers an empty player list for the probe connection. This is a real request, but it
+    /// adds no players.
+    RegisterPlayers,
+    LocalOutputs,
+    Upnp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum StageStatus {
+    Passed,
+    Failed,
+    /// Not run because a stage it depends on failed
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StageReport {
+    pub stage: Stage,
+    pub status: StageStatus,
+    pub duration_ms: u64,
+    pub message: String,
+    pub code: Option<ErrorCode>,
+    pub details: Option<String>,
+    pub retryable: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionReport {
+    pub api_url: Option<String>,
+    pub profile: Option<String>,
+    /// Whether the stored credentials were used. They only are for the current server.
+    pub authenticated: bool,
+    /// Whether every stage that ran passed
+    pub ok: bool,
+    pub stages: Vec<StageReport>,
+    /// The first failed stage of the server connection, which the stages after it depend on
+    #[serde(skip)]
+    failed: Option<Stage>,
+}
+
+type StageResult<T> = Result<(T, String), TauriPlayerError>;
+
+impl ConnectionReport {
+    /// Runs a stage of the server connection. It is skipped if an earlier one failed or
+    /// `check` is `None`.
+    async fn run<T>(
+        &mut self,
+        stage: Stage,
+        check: Option<impl Future<Output = StageResult<T>>>,
+    ) -> Option<T> {
+        match (self.failed, check) {
+            (None, Some(check)) => {
+                let value = self.run_local(stage, check).await;
+                if value.is_none() {
+                    self.failed = Some(stage);
+                }
+                value
+            }
+            (failed, _) => {
+                let message = failed.map_or_else(
+                    || "Skipped".to_string(),
+                    |x| format!("Skipped because {x:?} failed"),
+                );
+                self.skip(stage, message);
+                None
+            }
+        }
+    }
+
+    /// Runs a stage that doesn't depend on the server connection
+    async fn run_local<T>(
+        &mut self,
+        stage: Stage,
+        check: impl Future<Output = StageResult<T>>,
+    ) -> Option<T> {
+        let started = Instant::now();
+        let result = tokio::time::timeout(STAGE_TIMEOUT, check)
+            .await
+            .unwrap_or_else(|_| {
+                Err(TauriPlayerError::Timeout(format!(
+                    "{stage:?} did not finish within {}s",
+                    STAGE_TIMEOUT.as_secs()
+                )))
+            });
+        let duration_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+
+        let (report, value) = match result {
+            Ok((value, message)) => (
+                StageReport {
+                    stage,
+                    status: StageStatus::Passed,
+                    duration_ms,
+                    message: redact(&message).into_owned(),
+                    code: None,
+                    details: None,
+                    retryable: false,
+                },
+                Some(value),
+            ),
+            Err(e) => (
+                StageReport {
+                    stage,
+                    status: StageStatus::Failed,
+                    duration_ms,
+                    message: e.to_string(),
+                    code: Some(e.code()),
+                    details: e.details().map(|x| redact(&x).into_owned()),
+                    retryable: e.retryable(),
+                },
+                None,
+            ),
+        };
+
+        log::debug!(
+            "diagnose_connection: {stage:?} {:?} in {duration_ms}ms: {} {:?}",
+            report.status,
+            report.message,
+            report.details
+        );
+
+        self.stages.push(report);
+        value
+    }
+
+    /// Whether no stage failed
+    fn passed(&self) -> bool {
+        self.stages.iter().all(|x| x.status != StageStatus::Failed)
+    }
+
+    fn skip(&mut self, stage: Stage, message: String) {
+        log::debug!("diagnose_connection: {stage:?} skipped: {message}");
+
+        self.stages.push(StageReport {
+            stage,
+            status: StageStatus::Skipped,
+            duration_ms: 0,
+            message,
+            code: None,
+            details: None,
+            retryable: false,
+        });
+    }
+}
+
+/// Checks each step of connecting to `api_url` with `profile`, defaulting to the current
+/// connection, and reports which passed, failed or were skipped. Uses a separate WS
+/// connection, so the current connection is left as is. Other servers are checked without
+/// credentials.
+#[tauri::command]
+pub async fn diagnose_connection(
+    core: tauri::State<'_, AppCore>,
+    api_url: Option<String>,
+    profile: Option<String>,
+) -> Result<ConnectionReport, TauriPlayerError> {
+    let mut report = check_connection(&core, api_url, profile).await;
+
+    report
+        .run_local(Stage::LocalOutputs, check_local_outputs())
+        .await;
+
+    if core.upnp_listener_handle.is_some() {
+        report.run_local(Stage::Upnp, check_upnp()).await;
+    } else {
+        report.skip(Stage::Upnp, "UPnP is not available".to_string());
+    }
+
+    report.ok = report.passed();
+
+    Ok(report)
+}
+
+/// Runs the stages that depend on the server connection
+async fn check_connection(
+    core: &AppCore,
+    api_url: Option<String>,
+    profile: Option<String>,
+) -> ConnectionReport {
+    let current_api_url = core.api_url.read().await.clone();
+    let api_url = api_url.or_else(|| current_api_url.clone());
+    let profile = match profile {
+        Some(profile) => Some(profile),
+        None => core.profile.read().await.clone(),
+    };
+
+    let authenticated = api_url
+        .as_deref()
+        .zip(current_api_url.as_deref())
+        .is_some_and(|(api_url, current)| same_server(api_url, current));
+    let secrets = if authenticated {
+        current_secrets(core).await
+    } else {
+        ConnectionSecrets::default()
+    };
+
+    let mut report = ConnectionReport {
+        api_url: api_url.as_deref().map(|x| redact(x).into_owned()),
+        profile: profile.clone(),
+        authenticated,
+        ..Default::default()
+    };
+
+    let url = report
+        .run(Stage::UrlParse, Some(ready(parse_url(api_url.as_deref()))))
+        .await;
+    report.run(Stage::Dns, url.as_ref().map(resolve)).await;
+    let http_client = report
+        .run(Stage::Http, url.as_ref().map(|url| check_http(core, url)))
+        .await;
+
+    let client = api_url.zip(http_client).map(|(api_url, http_client)| {
+        diagnostic_client(api_url, http_client, profile.as_deref(), &secrets)
+    });
+    let auth = report
+        .run(Stage::Auth, client.as_ref().map(check_auth))
+        .await;
+    report
+        .run(
+            Stage::Profile,
+            auth.map(|profiles| ready(check_profile(profile.as_deref(), &profiles))),
+        )
+        .await;
+
+    let ws = report
+        .run(
+            Stage::WsHandshake,
+            client
+                .as_ref()
+                .map(|client| check_ws(core, client.api_url(), profile.as_deref(), &secrets)),
+        )
+        .await;
+    report
+        .run(
+            Stage::RegisterPlayers,
+            client
+                .as_ref()
+                .zip(ws.as_ref())
+                .map(|(client, ws)| check_register_players(client, &ws.connection_id)),
+        )
+        .await;
+
+    if let Some(ws) = ws {
+        ws.close().await;
+    }
+
+    report
+}
+
+/// Whether both URLs point at the same server. Parsing normalizes the case of the scheme and
+/// host and drops default ports.
+fn same_server(api_url: &str, current: &str) -> bool {
+    let parse = |x: &str| Url::parse(x.trim_end_matches('/')).ok();
+
+    parse(api_url).is_some_and(|x| Some(x) == parse(current))
+}
+
+fn parse_url(api_url: Option<&str>) -> StageResult<Url> {
+    let api_url = api_url.ok_or(TauriPlayerError::ApiUrlNotSet)?;
+    let url = Url::parse(api_url).map_err(|e| TauriPlayerError::InvalidUrl(e.to_string()))?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(TauriPlayerError::InvalidUrl(format!(
+            "Expected an http or https URL, got '{}'",
+            url.scheme()
+        )));
+    }
+    if url.host().is_none() {
+        return Err(TauriPlayerError::InvalidUrl("Missing host".to_string()));
+    }
+
+    let message = format!(
+        "{}://{}:{}",
+        url.scheme(),
+        url.host_str().unwrap_or_default(),
+        url.port_or_known_default().unwrap_or_default()
+    );
+
+    Ok((url, message))
+}
+
+async fn resolve(url: &Url) -> StageResult<()> {
+    let port = url.port_or_known_default().unwrap_or_default();
+
+    let domain = match url.host() {
+        Some(Host::Domain(domain)) => domain,
+        Some(host) => return Ok(((), format!("{host} is an IP address"))),
+        None => return Err(TauriPlayerError::InvalidUrl("Missing host".to_string())),
+    };
+
+    let addresses = tokio::net::lookup_host((domain, port))
+        .await
+        .map_err(|e| TauriPlayerError::Network(format!("Failed to resolve {domain}: {e}")))?
+        .map(|x| x.ip().to_string())
+        .collect::<Vec<_>>();
+
+    if addresses.is_empty() {
+        return Err(TauriPlayerError::Network(format!(
+            "{domain} did not resolve to any address"
+        )));
+    }
+
+    Ok(((), format!("{domain} resolved to {}", addresses.join(", "))))
+}
+
+/// Any response means the server is reachable, whatever its status
+async fn check_http(core: &AppCore, url: &Url) -> StageResult<Client> {
+    let client = http::client(core).await?;
+    let response = client.get(url.clone()).send().await?;
+
+    Ok((
+        client,
+        format!("Server responded with {}", response.status()),
+    ))
+}
+
+fn diagnostic_client(
+    api_url: String,
+    http_client: Client,
+    profile: Option<&str>,
+    secrets: &ConnectionSecrets,
+) -> MoosicBoxClient {
+    let mut client = MoosicBoxClient::new(api_url).with_http_client(http_client);
+
+    if let Some(profile) = profile {
+        client = client.with_profile(profile);
+    }
+    if let Some(api_token) = secrets.api_token.as_deref() {
+        client = client.with_api_token(api_token);
+    }
+    if let Some(client_id) = secrets.client_id.as_deref() {
+        client = client.with_client_id(client_id);
+    }
+
+    client
+}
+
+/// Lists the server's profiles, which requires the credentials to be accepted
+async fn check_auth(client: &MoosicBoxClient) -> StageResult<Vec<ApiProfile>> {
+    let profiles = client.profiles().await?;
+
+    Ok((profiles, "Credentials accepted".to_string()))
+}
+
+fn check_profile(profile: Option<&str>, profiles: &[ApiProfile]) -> StageResult<()> {
+    let profile = profile.ok_or(TauriPlayerError::MissingProfile)?;
+
+    if !profiles.iter().any(|x| x.name == profile) {
+        return Err(TauriPlayerError::ProfileNotFound(profile.to_string()));
+    }
+
+    Ok(((), format!("Profile '{profile}' exists")))
+}
+
+struct WsProbe {
+    handle: WsHandle,
+    token: CancellationToken,
+    connection_id: String,
+}
+
+impl WsProbe {
+    async fn close(self) {
+        if let Err(e) = self.handle.close().await {
+            log::debug!("diagnose_connection: failed to close WS probe: {e:?}");
+        }
+        self.token.cancel();
+    }
+}
+
+/// Opens a WS connection that doesn't reconnect and requests its connection id
+async fn check_ws(
+    core: &AppCore,
+    api_url: &str,
+    profile: Option<&str>,
+    secrets: &ConnectionSecrets,
+) -> StageResult<WsProbe> {
+    let profile = profile.ok_or(TauriPlayerError::MissingProfile)?;
+
+    let mut headers = HashMap::new();
+    if let Some(api_token) = secrets.api_token.as_deref() {
+        headers.insert("Authorization".to_string(), format!("bearer {api_token}"));
+    }
+
+    let ws_url = ws_url(api_url)?;
+    let connector = tls_client_config(core).await?.map(Connector::Rustls);
+    let token = CancellationToken::new();
+
+    let (client, handle) = WsClient::new(ws_url.to_string());
+    let mut client = client
+        .with_handshake_headers(headers)
+        .with_tls_connector(connector)
+        .with_compression(ws_compression_enabled())
+        .with_reconnect_policy(ReconnectPolicy::default().max_attempts(Some(0)))
+        .with_linked_cancellation_token(token.clone());
+
+    let mut state = client.connection_state();
+    let mut rx = client.start(
+        secrets.client_id.clone(),
+        secrets.signature_token.clone(),
+        profile.to_string(),
+        || {},
+    );
+    moosicbox_task::spawn("diagnose_connection: ws messages", async move {
+        while rx.recv().await.is_some() {}
+    });
+
+    let connection_id = async {
+        tokio::time::timeout(WS_HANDSHAKE_TIMEOUT, wait_connected(&mut state))
+            .await
+            .map_err(|_| {
+                TauriPlayerError::Timeout(format!(
+                    "Handshake did not complete within {}s",
+                    WS_HANDSHAKE_TIMEOUT.as_secs()
+                ))
+            })??;
+
+        let response = ws_request(
+            &handle,
+            InboundPayload::GetConnectionId(EmptyPayload {}),
+            |x| matches!(x, OutboundPayload::ConnectionId(_)),
+            WS_REQUEST_TIMEOUT,
+        )
+        .await?;
+
+        let OutboundPayload::ConnectionId(payload) = response else {
+            return Err(TauriPlayerError::InvalidResponse(format!(
+                "Unexpected GetConnectionId response: {response:?}"
+            )));
+        };
+
+        Ok::<_, TauriPlayerError>(payload.connection_id)
+    }
+    .await;
+
+    let connection_id = match connection_id {
+        Ok(connection_id) => connection_id,
+        Err(e) => {
+            token.cancel();
+            return Err(e);
+        }
+    };
+
+    let message = format!("Connected to {ws_url} with connection id {connection_id}");
+
+    Ok((
+        WsProbe {
+            handle,
+            token,
+            connection_id,
+        },
+        message,
+    ))
+}
+
+async fn wait_connected(
+    state: &mut watch::Receiver<ConnectionState>,
+) -> Result<(), TauriPlayerError> {
+    let mut reason = None;
+
+    loop {
+        let current = state.borrow_and_update().clone();
+
+        match current {
+            ConnectionState::Connected => return Ok(()),
+            ConnectionState::Unauthorized => return Err(TauriPlayerError::Unauthorized(401)),
+            ConnectionState::Disconnected { reason: Some(x) } => reason = Some(x),
+            ConnectionState::Reconnecting { .. }
+            | ConnectionState::GaveUp
+            | ConnectionState::Closed => {
+                return Err(TauriPlayerError::Ws(
+                    reason.unwrap_or_else(|| "Connection failed".to_string()),
+                ));
+            }
+            ConnectionState::Connecting | ConnectionState::Disconnected { reason: None } => {}
+        }
+
+        if state.changed().await.is_err() {
+            return Err(TauriPlayerError::Ws(
+                reason.unwrap_or_else(|| "Connection closed".to_string()),
+            ));
+        }
+    }
+}
+
+/// Registers an empty player list, so only the request itself and the connection id are checked
+async fn check_register_players(client: &MoosicBoxClient, connection_id: &str) -> StageResult<()> {
+    client.register_players(connection_id, &[]).await?;
+
+    Ok(((), "Server accepted player registration".to_string()))
+}
+
+async fn check_local_outputs() -> StageResult<()> {
+    if moosicbox_audio_output::output_factories().await.is_empty() {
+        moosicbox_audio_output::scan_outputs().await?;
+    }
+
+    let outputs = moosicbox_audio_output::output_factories().await;
+    if outputs.is_empty() {
+        return Err(TauriPlayerError::AudioOutput(
+            "No audio outputs found".to_string(),
+        ));
+    }
+
+    let names = outputs.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+
+    Ok((
+        (),
+        format!(
+            "Found {} audio outputs: {}",
+            outputs.len(),
+            names.join(", ")
+        ),
+    ))
+}
+
+async fn check_upnp() -> StageResult<()> {
+    moosicbox_upnp::scan_devices().await?;
+
+    let count = moosicbox_upnp::devices().await.len();
+
+    Ok(((), format!("Found {count} UPnP devices")))
+}
+
+#[cfg(test)]
+mod tests {
+    use moosicbox_app_http_test_utils::{MockResponse, MockServer};
+
+    use super::*;
+
+    fn passing() -> impl Future<Output = StageResult<()>> {
+        ready(Ok(((), "Passed".to_string())))
+    }
+
+    fn statuses(report: &ConnectionReport) -> Vec<(Stage, StageStatus)> {
+        report.stages.iter().map(|x| (x.stage, x.status)).collect()
+    }
+
+    /// A server that rejects every profile request, so the stages after `Auth` are skipped
+    async fn unauthorized_server() -> MockServer {
+        let server = MockServer::start().await;
+        server.route("GET", "/config/profiles", |_| {
+            MockResponse::status(401, "Unauthorized")
+        });
+        server
+    }
+
+    async fn connected_core(server: &MockServer) -> AppCore {
+        let core = AppCore::new();
+        core.api_url.write().await.replace(server.url());
+        core.profile.write().await.replace("master".to_string());
+        core.client_id
+            .write()
+            .await
+            .replace("client-id".to_string());
+        core.signature_token
+            .write()
+            .await
+            .replace("signature-token".to_string());
+        core.api_token
+            .write()
+            .await
+            .replace("api-token".to_string());
+        core
+    }
+
+    #[test]
+    fn run_skips_stages_after_a_failed_stage() {
+        tauri::async_runtime::block_on(async {
+            let mut report = ConnectionReport::default();
+
+            let url = report
+                .run(Stage::UrlParse, Some(ready(parse_url(Some("ftp://host")))))
+                .await;
+            let dns = report.run(Stage::Dns, Some(passing())).await;
+            let outputs = report.run_local(Stage::LocalOutputs, passing()).await;
+
+            assert!(url.is_none());
+            assert!(dns.is_none());
+            assert!(outputs.is_some());
+            assert_eq!(
+                statuses(&report),
+                vec![
+                    (Stage::UrlParse, StageStatus::Failed),
+                    (Stage::Dns, StageStatus::Skipped),
+                    (Stage::LocalOutputs, StageStatus::Passed),
+                ]
+            );
+            assert_eq!(report.stages[0].code, Some(ErrorCode::InvalidUrl));
+            assert_eq!(report.stages[1].message, "Skipped because UrlParse failed");
+            assert!(!report.passed());
+        });
+    }
+
+    #[test]
+    fn run_skips_stages_without_a_check() {
+        tauri::async_runtime::block_on(async {
+            let mut report = ConnectionReport::default();
+
+            report
+                .run(Stage::UrlParse, Some(ready(parse_url(Some("http://host")))))
+                .await;
+            report
+                .run(Stage::Dns, None::<std::future::Ready<StageResult<()>>>)
+                .await;
+            report.run(Stage::Http, Some(passing())).await;
+
+            assert_eq!(
+                statuses(&report),
+                vec![
+                    (Stage::UrlParse, StageStatus::Passed),
+                    (Stage::Dns, StageStatus::Skipped),
+                    (Stage::Http, StageStatus::Passed),
+                ]
+            );
+            assert_eq!(report.stages[0].message, "http://host:80");
+            assert_eq!(report.stages[1].message, "Skipped");
+            assert!(report.passed());
+        });
+    }
+
+    #[test]
+    fn same_server_ignores_normalized_differences() {
+        assert!(same_server(
+            "HTTPS://Example.com:443/",
+            "https://example.com"
+        ));
+        assert!(same_server("http://host/api/", "http://host/api"));
+        assert!(!same_server("http://host:8000", "http://host:8001"));
+        assert!(!same_server("http://host/a", "http://host/b"));
+        assert!(!same_server("https://host", "http://host"));
+        assert!(!same_server("not a url", "not a url"));
+    }
+
+    #[test]
+    fn check_connection_without_an_api_url_skips_the_server_stages() {
+        tauri::async_runtime::block_on(async {
+            let report = check_connection(&AppCore::new(), None, None).await;
+
+            assert!(!report.authenticated);
+            assert_eq!(report.stages[0].stage, Stage::UrlParse);
+            assert_eq!(report.stages[0].status, StageStatus::Failed);
+            assert_eq!(report.stages[0].code, Some(ErrorCode::ApiUrlNotSet));
+            assert_eq!(
+                report.stages[1..]
+                    .iter()
+                    .map(|x| (x.stage, x.status, x.message.as_str()))
+                    .collect::<Vec<_>>(),
+                [
+                    Stage::Dns,
+                    Stage::Http,
+                    Stage::Auth,
+                    Stage::Profile,
+                    Stage::WsHandshake,
+                    Stage::RegisterPlayers,
+                ]
+                .into_iter()
+                .map(|x| (x, StageStatus::Skipped, "Skipped because UrlParse failed"))
+                .collect::<Vec<_>>()
+            );
+        });
+    }
+
+    #[test]
+    fn check_connection_uses_the_stored_credentials_for_the_current_server() {
+        tauri::async_runtime::block_on(async {
+            let server = unauthorized_server().await;
+            let core = connected_core(&server).await;
+
+            let report = check_connection(&core, Some(format!("{}/", server.url())), None).await;
+
+            assert!(report.authenticated);
+            assert_eq!(report.profile.as_deref(), Some("master"));
+
+            let requests = server.requests();
+            let profiles = requests
+                .iter()
+                .find(|x| x.path == "/config/profiles")
+                .unwrap();
+            assert_eq!(profiles.header("authorization"), Some("Bearer api-token"));
+        });
+    }
+
+    #[test]
+    fn check_connection_does_not_send_the_stored_credentials_to_other_servers() {
+        tauri::async_runtime::block_on(async {
+            let current = unauthorized_server().await;
+            let other = unauthorized_server().await;
+            let core = connected_core(&current).await;
+
+            let report = check_connection(&core, Some(other.url()), None).await;
+
+            assert!(!report.authenticated);
+            assert_eq!(
+                statuses(&report)[..4],
+                [
+                    (Stage::UrlParse, StageStatus::Passed),
+                    (Stage::Dns, StageStatus::Passed),
+                    (Stage::Http, StageStatus::Passed),
+                    (Stage::Auth, StageStatus::Failed),
+                ]
+            );
+            assert!(current.requests().is_empty());
+
+            let requests = other.requests();
+            assert!(requests.iter().any(|x| x.path == "/config/profiles"));
+            for request in requests {
+                assert_eq!(request.header("authorization"), None);
+                assert_eq!(request.query("clientId"), None);
+                assert_eq!(request.query("signature"), None);
+            }
+        });
+    }
+
+    #[test]
+    fn check_connection_reports_unknown_profiles() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            server.route("GET", "/config/profiles", |_| {
+                MockResponse::json(&serde_json::json!([{"name": "master"}]))
+            });
+            let core = connected_core(&server).await;
+
+            let report = check_connection(&core, None, Some("other".to_string())).await;
+
+            assert_eq!(
+                statuses(&report)[3..5],
+                [
+                    (Stage::Auth, StageStatus::Passed),
+                    (Stage::Profile, StageStatus::Failed),
+                ]
+            );
+            assert_eq!(report.stages[4].code, Some(ErrorCode::ProfileNotFound));
+            assert_eq!(report.stages[4].message, "Profile 'other' not found");
+        });
+    }
+
+    #[test]
+    fn check_connection_reports_error_statuses_at_auth() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            let core = connected_core(&server).await;
+
+            let report = check_connection(&core, None, None).await;
+
+            assert_eq!(
+                statuses(&report)[3..5],
+                [
+                    (Stage::Auth, StageStatus::Failed),
+                    (Stage::Profile, StageStatus::Skipped),
+                ]
+            );
+            assert_eq!(report.stages[3].code, Some(ErrorCode::Http));
+        });
+    }
+}
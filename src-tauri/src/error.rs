@@ -0,0 +1,582 @@
+use std::borrow::Cow;
+
+use moosicbox_app_client::ClientError;
+use moosicbox_app_credentials::CredentialError;
+use moosicbox_app_http_cache::HttpCacheError;
+use moosicbox_app_logging::redact;
+use moosicbox_app_tls::TlsError;
+use moosicbox_app_ws::{CloseError, RequestError, WebsocketSendError};
+use moosicbox_audio_output::{AudioOutputError, AudioOutputScannerError};
+use moosicbox_player::PlayerError;
+use moosicbox_upnp::UpnpDeviceScannerError;
+use serde::{ser::SerializeStruct as _, Serialize, Serializer};
+use thiserror::Error;
+use tokio::task::JoinError;
+
+use crate::{
+    auth, diagnostics::DiagnosticsError, AppError, CloseWsError, ConnectionError,
+    FetchAudioZonesError, HandleWsMessageError, InitUpnpError, InitWsError, RegisterPlayersError,
+    ScanOutputsError, SendWsMessageError, SettingsError, WsRequestError,
+};
+
+/// Longest response body returned as the `details` of an `Http` error, in characters
+const MAX_BODY_DETAILS_LEN: usize = 1024;
+
+/// Identifies the kind of failure so the frontend can react to it without parsing messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    ApiUrlNotSet,
+    InvalidUrl,
+    MissingProfile,
+    ProfileNotFound,
+    ConnectionNotFound,
+    UploadFileNotFound,
+    Unauthorized,
+    Http,
+    Network,
+    Timeout,
+    Cancelled,
+    NotCached,
+    Cache,
+    InvalidResponse,
+    Serialization,
+    Player,
+    AudioOutput,
+    Ws,
+    Tls,
+    Credentials,
+    Settings,
+    Tauri,
+    Task,
+    Io,
+}
+
+/// The error returned by every command. Serializes as `{code, message, details, retryable}`.
+#[derive(Debug, Error)]
+pub enum TauriPlayerError {
+    #[error("API_URL not set")]
+    ApiUrlNotSet,
+    #[error("Invalid URL")]
+    InvalidUrl(String),
+    #[error("Missing profile")]
+    MissingProfile,
+    #[error("Profile '{0}' not found")]
+    ProfileNotFound(String),
+    #[error("Connection {0} not found")]
+    ConnectionNotFound(u64),
+    #[error("Upload file {0} not found")]
+    UploadFileNotFound(String),
+    #[error("Unauthorized ({0})")]
+    Unauthorized(u16),
+    #[error("Request failed with status {status}")]
+    Http { status: u16, body: String },
+    #[error("Network request failed")]
+    Network(String),
+    #[error("Request timed out")]
+    Timeout(String),
+    #[error("Request cancelled")]
+    Cancelled,
+    #[error("No cached response available offline")]
+    NotCached,
+    #[error("HTTP cache error")]
+    Cache(String),
+    #[error("Invalid response")]
+    InvalidResponse(String),
+    /// One of our own payloads couldn't be serialized
+    #[error("Failed to serialize request")]
+    Serialization(String),
+    #[error("Player error")]
+    Player(String),
+    #[error("Audio output error")]
+    AudioOutput(String),
+    #[error("WebSocket error")]
+    Ws(String),
+    #[error("TLS error")]
+    Tls(String),
+    #[error("Credential store error")]
+    Credentials(String),
+    #[error("Settings error")]
+    Settings(String),
+    #[error("Tauri error")]
+    Tauri(String),
+    #[error("Background task failed")]
+    Task(String),
+    #[error("IO error")]
+    IO(String),
+}
+
+impl TauriPlayerError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::ApiUrlNotSet => ErrorCode::ApiUrlNotSet,
+            Self::InvalidUrl(_) => ErrorCode::InvalidUrl,
+            Self::MissingProfile => ErrorCode::MissingProfile,
+            Self::ProfileNotFound(_) => ErrorCode::ProfileNotFound,
+            Self::ConnectionNotFound(_) => ErrorCode::ConnectionNotFound,
+            Self::UploadFileNotFound(_) => ErrorCode::UploadFileNotFound,
+            Self::Unauthorized(_) => ErrorCode::Unauthorized,
+            Self::Http { .. } => ErrorCode::Http,
+            Self::Network(_) => ErrorCode::Network,
+            Self::Timeout(_) => ErrorCode::Timeout,
+            Self::Cancelled => ErrorCode::Cancelled,
+            Self::NotCached => ErrorCode::NotCached,
+            Self::Cache(_) => ErrorCode::Cache,
+            Self::InvalidResponse(_) => ErrorCode::InvalidResponse,
+            Self::Serialization(_) => ErrorCode::Serialization,
+            Self::Player(_) => ErrorCode::Player,
+            Self::AudioOutput(_) => ErrorCode::AudioOutput,
+            Self::Ws(_) => ErrorCode::Ws,
+            Self::Tls(_) => ErrorCode::Tls,
+            Self::Credentials(_) => ErrorCode::Credentials,
+            Self::Settings(_) => ErrorCode::Settings,
+            Self::Tauri(_) => ErrorCode::Tauri,
+            Self::Task(_) => ErrorCode::Task,
+            Self::IO(_) => ErrorCode::Io,
+        }
+    }
+
+    /// More specific information about the failure, e.g. the underlying error or response body.
+    /// Response bodies are redacted and truncated.
+    pub fn details(&self) -> Option<Cow<'_, str>> {
+        match self {
+            Self::ApiUrlNotSet
+            | Self::MissingProfile
+            | Self::ProfileNotFound(_)
+            | Self::ConnectionNotFound(_)
+            | Self::UploadFileNotFound(_)
+            | Self::Unauthorized(_)
+            | Self::Cancelled
+            | Self::NotCached => None,
+            Self::Http { body, .. } => (!body.is_empty()).then(|| body_details(body)),
+            Self::InvalidUrl(details)
+            | Self::Network(details)
+            | Self::Timeout(details)
+            | Self::InvalidResponse(details)
+            | Self::Serialization(details)
+            | Self::Player(details)
+            | Self::AudioOutput(details)
+            | Self::Ws(details)
+            | Self::Tls(details)
+            | Self::Cache(details)
+            | Self::Credentials(details)
+            | Self::Settings(details)
+            | Self::Tauri(details)
+            | Self::Task(details)
+            | Self::IO(details) => Some(Cow::Borrowed(details)),
+        }
+    }
+
+    /// Whether the same call may succeed if it is made again
+    pub fn retryable(&self) -> bool {
+        match self {
+            Self::Network(_) | Self::Timeout(_) | Self::Ws(_) => true,
+            Self::Http { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+fn body_details(body: &str) -> Cow<'_, str> {
+    let body = redact(body);
+
+    match body.char_indices().nth(MAX_BODY_DETAILS_LEN) {
+        Some((end, _)) => Cow::Owned(format!("{}…", &body[..end])),
+        None => body,
+    }
+}
+
+impl Serialize for TauriPlayerError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("TauriPlayerError", 4)?;
+        state.serialize_field("code", &self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("details", &self.details())?;
+        state.serialize_field("retryable", &self.retryable())?;
+        state.end()
+    }
+}
+
+impl From<reqwest::Error> for TauriPlayerError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            return Self::Timeout(err.to_string());
+        }
+
+        if let Some(status) = err.status() {
+            if auth::is_unauthorized_status(status) {
+                return Self::Unauthorized(status.as_u16());
+            }
+            return Self::Http {
+                status: status.as_u16(),
+                body: String::new(),
+            };
+        }
+
+        if err.is_decode() {
+            return Self::InvalidResponse(err.to_string());
+        }
+
+        Self::Network(err.to_string())
+    }
+}
+
+impl From<ClientError> for TauriPlayerError {
+    fn from(err: ClientError) -> Self {
+        match err {
+            ClientError::Reqwest(e) => e.into(),
+            ClientError::Serde(e) => e.into(),
+            ClientError::Unauthorized(status) => Self::Unauthorized(status),
+            ClientError::Http { status, body } => Self::Http { status, body },
+            ClientError::Sender(e) => match e.downcast::<Self>() {
+                Ok(e) => *e,
+                Err(e) => Self::Network(e.to_string()),
+            },
+        }
+    }
+}
+
+impl From<std::io::Error> for TauriPlayerError {
+    fn from(err: std::io::Error) -> Self {
+        Self::IO(err.to_string())
+    }
+}
+
+/// For parsing responses. Failures serializing our own payloads are mapped to `Serialization`
+/// where they happen.
+impl From<serde_json::Error> for TauriPlayerError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::InvalidResponse(err.to_string())
+    }
+}
+
+impl From<PlayerError> for TauriPlayerError {
+    fn from(err: PlayerError) -> Self {
+        match auth::unauthorized_status(&err) {
+            Some(status) => Self::Unauthorized(status.as_u16()),
+            None => Self::Player(err.to_string()),
+        }
+    }
+}
+
+impl From<AudioOutputError> for TauriPlayerError {
+    fn from(err: AudioOutputError) -> Self {
+        Self::AudioOutput(err.to_string())
+    }
+}
+
+impl From<AudioOutputScannerError> for TauriPlayerError {
+    fn from(err: AudioOutputScannerError) -> Self {
+        Self::AudioOutput(err.to_string())
+    }
+}
+
+impl From<UpnpDeviceScannerError> for TauriPlayerError {
+    fn from(err: UpnpDeviceScannerError) -> Self {
+        Self::AudioOutput(err.to_string())
+    }
+}
+
+impl From<ConnectionError> for TauriPlayerError {
+    fn from(err: ConnectionError) -> Self {
+        match err {
+            ConnectionError::NotFound(id) => Self::ConnectionNotFound(id),
+        }
+    }
+}
+
+impl From<CredentialError> for TauriPlayerError {
+    fn from(err: CredentialError) -> Self {
+        Self::Credentials(err.to_string())
+    }
+}
+
+impl From<HttpCacheError> for TauriPlayerError {
+    fn from(err: HttpCacheError) -> Self {
+        Self::Cache(err.to_string())
+    }
+}
+
+impl From<DiagnosticsError> for TauriPlayerError {
+    fn from(err: DiagnosticsError) -> Self {
+        match err {
+            DiagnosticsError::IO(e) => e.into(),
+            DiagnosticsError::Serde(e) => Self::Serialization(e.to_string()),
+            DiagnosticsError::Zip(e) => Self::IO(e.to_string()),
+        }
+    }
+}
+
+impl From<SettingsError> for TauriPlayerError {
+    fn from(err: SettingsError) -> Self {
+        Self::Settings(err.to_string())
+    }
+}
+
+impl From<TlsError> for TauriPlayerError {
+    fn from(err: TlsError) -> Self {
+        Self::Tls(err.to_string())
+    }
+}
+
+impl From<tauri::Error> for TauriPlayerError {
+    fn from(err: tauri::Error) -> Self {
+        Self::Tauri(err.to_string())
+    }
+}
+
+impl From<JoinError> for TauriPlayerError {
+    fn from(err: JoinError) -> Self {
+        Self::Task(err.to_string())
+    }
+}
+
+impl From<WebsocketSendError> for TauriPlayerError {
+    fn from(err: WebsocketSendError) -> Self {
+        Self::Ws(err.to_string())
+    }
+}
+
+impl From<CloseError> for TauriPlayerError {
+    fn from(err: CloseError) -> Self {
+        Self::Ws(err.to_string())
+    }
+}
+
+impl From<RequestError> for TauriPlayerError {
+    fn from(err: RequestError) -> Self {
+        match err {
+            RequestError::Timeout => Self::Timeout(err.to_string()),
+            RequestError::Serde(_) | RequestError::InvalidPayload => {
+                Self::Serialization(err.to_string())
+            }
+            _ => Self::Ws(err.to_string()),
+        }
+    }
+}
+
+impl From<WsRequestError> for TauriPlayerError {
+    fn from(err: WsRequestError) -> Self {
+        match err {
+            WsRequestError::Serde(e) => e.into(),
+            WsRequestError::Request(e) => e.into(),
+        }
+    }
+}
+
+impl From<HandleWsMessageError> for TauriPlayerError {
+    fn from(err: HandleWsMessageError) -> Self {
+        match err {
+            HandleWsMessageError::Serde(e) => e.into(),
+            HandleWsMessageError::Player(e) => e.into(),
+            HandleWsMessageError::Emit(e) => e.into(),
+            HandleWsMessageError::Tauri(e) => e,
+        }
+    }
+}
+
+impl From<SendWsMessageError> for TauriPlayerError {
+    fn from(err: SendWsMessageError) -> Self {
+        match err {
+            SendWsMessageError::WebsocketSend(e) => e.into(),
+            SendWsMessageError::HandleWsMessage(e) => e.into(),
+            SendWsMessageError::Serde(e) => Self::Serialization(e.to_string()),
+        }
+    }
+}
+
+impl From<CloseWsError> for TauriPlayerError {
+    fn from(err: CloseWsError) -> Self {
+        match err {
+            CloseWsError::TauriPlayer(e) => e,
+            CloseWsError::Close(e) => e.into(),
+            CloseWsError::Join(e) => e.into(),
+        }
+    }
+}
+
+impl From<InitWsError> for TauriPlayerError {
+    fn from(err: InitWsError) -> Self {
+        match err {
+            InitWsError::AudioOutputScanner(e) => e.into(),
+            InitWsError::Serde(e) => e.into(),
+            InitWsError::TauriPlayer(e) => e,
+            InitWsError::CloseWs(e) => e.into(),
+            InitWsError::Tls(e) => e.into(),
+            InitWsError::MissingProfile => Self::MissingProfile,
+        }
+    }
+}
+
+impl From<RegisterPlayersError> for TauriPlayerError {
+    fn from(err: RegisterPlayersError) -> Self {
+        match err {
+            RegisterPlayersError::Client(e) => e.into(),
+            RegisterPlayersError::TauriPlayer(e) => e,
+            RegisterPlayersError::MissingProfile => Self::MissingProfile,
+        }
+    }
+}
+
+impl From<FetchAudioZonesError> for TauriPlayerError {
+    fn from(err: FetchAudioZonesError) -> Self {
+        match err {
+            FetchAudioZonesError::Client(e) => e.into(),
+            FetchAudioZonesError::TauriPlayer(e) => e,
+            FetchAudioZonesError::MissingProfile => Self::MissingProfile,
+        }
+    }
+}
+
+impl From<ScanOutputsError> for TauriPlayerError {
+    fn from(err: ScanOutputsError) -> Self {
+        match err {
+            ScanOutputsError::AudioOutputScanner(e) => e.into(),
+            ScanOutputsError::Serde(e) => e.into(),
+            ScanOutputsError::TauriPlayer(e) => e,
+            ScanOutputsError::RegisterPlayers(e) => e.into(),
+        }
+    }
+}
+
+impl From<InitUpnpError> for TauriPlayerError {
+    fn from(err: InitUpnpError) -> Self {
+        match err {
+            InitUpnpError::UpnpDeviceScanner(e) => e.into(),
+            InitUpnpError::TauriPlayer(e) => e,
+            InitUpnpError::AudioOutput(e) => e.into(),
+            InitUpnpError::RegisterPlayers(e) => e.into(),
+        }
+    }
+}
+
+impl From<AppError> for TauriPlayerError {
+    fn from(err: AppError) -> Self {
+        match err {
+            AppError::Tauri(e) => e.into(),
+            AppError::SendWsMessage(e) => e.into(),
+            AppError::WsRequest(e) => e.into(),
+            AppError::UnexpectedWsResponse(e) => Self::InvalidResponse(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn serialized(err: impl Into<TauriPlayerError>) -> serde_json::Value {
+        serde_json::to_value(err.into()).unwrap()
+    }
+
+    #[test]
+    fn serializes_code_message_details_and_retryable() {
+        assert_eq!(
+            serialized(TauriPlayerError::ApiUrlNotSet),
+            json!({
+                "code": "API_URL_NOT_SET",
+                "message": "API_URL not set",
+                "details": null,
+                "retryable": false,
+            })
+        );
+        assert_eq!(
+            serialized(TauriPlayerError::Network("connection refused".to_string())),
+            json!({
+                "code": "NETWORK",
+                "message": "Network request failed",
+                "details": "connection refused",
+                "retryable": true,
+            })
+        );
+    }
+
+    #[test]
+    fn http_errors_are_retryable_for_server_errors_and_rate_limits() {
+        let http = |status: u16, body: &str| TauriPlayerError::Http {
+            status,
+            body: body.to_string(),
+        };
+
+        assert_eq!(
+            serialized(http(503, "down")),
+            json!({
+                "code": "HTTP",
+                "message": "Request failed with status 503",
+                "details": "down",
+                "retryable": true,
+            })
+        );
+        assert_eq!(serialized(http(429, ""))["retryable"], json!(true));
+        assert_eq!(serialized(http(404, ""))["retryable"], json!(false));
+        assert_eq!(serialized(http(404, ""))["details"], json!(null));
+    }
+
+    #[test]
+    fn error_codes_are_screaming_snake_case() {
+        assert_eq!(
+            serialized(TauriPlayerError::InvalidUrl("x".to_string()))["code"],
+            json!("INVALID_URL")
+        );
+        assert_eq!(
+            serialized(TauriPlayerError::ConnectionNotFound(1))["code"],
+            json!("CONNECTION_NOT_FOUND")
+        );
+        assert_eq!(
+            serialized(TauriPlayerError::IO("x".to_string()))["code"],
+            json!("IO")
+        );
+    }
+
+    #[test]
+    fn converted_errors_keep_a_typed_code() {
+        assert_eq!(
+            serialized(ClientError::Unauthorized(401)),
+            json!({
+                "code": "UNAUTHORIZED",
+                "message": "Unauthorized (401)",
+                "details": null,
+                "retryable": false,
+            })
+        );
+        assert_eq!(
+            serialized(AppError::UnexpectedWsResponse(
+                "GetConnectionId got x".to_string()
+            ))["code"],
+            json!("INVALID_RESPONSE")
+        );
+        assert_eq!(
+            serialized(serde_json::from_str::<u8>("x").unwrap_err())["code"],
+            json!("INVALID_RESPONSE")
+        );
+        assert_eq!(
+            serialized(RequestError::Serde(
+                serde_json::from_str::<u8>("x").unwrap_err()
+            ))["code"],
+            json!("SERIALIZATION")
+        );
+    }
+
+    #[test]
+    fn http_error_details_are_redacted_and_truncated() {
+        moosicbox_app_logging::register_secret("error-details-secret");
+
+        let details = serialized(TauriPlayerError::Http {
+            status: 500,
+            body: "token error-details-secret rejected".to_string(),
+        })["details"]
+            .clone();
+        assert_eq!(details, json!("token [redacted] rejected"));
+
+        let details = TauriPlayerError::Http {
+            status: 500,
+            body: "x".repeat(MAX_BODY_DETAILS_LEN + 10),
+        }
+        .details()
+        .unwrap()
+        .into_owned();
+        assert_eq!(details, format!("{}…", "x".repeat(MAX_BODY_DETAILS_LEN)));
+    }
+}
@@ -0,0 +1,459 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use moosicbox_app_tls::TlsConfig;
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::{tls_client_config, AppCore, TauriPlayerError};
+
+/// Timeouts, pooling and retries of the API client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HttpConfig {
+    pub connect_timeout_ms: u64,
+    /// Applies to the whole request, except for streamed responses
+    pub request_timeout_ms: u64,
+    pub pool_idle_timeout_ms: u64,
+    pub pool_max_idle_per_host: usize,
+    /// Retries of idempotent requests that failed to connect, timed out or got a 502, 503 or 504
+    pub max_retries: u32,
+    pub retry_initial_delay_ms: u64,
+    pub retry_max_delay_ms: u64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_ms: 10_000,
+            request_timeout_ms: 30_000,
+            pool_idle_timeout_ms: 90_000,
+            pool_max_idle_per_host: 8,
+            max_retries: 2,
+            retry_initial_delay_ms: 250,
+            retry_max_delay_ms: 2_000,
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Delay before retry `attempt`, starting at 1 and doubling up to the max delay
+    pub fn retry_delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .retry_initial_delay_ms
+            .saturating_mul(2_u64.saturating_pow(attempt.saturating_sub(1)));
+
+        Duration::from_millis(delay.min(self.retry_max_delay_ms))
+    }
+}
+
+/// The client is rebuilt when any of these change, so each connection gets its own pool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpClientKey {
+    api_url: Option<String>,
+    tls_config: Option<TlsConfig>,
+    config: HttpConfig,
+}
+
+/// Cancellation tokens of the in-flight requests that were given an id
+#[derive(Debug, Default)]
+pub struct RequestRegistry {
+    tokens: Mutex<HashMap<String, CancellationToken>>,
+}
+
+impl RequestRegistry {
+    /// Cancels the request `id`. Returns whether it was still in flight.
+    pub fn cancel(&self, id: &str) -> bool {
+        let token = self.tokens.lock().unwrap().remove(id);
+
+        token.inspect(CancellationToken::cancel).is_some()
+    }
+
+    fn register(self: &Arc<Self>, id: String) -> RegisteredRequest {
+        let token = CancellationToken::new();
+
+        if let Some(previous) = self
+            .tokens
+            .lock()
+            .unwrap()
+            .insert(id.clone(), token.clone())
+        {
+            log::debug!("RequestRegistry: replacing request id={id}");
+            previous.cancel();
+        }
+
+        RegisteredRequest {
+            registry: self.clone(),
+            id,
+            token,
+        }
+    }
+}
+
+struct RegisteredRequest {
+    registry: Arc<RequestRegistry>,
+    id: String,
+    token: CancellationToken,
+}
+
+impl Drop for RegisteredRequest {
+    fn drop(&mut self) {
+        let mut tokens = self.registry.tokens.lock().unwrap();
+
+        if tokens.get(&self.id).is_some_and(|x| x == &self.token) {
+            tokens.remove(&self.id);
+        }
+    }
+}
+
+/// The API client for the current connection, built on first use and reused after
+pub(crate) async fn client(core: &AppCore) -> Result<Client, TauriPlayerError> {
+    let key = HttpClientKey {
+        api_url: core.api_url.read().await.clone(),
+        tls_config: core.tls_config.read().await.clone(),
+        config: core.http_config.read().await.clone(),
+    };
+
+    let mut cached = core.http_client.write().await;
+
+    if let Some((cached_key, client)) = cached.as_ref() {
+        if *cached_key == key {
+            return Ok(client.clone());
+        }
+    }
+
+    log::debug!("http client: building client for api_url={:?}", key.api_url);
+
+    let tls_config = tls_client_config(core).await.map_err(|e| {
+        log::error!("Invalid TLS config: {e:?}");
+        e
+    })?;
+
+    let config = &key.config;
+    let mut builder = Client::builder()
+        .connect_timeout(Duration::from_millis(config.connect_timeout_ms))
+        .pool_idle_timeout(Duration::from_millis(config.pool_idle_timeout_ms))
+        .pool_max_idle_per_host(config.pool_max_idle_per_host)
+        .tcp_keepalive(Duration::from_secs(60));
+
+    if let Some(tls_config) = tls_config {
+        builder = builder.use_preconfigured_tls((*tls_config).clone());
+    }
+
+    let client = builder.build().map_err(|e| {
+        log::error!("Failed to build http client: {e:?}");
+        TauriPlayerError::from(e)
+    })?;
+
+    cached.replace((key, client.clone()));
+
+    Ok(client)
+}
+
+/// Sends the request with the configured request timeout, retrying idempotent requests
+pub(crate) async fn send(
+    core: &AppCore,
+    builder: RequestBuilder,
+) -> Result<Response, TauriPlayerError> {
+    let config = core.http_config.read().await.clone();
+    let timeout = Duration::from_millis(config.request_timeout_ms);
+
+    execute(&config, builder, Some(timeout)).await
+}
+
+/// Like `send`, but without a total timeout so large responses can be streamed
+pub(crate) async fn send_streaming(
+    core: &AppCore,
+    builder: RequestBuilder,
+) -> Result<Response, TauriPlayerError> {
+    let config = core.http_config.read().await.clone();
+
+    execute(&config, builder, None).await
+}
+
+async fn execute(
+    config: &HttpConfig,
+    builder: RequestBuilder,
+    timeout: Option<Duration>,
+) -> Result<Response, TauriPlayerError> {
+    let (client, request) = builder.build_split();
+    let mut request = request?;
+
+    if let Some(timeout) = timeout {
+        request.timeout_mut().get_or_insert(timeout);
+    }
+
+    let retryable = is_idempotent(request.method());
+    let mut attempt = 0;
+
+    loop {
+        let retry = if retryable && attempt < config.max_retries {
+            request.try_clone()
+        } else {
+            None
+        };
+
+        let result = client.execute(request).await;
+
+        let Some(next) = retry else {
+            return Ok(result?);
+        };
+
+        let should_retry = match &result {
+            Ok(response) => is_retryable_status(response.status()),
+            Err(e) => e.is_connect() || e.is_timeout(),
+        };
+
+        if !should_retry {
+            return Ok(result?);
+        }
+
+        log::debug!("http send: retrying attempt={attempt} result={result:?}");
+
+        attempt += 1;
+        tokio::time::sleep(config.retry_delay(attempt)).await;
+        request = next;
+    }
+}
+
+/// Runs `future`, aborting it with `TauriPlayerError::Cancelled` if `cancel_request` is called
+/// with `request_id`
+pub(crate) async fn cancellable<T>(
+    core: &AppCore,
+    request_id: Option<String>,
+    future: impl Future<Output = Result<T, TauriPlayerError>>,
+) -> Result<T, TauriPlayerError> {
+    let Some(request_id) = request_id else {
+        return future.await;
+    };
+
+    let request = core.http_requests.register(request_id);
+
+    tokio::select! {
+        result = future => result,
+        () = request.token.cancelled() => {
+            log::debug!("cancellable: cancelled request id={}", request.id);
+            Err(TauriPlayerError::Cancelled)
+        }
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+    )
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use moosicbox_app_http_test_utils::{MockResponse, MockServer};
+
+    use super::*;
+
+    async fn core_for(server: &MockServer) -> AppCore {
+        let core = AppCore::new();
+        core.api_url.write().await.replace(server.url());
+        *core.http_config.write().await = HttpConfig {
+            retry_initial_delay_ms: 1,
+            retry_max_delay_ms: 1,
+            ..HttpConfig::default()
+        };
+        core
+    }
+
+    async fn cached_key(core: &AppCore) -> Option<HttpClientKey> {
+        core.http_client
+            .read()
+            .await
+            .as_ref()
+            .map(|(key, _)| key.clone())
+    }
+
+    async fn send_to(core: &AppCore, method: Method, path: &str) -> Response {
+        let url = format!("{}{path}", core.api_url.read().await.as_deref().unwrap());
+        let builder = client(core).await.unwrap().request(method, url);
+
+        send(core, builder).await.unwrap()
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_max_delay() {
+        let config = HttpConfig {
+            retry_initial_delay_ms: 100,
+            retry_max_delay_ms: 350,
+            ..HttpConfig::default()
+        };
+
+        assert_eq!(config.retry_delay(1), Duration::from_millis(100));
+        assert_eq!(config.retry_delay(2), Duration::from_millis(200));
+        assert_eq!(config.retry_delay(3), Duration::from_millis(350));
+        assert_eq!(config.retry_delay(u32::MAX), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn client_is_reused_until_its_key_changes() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            let core = core_for(&server).await;
+
+            client(&core).await.unwrap();
+            let first = cached_key(&core).await.unwrap();
+            assert_eq!(first.api_url, Some(server.url()));
+
+            client(&core).await.unwrap();
+            assert_eq!(cached_key(&core).await, Some(first.clone()));
+
+            core.http_config.write().await.max_retries = 5;
+            client(&core).await.unwrap();
+            let second = cached_key(&core).await.unwrap();
+            assert_ne!(second, first);
+            assert_eq!(second.config.max_retries, 5);
+
+            core.api_url
+                .write()
+                .await
+                .replace("http://127.0.0.1:1".to_string());
+            client(&core).await.unwrap();
+            let third = cached_key(&core).await.unwrap();
+            assert_eq!(third.api_url.as_deref(), Some("http://127.0.0.1:1"));
+            assert_eq!(third.config, second.config);
+        });
+    }
+
+    #[test]
+    fn idempotent_requests_are_retried_on_gateway_errors() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            server.route("GET", "/busy", |_| MockResponse::status(503, ""));
+            let core = core_for(&server).await;
+
+            let response = send_to(&core, Method::GET, "/busy").await;
+
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(server.requests().len(), 3);
+        });
+    }
+
+    #[test]
+    fn retry_stops_after_a_successful_response() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            let calls = Arc::new(AtomicUsize::new(0));
+            server.route("PUT", "/flaky", {
+                let calls = calls.clone();
+                move |_| match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => MockResponse::status(502, ""),
+                    _ => MockResponse::status(200, "ok"),
+                }
+            });
+            let core = core_for(&server).await;
+
+            let response = send_to(&core, Method::PUT, "/flaky").await;
+
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(server.requests().len(), 2);
+        });
+    }
+
+    #[test]
+    fn non_idempotent_requests_are_not_retried() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            server.route("POST", "/busy", |_| MockResponse::status(503, ""));
+            let core = core_for(&server).await;
+
+            let response = send_to(&core, Method::POST, "/busy").await;
+
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(server.requests().len(), 1);
+        });
+    }
+
+    #[test]
+    fn other_error_statuses_are_not_retried() {
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            server.route("GET", "/error", |_| MockResponse::status(500, ""));
+            let core = core_for(&server).await;
+
+            let response = send_to(&core, Method::GET, "/error").await;
+
+            assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+            assert_eq!(server.requests().len(), 1);
+        });
+    }
+
+    #[test]
+    fn cancel_request_aborts_the_registered_request() {
+        tauri::async_runtime::block_on(async {
+            let core = AppCore::new();
+            let task = tauri::async_runtime::spawn({
+                let core = core.clone();
+                async move {
+                    cancellable(
+                        &core,
+                        Some("a".to_string()),
+                        std::future::pending::<Result<(), TauriPlayerError>>(),
+                    )
+                    .await
+                }
+            });
+
+            while !core.http_requests.tokens.lock().unwrap().contains_key("a") {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+
+            assert!(core.http_requests.cancel("a"));
+            assert!(matches!(
+                task.await.unwrap(),
+                Err(TauriPlayerError::Cancelled)
+            ));
+            assert!(!core.http_requests.cancel("a"));
+        });
+    }
+
+    #[test]
+    fn finished_requests_are_unregistered() {
+        tauri::async_runtime::block_on(async {
+            let core = AppCore::new();
+
+            let result = cancellable(&core, Some("a".to_string()), async { Ok(1) }).await;
+
+            assert_eq!(result.unwrap(), 1);
+            assert!(core.http_requests.tokens.lock().unwrap().is_empty());
+            assert!(!core.http_requests.cancel("a"));
+        });
+    }
+
+    #[test]
+    fn reusing_an_id_cancels_the_previous_request() {
+        let registry = Arc::new(RequestRegistry::default());
+
+        let first = registry.register("a".to_string());
+        let second = registry.register("a".to_string());
+
+        assert!(first.token.is_cancelled());
+        assert!(!second.token.is_cancelled());
+
+        drop(first);
+        assert!(registry.tokens.lock().unwrap().contains_key("a"));
+
+        assert!(registry.cancel("a"));
+        assert!(second.token.is_cancelled());
+    }
+}
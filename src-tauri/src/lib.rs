@@ -2,13 +2,21 @@ use std::{
     collections::HashMap,
     env,
     fmt::Debug,
-    sync::{Arc, LazyLock, OnceLock},
+    sync::{Arc, LazyLock},
 };
 
 use async_recursion::async_recursion;
 use log::info;
+use moosicbox_app_client::{all_pages, ClientError, MoosicBoxClient, DEFAULT_PAGE_SIZE};
+use moosicbox_app_credentials::{CredentialStore, FileBackend};
+use moosicbox_app_http_cache::HttpCache;
+use moosicbox_app_logging::{register_secret, Redacted, RedactingLayer, RedactingLogLayer};
+use moosicbox_app_tls::{
+    rustls::ClientConfig, TlsConfig, TlsError, UntrustedCertificate, UntrustedCertificateCallback,
+};
 use moosicbox_app_ws::{
-    CloseError, WebsocketSendError, WebsocketSender as _, WsClient, WsHandle, WsMessage,
+    CloseError, CompressionStats, ConnectionState, Connector, DeliveryReceipt, Priority,
+    RequestError, WebsocketSendError, WsClient, WsHandle, WsMessage,
 };
 use moosicbox_audio_output::{AudioOutputError, AudioOutputFactory, AudioOutputScannerError};
 use moosicbox_audio_zone::models::{ApiAudioZoneWithSession, ApiPlayer};
@@ -18,15 +26,14 @@ use moosicbox_core::{
 };
 use moosicbox_mdns::scanner::service::Commander;
 use moosicbox_music_api::{FromId, MusicApi, MusicApisError, SourceToMusicApi};
-use moosicbox_paging::Page;
 use moosicbox_player::{
     local::LocalPlayer, Playback, PlaybackHandler, PlaybackRetryOptions, PlaybackType, PlayerError,
     PlayerSource, Track,
 };
 use moosicbox_remote_library::RemoteLibraryMusicApi;
 use moosicbox_session::models::{
-    ApiConnection, ApiPlaybackTarget, ApiSession, ApiUpdateSession, ApiUpdateSessionPlaylist,
-    PlaybackTarget, RegisterPlayer, UpdateSession, UpdateSessionPlaylistTrack,
+    ApiPlaybackTarget, ApiSession, ApiUpdateSession, ApiUpdateSessionPlaylist, PlaybackTarget,
+    RegisterPlayer, UpdateSession, UpdateSessionPlaylistTrack,
 };
 use moosicbox_upnp::{
     listener::Handle, player::UpnpAvTransportService, Device, Service, UpnpDeviceScannerError,
@@ -34,15 +41,42 @@ use moosicbox_upnp::{
 use moosicbox_ws::models::{
     EmptyPayload, InboundPayload, OutboundPayload, SessionUpdatedPayload, UpdateSessionPayload,
 };
-use reqwest::RequestBuilder;
+use reqwest::{url::Url, RequestBuilder};
 use serde::{Deserialize, Serialize};
 use strum_macros::{AsRefStr, EnumString};
-use tauri::{async_runtime::RwLock, AppHandle, Emitter};
 use thiserror::Error;
-use tokio::task::{JoinError, JoinHandle};
+use tokio::task::JoinError;
 use tokio_util::sync::CancellationToken;
 
+mod art;
+mod auth;
+mod cache;
+mod connections;
+mod credentials;
+mod diagnostics;
+mod doctor;
+mod error;
+mod http;
 mod mdns;
+mod proxy;
+mod settings;
+mod state;
+
+pub use art::{album_art_uri, ART_SCHEME};
+pub use auth::AuthRequired;
+pub use cache::{CachedProxyResponse, HttpCacheConfig, HttpCacheMode};
+pub use connections::{ConnectionError, ConnectionRegistry, NewConnection, SavedConnection};
+pub use credentials::{ConnectionSecrets, CredentialStatus};
+pub use error::{ErrorCode, TauriPlayerError};
+pub use http::HttpConfig;
+pub use proxy::{
+    ProxyBody, ProxyMethod, ProxyRequest, ProxyResponse, ProxyResponseBody, ProxyResponseType,
+    ProxyStreamEvent,
+};
+pub use settings::{Settings, SettingsError, SETTINGS_VERSION};
+pub use state::AppCore;
+
+use crate::credentials::{connection_scope, CURRENT_SCOPE};
 
 #[derive(Clone, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -51,15 +85,52 @@ pub struct WsConnectMessage {
     pub ws_url: String,
 }
 
-#[derive(Debug, Error, Serialize, Deserialize)]
-pub enum TauriPlayerError {
-    #[error("Unknown({0})")]
-    Unknown(String),
+#[derive(Clone, Serialize, Debug)]
+#[serde(tag = "type", rename_all_fields = "camelCase")]
+pub enum WsStatusMessage {
+    Connecting,
+    Connected,
+    Disconnected { reason: Option<String> },
+    Reconnecting { attempt: u32, next_in_ms: u64 },
+    GaveUp,
+    Unauthorized,
+    Closed,
+}
+
+impl From<ConnectionState> for WsStatusMessage {
+    fn from(value: ConnectionState) -> Self {
+        match value {
+            ConnectionState::Connecting => Self::Connecting,
+            ConnectionState::Connected => Self::Connected,
+            ConnectionState::Disconnected { reason } => Self::Disconnected { reason },
+            ConnectionState::Reconnecting { attempt, next_in } => Self::Reconnecting {
+                attempt,
+                next_in_ms: next_in.as_millis() as u64,
+            },
+            ConnectionState::GaveUp => Self::GaveUp,
+            ConnectionState::Unauthorized => Self::Unauthorized,
+            ConnectionState::Closed => Self::Closed,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WsCompressionStats {
+    pub negotiated: bool,
+    pub compressed_bytes: u64,
+    pub uncompressed_bytes: u64,
+    pub sent_bytes: u64,
 }
 
-impl From<PlayerError> for TauriPlayerError {
-    fn from(err: PlayerError) -> Self {
-        TauriPlayerError::Unknown(err.to_string())
+impl From<CompressionStats> for WsCompressionStats {
+    fn from(value: CompressionStats) -> Self {
+        Self {
+            negotiated: value.negotiated,
+            compressed_bytes: value.compressed_bytes,
+            uncompressed_bytes: value.uncompressed_bytes,
+            sent_bytes: value.sent_bytes,
+        }
     }
 }
 
@@ -69,12 +140,14 @@ pub enum AppError {
     Tauri(#[from] tauri::Error),
     #[error(transparent)]
     SendWsMessage(#[from] SendWsMessageError),
-    #[error("Unknown({0})")]
-    Unknown(String),
+    #[error(transparent)]
+    WsRequest(#[from] WsRequestError),
+    #[error("Unexpected WS response: {0}")]
+    UnexpectedWsResponse(String),
 }
 
-static APP: OnceLock<AppHandle> = OnceLock::new();
-static LOG_LAYER: OnceLock<moosicbox_logging::free_log_client::FreeLogLayer> = OnceLock::new();
+/// Every property set on the remote log layer goes through the redacting wrapper
+pub(crate) type LogLayer = RedactingLogLayer<moosicbox_logging::free_log_client::FreeLogLayer>;
 
 type ApiPlayersMap = HashMap<u64, Vec<(ApiPlayer, PlayerType, AudioOutputFactory)>>;
 
@@ -86,54 +159,7 @@ struct PlaybackTargetSessionPlayer {
     player_type: PlayerType,
 }
 
-static API_URL: LazyLock<Arc<RwLock<Option<String>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(None)));
-static PROFILE: LazyLock<Arc<RwLock<Option<String>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(None)));
-static WS_URL: LazyLock<Arc<RwLock<Option<String>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(None)));
-static WS_CONNECTION_ID: LazyLock<Arc<RwLock<Option<String>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(None)));
-static CONNECTION_ID: LazyLock<Arc<RwLock<Option<String>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(None)));
-static SIGNATURE_TOKEN: LazyLock<Arc<RwLock<Option<String>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(None)));
-static CLIENT_ID: LazyLock<Arc<RwLock<Option<String>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(None)));
-static API_TOKEN: LazyLock<Arc<RwLock<Option<String>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(None)));
-static WS_TOKEN: LazyLock<Arc<RwLock<Option<CancellationToken>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(None)));
-static WS_HANDLE: LazyLock<Arc<RwLock<Option<WsHandle>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(None)));
-static WS_JOIN_HANDLE: LazyLock<Arc<RwLock<Option<JoinHandle<()>>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(None)));
-static AUDIO_ZONE_ACTIVE_API_PLAYERS: LazyLock<Arc<RwLock<ApiPlayersMap>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(HashMap::new())));
-static ACTIVE_PLAYERS: LazyLock<Arc<RwLock<Vec<PlaybackTargetSessionPlayer>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(vec![])));
-static PLAYBACK_QUALITY: LazyLock<Arc<RwLock<Option<PlaybackQuality>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(None)));
-static WS_MESSAGE_BUFFER: LazyLock<Arc<RwLock<Vec<InboundPayload>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(vec![])));
-static CURRENT_PLAYBACK_TARGET: LazyLock<Arc<RwLock<Option<PlaybackTarget>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(None)));
-static CURRENT_CONNECTIONS: LazyLock<Arc<RwLock<Vec<ApiConnection>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(vec![])));
-static PENDING_PLAYER_SESSIONS: LazyLock<Arc<RwLock<HashMap<u64, u64>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(HashMap::new())));
-static CURRENT_SESSIONS: LazyLock<Arc<RwLock<Vec<ApiSession>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(vec![])));
-static CURRENT_SESSION_ID: LazyLock<Arc<RwLock<Option<u64>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(None)));
-static CURRENT_AUDIO_ZONES: LazyLock<Arc<RwLock<Vec<ApiAudioZoneWithSession>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(vec![])));
-#[allow(clippy::type_complexity)]
-static CURRENT_PLAYERS: LazyLock<Arc<RwLock<Vec<(ApiPlayer, PlayerType, AudioOutputFactory)>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(vec![])));
-static UPNP_AV_TRANSPORT_SERVICES: LazyLock<
-    tokio::sync::RwLock<Vec<moosicbox_upnp::player::UpnpAvTransportService>>,
-> = LazyLock::new(|| tokio::sync::RwLock::new(vec![]));
+const WS_REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(10_000);
 
 const DEFAULT_PLAYBACK_RETRY_OPTIONS: PlaybackRetryOptions = PlaybackRetryOptions {
     max_attempts: 10,
@@ -178,51 +204,64 @@ impl std::fmt::Debug for PlayerType {
 }
 
 async fn new_player(
+    core: &AppCore,
     session_id: u64,
     playback_target: ApiPlaybackTarget,
     output: AudioOutputFactory,
     player_type: PlayerType,
 ) -> Result<PlaybackHandler, TauriPlayerError> {
-    let profile = { PROFILE.read().await.clone() };
+    let profile = { core.profile.read().await.clone() };
     let Some(profile) = profile else {
-        return Err(TauriPlayerError::Unknown("Missing profile".to_string()));
+        return Err(TauriPlayerError::MissingProfile);
     };
 
     let mut headers = HashMap::new();
     headers.insert("moosicbox-profile".to_string(), profile);
 
-    if API_TOKEN.read().await.is_some() {
+    if core.api_token.read().await.is_some() {
         headers.insert(
             "Authorization".to_string(),
-            API_TOKEN.read().await.clone().unwrap().to_string(),
+            core.api_token.read().await.clone().unwrap().to_string(),
         );
     }
 
-    let query = if CLIENT_ID.read().await.is_some() && SIGNATURE_TOKEN.read().await.is_some() {
-        let mut query = HashMap::new();
-        query.insert(
-            "clientId".to_string(),
-            CLIENT_ID.read().await.clone().unwrap().to_string(),
-        );
-        query.insert(
-            "signature".to_string(),
-            SIGNATURE_TOKEN.read().await.clone().unwrap().to_string(),
-        );
-        Some(query)
-    } else {
-        None
-    };
+    let query =
+        if core.client_id.read().await.is_some() && core.signature_token.read().await.is_some() {
+            let mut query = HashMap::new();
+            query.insert(
+                "clientId".to_string(),
+                core.client_id.read().await.clone().unwrap().to_string(),
+            );
+            query.insert(
+                "signature".to_string(),
+                core.signature_token
+                    .read()
+                    .await
+                    .clone()
+                    .unwrap()
+                    .to_string(),
+            );
+            Some(query)
+        } else {
+            None
+        };
 
-    let host = API_URL
+    let host = core
+        .api_url
         .read()
         .await
         .clone()
-        .ok_or_else(|| TauriPlayerError::Unknown("API_URL not set".to_string()))?;
+        .ok_or(TauriPlayerError::ApiUrlNotSet)?;
+
+    // The shared client has the connection's TLS config and no total timeout, so audio from
+    // servers with pinned or custom root certificates streams like any other response
+    let client = http::client(core).await?;
 
     let player_source = PlayerSource::Remote {
         host: host.clone(),
         headers: Some(headers),
         query,
+        client: Some(client),
     };
 
     let mut player = match player_type {
@@ -230,9 +269,8 @@ async fn new_player(
             let local_player = LocalPlayer::new(player_source, Some(PlaybackType::Stream))
                 .await
                 .map_err(|e| {
-                    TauriPlayerError::Unknown(format!(
-                        "Failed to initialize new local player: {e:?}"
-                    ))
+                    log::error!("Failed to initialize new local player: {e:?}");
+                    e
                 })?
                 .with_output(output.clone());
 
@@ -285,7 +323,7 @@ async fn new_player(
     };
 
     let session = {
-        CURRENT_SESSIONS
+        core.current_sessions
             .read()
             .await
             .iter()
@@ -293,7 +331,7 @@ async fn new_player(
             .cloned()
     };
 
-    let profile = { PROFILE.read().await.clone() };
+    let profile = { core.profile.read().await.clone() };
 
     if let (Some(profile), Some(session)) = (profile.clone(), session) {
         log::debug!("new_player: init_from_api_session session={session:?}");
@@ -302,7 +340,7 @@ async fn new_player(
         }
     } else {
         log::debug!("new_player: No session info available for player yet");
-        PENDING_PLAYER_SESSIONS
+        core.pending_player_sessions
             .write()
             .await
             .insert(player.id as u64, session_id);
@@ -318,7 +356,7 @@ async fn new_player(
             None,
             None,
             None,
-            *PLAYBACK_QUALITY.read().await,
+            *core.playback_quality.read().await,
             Some(session_id),
             profile,
             Some(playback_target.into()),
@@ -340,19 +378,25 @@ async fn show_main_window(window: tauri::Window) {
 }
 
 #[tauri::command]
-async fn on_startup() -> Result<(), tauri::Error> {
+async fn on_startup(core: tauri::State<'_, AppCore>) -> Result<(), TauriPlayerError> {
     log::debug!("on_startup");
 
-    let connection_id = { WS_CONNECTION_ID.read().await.clone() };
+    let connection_state = {
+        core.ws_handle
+            .read()
+            .await
+            .as_ref()
+            .map(|handle| handle.connection_state().borrow().clone())
+    };
+
+    if let Some(connection_state) = connection_state {
+        core.emit("ws-status", WsStatusMessage::from(connection_state))?;
+    }
+
+    let connection_id = { core.ws_connection_id.read().await.clone() };
 
     if let Some(connection_id) = connection_id {
-        APP.get().unwrap().emit(
-            "ws-connect",
-            WsConnectMessage {
-                connection_id,
-                ws_url: WS_URL.read().await.to_owned().unwrap_or_default(),
-            },
-        )?;
+        emit_ws_connect(&core, connection_id).await?;
     }
 
     Ok(())
@@ -364,12 +408,19 @@ pub struct AppState {
     connection_id: Option<String>,
     connection_name: Option<String>,
     api_url: Option<String>,
+    /// Kept in the credential store and never serialized
+    #[serde(skip_serializing)]
     client_id: Option<String>,
+    #[serde(skip_serializing)]
     signature_token: Option<String>,
+    #[serde(skip_serializing)]
     api_token: Option<String>,
     profile: Option<String>,
     playback_target: Option<PlaybackTarget>,
     current_session_id: Option<u64>,
+    tls: Option<TlsConfig>,
+    #[serde(default)]
+    ws_query_auth: bool,
 }
 
 impl std::fmt::Display for AppState {
@@ -379,21 +430,35 @@ impl std::fmt::Display for AppState {
 }
 
 #[tauri::command]
-async fn set_state(state: AppState) -> Result<(), TauriPlayerError> {
-    log::debug!("set_state: state={state:?}");
+async fn set_state(
+    core: tauri::State<'_, AppCore>,
+    state: AppState,
+) -> Result<(), TauriPlayerError> {
+    core.settings_restored().await;
+    if apply_state(&core, state).await? {
+        update_state(&core).await?;
+    }
+    sync_active_connection(&core).await;
+    persist_settings(&core).await;
+
+    Ok(())
+}
+
+/// Returns whether the connection details changed, in which case the caller reconnects.
+async fn apply_state(core: &AppCore, state: AppState) -> Result<bool, TauriPlayerError> {
+    log::debug!("set_state: state={:?}", Redacted(&state));
 
     let mut updated_connection_details = false;
 
     {
         if let Some(connection_id) = &state.connection_id {
-            LOG_LAYER
-                .get()
+            core.log_layer()
                 .map(|x| x.set_property("connectionId", connection_id.to_owned().into()));
         } else {
-            LOG_LAYER.get().map(|x| x.remove_property("connectionId"));
+            core.log_layer().map(|x| x.remove_property("connectionId"));
         }
 
-        let mut connection_id = CONNECTION_ID.write().await;
+        let mut connection_id = core.connection_id.write().await;
 
         if connection_id.as_ref() != state.connection_id.as_ref() {
             log::debug!(
@@ -410,24 +475,25 @@ async fn set_state(state: AppState) -> Result<(), TauriPlayerError> {
 
     {
         if let Some(connection_name) = &state.connection_name {
-            LOG_LAYER
-                .get()
+            core.log_layer()
                 .map(|x| x.set_property("connectionName", connection_name.to_owned().into()));
         } else {
-            LOG_LAYER.get().map(|x| x.remove_property("connectionName"));
+            core.log_layer()
+                .map(|x| x.remove_property("connectionName"));
         }
+
+        *core.connection_name.write().await = state.connection_name;
     }
 
     {
         if let Some(client_id) = &state.client_id {
-            LOG_LAYER
-                .get()
+            core.log_layer()
                 .map(|x| x.set_property("clientId", client_id.to_owned().into()));
         } else {
-            LOG_LAYER.get().map(|x| x.remove_property("clientId"));
+            core.log_layer().map(|x| x.remove_property("clientId"));
         }
 
-        let mut client_id = CLIENT_ID.write().await;
+        let mut client_id = core.client_id.write().await;
 
         if client_id.as_ref() != state.client_id.as_ref() {
             log::debug!(
@@ -443,13 +509,17 @@ async fn set_state(state: AppState) -> Result<(), TauriPlayerError> {
     }
 
     {
-        let mut signature_token = SIGNATURE_TOKEN.write().await;
+        if let Some(value) = &state.signature_token {
+            register_secret(value);
+        }
+
+        let mut signature_token = core.signature_token.write().await;
 
         if signature_token.as_ref() != state.signature_token.as_ref() {
             log::debug!(
                 "set_state: updating SIGNATURE_TOKEN from '{:?}' -> '{:?}'",
-                signature_token.as_ref(),
-                state.signature_token.as_ref()
+                Redacted(signature_token.as_ref()),
+                Redacted(state.signature_token.as_ref())
             );
             *signature_token = state.signature_token;
             updated_connection_details = true;
@@ -459,13 +529,17 @@ async fn set_state(state: AppState) -> Result<(), TauriPlayerError> {
     }
 
     {
-        let mut api_token = API_TOKEN.write().await;
+        if let Some(value) = &state.api_token {
+            register_secret(value);
+        }
+
+        let mut api_token = core.api_token.write().await;
 
         if api_token.as_ref() != state.api_token.as_ref() {
             log::debug!(
                 "set_state: updating API_TOKEN from '{:?}' -> '{:?}'",
-                api_token.as_ref(),
-                state.api_token.as_ref()
+                Redacted(api_token.as_ref()),
+                Redacted(state.api_token.as_ref())
             );
             *api_token = state.api_token;
             updated_connection_details = true;
@@ -474,16 +548,47 @@ async fn set_state(state: AppState) -> Result<(), TauriPlayerError> {
         }
     }
 
+    {
+        let mut tls_config = core.tls_config.write().await;
+
+        if tls_config.as_ref() != state.tls.as_ref() {
+            log::debug!(
+                "set_state: updating TLS_CONFIG from '{:?}' -> '{:?}'",
+                tls_config.as_ref(),
+                state.tls.as_ref()
+            );
+            *tls_config = state.tls;
+            updated_connection_details = true;
+        } else {
+            log::debug!("set_state: no update to TLS_CONFIG");
+        }
+    }
+
+    {
+        let mut ws_query_auth = core.ws_query_auth.write().await;
+
+        if *ws_query_auth != state.ws_query_auth {
+            log::debug!(
+                "set_state: updating WS_QUERY_AUTH from '{}' -> '{}'",
+                *ws_query_auth,
+                state.ws_query_auth
+            );
+            *ws_query_auth = state.ws_query_auth;
+            updated_connection_details = true;
+        } else {
+            log::debug!("set_state: no update to WS_QUERY_AUTH");
+        }
+    }
+
     {
         if let Some(api_url) = &state.api_url {
-            LOG_LAYER
-                .get()
+            core.log_layer()
                 .map(|x| x.set_property("apiUrl", api_url.to_owned().into()));
         } else {
-            LOG_LAYER.get().map(|x| x.remove_property("apiUrl"));
+            core.log_layer().map(|x| x.remove_property("apiUrl"));
         }
 
-        let mut api_url = API_URL.write().await;
+        let mut api_url = core.api_url.write().await;
 
         if api_url.as_ref() != state.api_url.as_ref() {
             log::debug!(
@@ -500,14 +605,13 @@ async fn set_state(state: AppState) -> Result<(), TauriPlayerError> {
 
     {
         if let Some(profile) = &state.profile {
-            LOG_LAYER
-                .get()
+            core.log_layer()
                 .map(|x| x.set_property("profile", profile.to_owned().into()));
         } else {
-            LOG_LAYER.get().map(|x| x.remove_property("profile"));
+            core.log_layer().map(|x| x.remove_property("profile"));
         }
 
-        let mut profile = PROFILE.write().await;
+        let mut profile = core.profile.write().await;
 
         if profile.as_ref() != state.profile.as_ref() {
             log::debug!(
@@ -523,261 +627,734 @@ async fn set_state(state: AppState) -> Result<(), TauriPlayerError> {
     }
 
     {
-        *CURRENT_PLAYBACK_TARGET.write().await = state.playback_target;
+        *core.current_playback_target.write().await = state.playback_target;
     }
 
     {
-        *CURRENT_SESSION_ID.write().await = state.current_session_id;
+        *core.current_session_id.write().await = state.current_session_id;
     }
 
     if state.current_session_id.is_some() {
-        update_playlist().await.map_err(|e| {
+        update_playlist(core).await.map_err(|e| {
             log::error!("Failed to update playlist: {e:?}");
-            TauriPlayerError::Unknown(e.to_string())
+            e
         })?;
     }
 
-    if updated_connection_details {
-        update_state().await?;
-    }
-
-    Ok(())
+    Ok(updated_connection_details)
 }
 
-#[async_recursion]
-pub async fn update_state() -> Result<(), TauriPlayerError> {
-    let has_connection_id = { CONNECTION_ID.read().await.is_some() };
-    log::debug!("update_state: has_connection_id={has_connection_id}");
-
-    if has_connection_id {
-        moosicbox_task::spawn("set_state: scan_outputs", async {
-            log::debug!("Attempting to scan_outputs...");
-            scan_outputs().await
-        });
-
-        let inited_upnp_players =
-            moosicbox_task::spawn("set_state: init_upnp_players", async move {
-                log::debug!("Attempting to init_upnp_players...");
-                init_upnp_players().await
-            });
+/// Saves the connection state and playback quality so `restore_settings` can pick them up on
+/// the next launch. Tokens go to the credential store, the rest to the settings file.
+async fn persist_settings(core: &AppCore) {
+    let connections = core.connections.read().await.clone();
 
-        let reinited_players = moosicbox_task::spawn("set_state: reinit_players", async move {
-            inited_upnp_players
-                .await
-                .map_err(|e| TauriPlayerError::Unknown(e.to_string()))?
-                .map_err(|e| TauriPlayerError::Unknown(e.to_string()))?;
-            log::debug!("Attempting to reinit_players...");
-            reinit_players().await
-        });
+    if let Some(store) = core.credentials() {
+        if let Err(e) = current_secrets(core).await.save(store, CURRENT_SCOPE) {
+            log::error!("Failed to persist credentials: {e:?}");
+        }
 
-        moosicbox_task::spawn("set_state: fetch_audio_zones", async move {
-            reinited_players
-                .await
-                .map_err(|e| TauriPlayerError::Unknown(e.to_string()))?
-                .map_err(|e| TauriPlayerError::Unknown(e.to_string()))?;
-            log::debug!("Attempting to fetch_audio_zones...");
-            fetch_audio_zones().await
-        });
+        for connection in connections.list() {
+            if let Err(e) = connection
+                .secrets()
+                .save(store, &connection_scope(connection.id))
+            {
+                log::error!(
+                    "Failed to persist credentials for connection {}: {e:?}",
+                    connection.id
+                );
+            }
+        }
     }
 
-    moosicbox_task::spawn("set_state: init_ws_connection", async move {
-        log::debug!("Attempting to init_ws_connection...");
-        init_ws_connection().await
-    });
+    let settings = Settings {
+        version: SETTINGS_VERSION,
+        state: AppState {
+            connection_id: core.connection_id.read().await.clone(),
+            connection_name: core.connection_name.read().await.clone(),
+            api_url: core.api_url.read().await.clone(),
+            client_id: core.client_id.read().await.clone(),
+            signature_token: core.signature_token.read().await.clone(),
+            api_token: core.api_token.read().await.clone(),
+            profile: core.profile.read().await.clone(),
+            playback_target: core.current_playback_target.read().await.clone(),
+            current_session_id: *core.current_session_id.read().await,
+            tls: core.tls_config.read().await.clone(),
+            ws_query_auth: *core.ws_query_auth.read().await,
+        },
+        playback_quality: *core.playback_quality.read().await,
+        connections,
+        http: core.http_config.read().await.clone(),
+        cache: core.http_cache_config.read().await.clone(),
+    };
 
-    Ok(())
+    if let Err(e) = core.save_settings(&settings) {
+        log::error!("Failed to persist settings: {e:?}");
+    }
 }
 
-async fn reinit_players() -> Result<(), TauriPlayerError> {
-    let mut players_map = ACTIVE_PLAYERS.write().await;
-    let ids = {
-        players_map
-            .iter()
-            .map(|x| {
-                (
-                    x.playback_target.clone(),
-                    x.session_id,
-                    x.player.clone(),
-                    x.player_type.clone(),
-                )
-            })
-            .collect::<Vec<_>>()
+/// Applies the persisted settings, so playback, UPnP and the WS connection come up without
+/// waiting for the frontend to call `set_state`
+async fn restore_settings(core: &AppCore) -> Result<(), TauriPlayerError> {
+    let settings = match core.load_settings() {
+        Ok(Some(settings)) => settings,
+        Ok(None) => {
+            log::debug!("restore_settings: no persisted settings");
+            return Ok(());
+        }
+        Err(e) => {
+            log::error!("Failed to load settings: {e:?}");
+            return Ok(());
+        }
     };
 
-    for (i, (playback_target, session_id, player, ptype)) in ids.into_iter().enumerate() {
-        let output = player.output.as_ref().unwrap().lock().unwrap().clone();
-        log::debug!("reinit_players: playback_target={playback_target:?} session_id={session_id} output={output:?}");
-        let mut created_player =
-            new_player(session_id, playback_target.clone(), output, ptype.clone()).await?;
+    log::debug!("restore_settings: version={}", settings.version);
 
-        let playback = player.playback.read().unwrap().clone();
+    let Settings {
+        mut state,
+        playback_quality,
+        mut connections,
+        http,
+        cache,
+        ..
+    } = settings;
 
-        if let Some(playback) = playback {
-            created_player
-                .update_playback(
-                    false,
-                    None,
-                    None,
-                    Some(playback.playing),
-                    Some(playback.position),
-                    Some(playback.progress),
-                    Some(playback.volume.load(std::sync::atomic::Ordering::SeqCst)),
-                    Some(playback.tracks.clone()),
-                    Some(playback.quality),
-                    Some(playback.session_id),
-                    Some(playback.profile),
-                    Some(playback_target.clone().into()),
-                    false,
-                    None,
-                )
-                .await?;
-        }
+    let mut has_plaintext_secrets = false;
 
-        players_map[i] = PlaybackTargetSessionPlayer {
-            playback_target,
-            session_id,
-            player: created_player,
-            player_type: ptype,
+    if let Some(store) = core.credentials() {
+        let secrets = ConnectionSecrets {
+            client_id: state.client_id.take(),
+            signature_token: state.signature_token.take(),
+            api_token: state.api_token.take(),
         };
+        has_plaintext_secrets |= secrets != ConnectionSecrets::default();
+        let secrets = restore_secrets(store, CURRENT_SCOPE, secrets);
+        state.client_id = secrets.client_id;
+        state.signature_token = secrets.signature_token;
+        state.api_token = secrets.api_token;
+
+        for connection in connections.list_mut() {
+            let secrets = connection.secrets();
+            has_plaintext_secrets |= secrets != ConnectionSecrets::default();
+            connection.set_secrets(restore_secrets(
+                store,
+                &connection_scope(connection.id),
+                secrets,
+            ));
+        }
+    }
+
+    if let Some(quality) = playback_quality {
+        core.playback_quality.write().await.replace(quality);
+    }
+
+    *core.connections.write().await = connections;
+    *core.http_config.write().await = http;
+
+    // The HTTP cache is opened with this size cap once the settings are restored
+    *core.http_cache_config.write().await = cache;
+
+    if apply_state(core, state).await? {
+        update_state(core).await?;
+    }
+
+    if has_plaintext_secrets {
+        log::debug!("restore_settings: moving plaintext tokens to the credential store");
+        persist_settings(core).await;
     }
 
     Ok(())
 }
 
-async fn set_audio_zone_active_players(
-    session_id: u64,
-    audio_zone_id: u64,
-    players: Vec<(ApiPlayer, PlayerType, AudioOutputFactory)>,
-) -> Result<(), TauriPlayerError> {
-    log::debug!(
-        "set_audio_zone_active_players: session_id={session_id} audio_zone_id={audio_zone_id} {:?}",
-        players.iter().map(|(x, _, _)| x).collect::<Vec<_>>()
-    );
+/// The stored secrets for `scope`, unless the settings file still has `secrets` in plaintext
+/// from before tokens were moved to the credential store
+fn restore_secrets(
+    store: &CredentialStore,
+    scope: &str,
+    secrets: ConnectionSecrets,
+) -> ConnectionSecrets {
+    if secrets != ConnectionSecrets::default() {
+        return secrets;
+    }
 
-    let mut api_players_map = AUDIO_ZONE_ACTIVE_API_PLAYERS.write().await;
-    api_players_map.insert(audio_zone_id, players.clone());
+    ConnectionSecrets::load(store, scope).unwrap_or_else(|e| {
+        log::error!("Failed to load credentials for {scope}: {e:?}");
+        secrets
+    })
+}
 
-    {
-        let mut players_map = ACTIVE_PLAYERS.write().await;
-        for (player, ptype, output) in players.iter() {
-            if let Some(existing) = players_map.iter().find(|x| match x.playback_target {
-                ApiPlaybackTarget::AudioZone { audio_zone_id: id } => id == audio_zone_id,
-                _ => false,
-            }) {
-                let different_session = {
-                    !existing
-                        .player
-                        .playback
-                        .read()
-                        .unwrap()
-                        .as_ref()
-                        .is_some_and(|p| p.session_id == session_id)
-                };
+async fn current_secrets(core: &AppCore) -> ConnectionSecrets {
+    ConnectionSecrets {
+        client_id: core.client_id.read().await.clone(),
+        signature_token: core.signature_token.read().await.clone(),
+        api_token: core.api_token.read().await.clone(),
+    }
+}
 
-                let same_output = existing
-                    .player
-                    .output
-                    .as_ref()
-                    .is_some_and(|output| output.lock().unwrap().id == player.audio_output_id);
+/// Copies the current connection details into the active saved connection when they belong to
+/// the same server
+async fn sync_active_connection(core: &AppCore) {
+    let api_url = { core.api_url.read().await.clone() };
 
-                if !different_session && same_output {
-                    log::debug!(
-                        "Skipping existing player for audio_zone_id={audio_zone_id} audio_output_id={}",
-                        player.audio_output_id
-                    );
-                    continue;
-                }
-            }
+    let mut connections = core.connections.write().await;
+    let Some(active) = connections.active_mut() else {
+        return;
+    };
 
-            let playback_target = ApiPlaybackTarget::AudioZone { audio_zone_id };
-            let player = new_player(
-                session_id,
-                playback_target.clone(),
-                output.clone(),
-                ptype.clone(),
-            )
-            .await?;
+    if api_url.as_ref() != Some(&active.api_url) {
+        return;
+    }
+
+    active.connection_id = core.connection_id.read().await.clone();
+    active.client_id = core.client_id.read().await.clone();
+    active.signature_token = core.signature_token.read().await.clone();
+    active.api_token = core.api_token.read().await.clone();
+    active.profile = core.profile.read().await.clone();
+    active.playback_target = core.current_playback_target.read().await.clone();
+    active.tls = core.tls_config.read().await.clone();
+    active.ws_query_auth = *core.ws_query_auth.read().await;
+}
+
+/// Closes the WS connection, pauses and drops every player and clears the connection and
+/// session state, so the next `apply_state` starts from scratch
+async fn teardown_connection(core: &AppCore) -> Result<(), CloseWsError> {
+    log::debug!("teardown_connection: closing ws connection");
+    close_ws_connection(core).await?;
+
+    if let Some(token) = core.ws_token.write().await.take() {
+        token.cancel();
+    }
+    core.ws_handle.write().await.take();
+    core.ws_connection_id.write().await.take();
+    core.ws_url.write().await.take();
+    core.ws_message_buffer.write().await.clear();
+
+    log::debug!("teardown_connection: stopping players");
+    let players = {
+        core.active_players
+            .write()
+            .await
+            .drain(..)
+            .collect::<Vec<_>>()
+    };
+
+    for x in players {
+        let mut player = x.player;
+        if let Err(e) = player.pause(None).await {
             log::debug!(
-                "set_audio_zone_active_players: audio_zone_id={audio_zone_id} session_id={session_id:?}"
+                "teardown_connection: failed to pause player {}: {e:?}",
+                player.id
             );
-            let playback_target_session_player = PlaybackTargetSessionPlayer {
-                playback_target,
-                session_id,
-                player,
-                player_type: ptype.clone(),
-            };
-            if let Some((i, _)) =
-                players_map
-                    .iter()
-                    .enumerate()
-                    .find(|(_, x)| match x.playback_target {
-                        ApiPlaybackTarget::AudioZone { audio_zone_id: id } => {
-                            id == audio_zone_id && x.session_id == session_id
-                        }
-                        _ => false,
-                    })
-            {
-                players_map[i] = playback_target_session_player;
-            } else {
-                players_map.push(playback_target_session_player);
-            }
         }
     }
 
+    core.audio_zone_active_api_players.write().await.clear();
+    core.current_players.write().await.clear();
+    core.pending_player_sessions.write().await.clear();
+    core.current_sessions.write().await.clear();
+    core.current_audio_zones.write().await.clear();
+    core.current_connections.write().await.clear();
+    core.upnp_av_transport_services.write().await.clear();
+
+    core.connection_id.write().await.take();
+    core.connection_name.write().await.take();
+    core.api_url.write().await.take();
+    core.client_id.write().await.take();
+    core.signature_token.write().await.take();
+    core.api_token.write().await.take();
+    core.profile.write().await.take();
+    core.tls_config.write().await.take();
+    *core.ws_query_auth.write().await = false;
+    core.current_playback_target.write().await.take();
+    core.current_session_id.write().await.take();
+
     Ok(())
 }
 
-async fn update_audio_zones() -> Result<(), TauriPlayerError> {
-    let audio_zones_binding = CURRENT_AUDIO_ZONES.read().await;
-    let audio_zones: &[ApiAudioZoneWithSession] = audio_zones_binding.as_ref();
-    let players_binding = CURRENT_PLAYERS.read().await;
-    let players: &[(ApiPlayer, PlayerType, AudioOutputFactory)] = players_binding.as_ref();
-
+/// Tears down the current connection, then applies `connection`'s state and brings it up in
+/// order: outputs are scanned, players initialized and the WS connected before this returns.
+/// Callers hold `core.connection_switch` from updating the registry until this returns.
+async fn switch_connection(
+    core: &AppCore,
+    connection: Option<&SavedConnection>,
+) -> Result<(), TauriPlayerError> {
     log::debug!(
-        "\
-        Updating audio zones\n\t\
-        audio_zones={audio_zones:?}\n\t\
-        players={:?}\n\t\
-        ",
-        players.iter().map(|(x, _, _)| x).collect::<Vec<_>>()
+        "switch_connection: connection={:?}",
+        connection.map(|x| x.id)
     );
 
-    for audio_zone in audio_zones {
-        let players = audio_zone
-            .players
-            .clone()
-            .into_iter()
-            .filter_map(|x| {
-                players
-                    .iter()
-                    .find(|(p, _, _)| p.player_id == x.player_id)
-                    .map(|(_, ptype, output)| (x, ptype.clone(), output.clone()))
-            })
-            .collect::<Vec<_>>();
+    teardown_connection(core).await?;
 
-        if !players.is_empty() {
-            set_audio_zone_active_players(audio_zone.session_id, audio_zone.id, players).await?;
-        }
+    apply_state(core, connection.map(Into::into).unwrap_or_default()).await?;
+    persist_settings(core).await;
+
+    if connection.is_some() {
+        connect(core).await?;
     }
+
     Ok(())
 }
 
-async fn update_connection_outputs(session_ids: &[u64]) -> Result<(), TauriPlayerError> {
-    let Some(current_connection_id) = ({ CONNECTION_ID.read().await.clone() }) else {
-        return Ok(());
-    };
+/// Brings up the current connection, waiting for each step in turn
+async fn connect(core: &AppCore) -> Result<(), TauriPlayerError> {
+    log::debug!("connect: scanning outputs");
+    scan_outputs(core).await?;
+    log::debug!("connect: initializing players");
+    init_upnp_players(core).await?;
+    reinit_players(core).await?;
+    log::debug!("connect: connecting to ws");
+    init_ws_connection(core).await?;
+
+    if let Err(e) = fetch_audio_zones(core).await {
+        log::error!("connect: failed to fetch audio zones: {e:?}");
+    }
 
-    let local_outputs = moosicbox_audio_output::output_factories().await;
-    let upnp_outputs = UPNP_AV_TRANSPORT_SERVICES
+    Ok(())
+}
+
+#[tauri::command]
+async fn list_connections(
+    core: tauri::State<'_, AppCore>,
+) -> Result<Vec<SavedConnection>, TauriPlayerError> {
+    Ok(core.connections.read().await.list().to_vec())
+}
+
+#[tauri::command]
+async fn active_connection(
+    core: tauri::State<'_, AppCore>,
+) -> Result<Option<SavedConnection>, TauriPlayerError> {
+    Ok(core.connections.read().await.active().cloned())
+}
+
+#[tauri::command]
+async fn add_connection(
+    core: tauri::State<'_, AppCore>,
+    connection: NewConnection,
+) -> Result<SavedConnection, TauriPlayerError> {
+    let connection = core.connections.write().await.add(connection);
+    persist_settings(&core).await;
+
+    Ok(connection)
+}
+
+#[tauri::command]
+async fn rename_connection(
+    core: tauri::State<'_, AppCore>,
+    id: u64,
+    name: String,
+) -> Result<SavedConnection, TauriPlayerError> {
+    let connection = core.connections.write().await.rename(id, name)?;
+
+    if core
+        .connections
         .read()
         .await
-        .iter()
-        .cloned()
-        .map(|x| x.try_into())
-        .collect::<Result<Vec<AudioOutputFactory>, AudioOutputError>>()
-        .map_err(|e| TauriPlayerError::Unknown(format!("Error: {e:?}")))?;
+        .active()
+        .is_some_and(|x| x.id == id)
+    {
+        if let Some(layer) = core.log_layer() {
+            layer.set_property("connectionName", connection.name.clone().into());
+        }
+        *core.connection_name.write().await = Some(connection.name.clone());
+    }
 
-    let outputs = [local_outputs, upnp_outputs].concat();
+    persist_settings(&core).await;
+
+    Ok(connection)
+}
+
+/// Removes a saved connection. Removing the active connection disconnects from its server.
+#[tauri::command]
+async fn remove_connection(
+    core: tauri::State<'_, AppCore>,
+    id: u64,
+) -> Result<SavedConnection, TauriPlayerError> {
+    let _switch = core.connection_switch.lock().await;
+
+    let was_active = {
+        core.connections
+            .read()
+            .await
+            .active()
+            .is_some_and(|x| x.id == id)
+    };
+
+    let connection = core.connections.write().await.remove(id)?;
+
+    if let Some(store) = core.credentials() {
+        if let Err(e) = ConnectionSecrets::delete(store, &connection_scope(id)) {
+            log::error!("Failed to delete credentials for connection {id}: {e:?}");
+        }
+    }
+
+    if was_active {
+        switch_connection(&core, None).await?;
+    } else {
+        persist_settings(&core).await;
+    }
+
+    Ok(connection)
+}
+
+#[tauri::command]
+async fn activate_connection(
+    core: tauri::State<'_, AppCore>,
+    id: u64,
+) -> Result<SavedConnection, TauriPlayerError> {
+    let _switch = core.connection_switch.lock().await;
+    let connection = core.connections.write().await.activate(id)?;
+
+    switch_connection(&core, Some(&connection)).await?;
+
+    Ok(connection)
+}
+
+/// Which tokens the current connection has. The tokens themselves never leave the backend.
+#[tauri::command]
+async fn credential_status(
+    core: tauri::State<'_, AppCore>,
+) -> Result<CredentialStatus, TauriPlayerError> {
+    Ok(current_secrets(&core).await.status())
+}
+
+/// Forgets the current connection's tokens, both in memory and in the credential store, and
+/// reconnects without them
+#[tauri::command]
+async fn clear_credentials(core: tauri::State<'_, AppCore>) -> Result<(), TauriPlayerError> {
+    log::debug!("clear_credentials");
+
+    core.client_id.write().await.take();
+    core.signature_token.write().await.take();
+    core.api_token.write().await.take();
+
+    if let Some(store) = core.credentials() {
+        ConnectionSecrets::delete(store, CURRENT_SCOPE)?;
+    }
+
+    sync_active_connection(&core).await;
+    persist_settings(&core).await;
+    update_state(&core).await?;
+
+    Ok(())
+}
+
+/// Pins a certificate reported by the `tls-trust-required` event and reconnects with it
+#[tauri::command]
+async fn trust_certificate(
+    core: tauri::State<'_, AppCore>,
+    fingerprint: String,
+) -> Result<TlsConfig, TauriPlayerError> {
+    log::debug!("trust_certificate: fingerprint={fingerprint}");
+
+    let tls_config = {
+        let mut tls_config = core.tls_config.write().await;
+        let config = tls_config.get_or_insert_with(TlsConfig::default);
+        config.pin(&fingerprint);
+        config.clone()
+    };
+
+    update_state(&core).await?;
+    persist_settings(&core).await;
+
+    Ok(tls_config)
+}
+
+/// The rustls config for the current connection, or `None` to use the default web PKI roots
+async fn tls_client_config(core: &AppCore) -> Result<Option<Arc<ClientConfig>>, TlsError> {
+    let Some(tls_config) = core.tls_config.read().await.clone() else {
+        return Ok(None);
+    };
+
+    if tls_config.is_default() {
+        return Ok(None);
+    }
+
+    let on_untrusted: UntrustedCertificateCallback = {
+        let core = core.clone();
+        Arc::new(move |certificate: UntrustedCertificate| {
+            log::debug!("tls_client_config: untrusted certificate {certificate:?}");
+            if let Err(e) = core.emit("tls-trust-required", certificate) {
+                log::error!("Failed to emit tls-trust-required: {e:?}");
+            }
+        })
+    };
+
+    tls_config.client_config(Some(on_untrusted)).map(Some)
+}
+
+#[async_recursion]
+pub async fn update_state(core: &AppCore) -> Result<(), TauriPlayerError> {
+    let has_connection_id = { core.connection_id.read().await.is_some() };
+    log::debug!("update_state: has_connection_id={has_connection_id}");
+
+    if has_connection_id {
+        moosicbox_task::spawn("set_state: scan_outputs", {
+            let core = core.clone();
+            async move {
+                log::debug!("Attempting to scan_outputs...");
+                scan_outputs(&core).await
+            }
+        });
+
+        let inited_upnp_players = moosicbox_task::spawn("set_state: init_upnp_players", {
+            let core = core.clone();
+            async move {
+                log::debug!("Attempting to init_upnp_players...");
+                init_upnp_players(&core).await
+            }
+        });
+
+        let reinited_players = moosicbox_task::spawn("set_state: reinit_players", {
+            let core = core.clone();
+            async move {
+                inited_upnp_players.await??;
+                log::debug!("Attempting to reinit_players...");
+                reinit_players(&core).await
+            }
+        });
+
+        moosicbox_task::spawn("set_state: fetch_audio_zones", {
+            let core = core.clone();
+            async move {
+                reinited_players.await.map_err(TauriPlayerError::from)??;
+                log::debug!("Attempting to fetch_audio_zones...");
+                fetch_audio_zones(&core).await
+            }
+        });
+    }
+
+    moosicbox_task::spawn("set_state: init_ws_connection", {
+        let core = core.clone();
+        async move {
+            log::debug!("Attempting to init_ws_connection...");
+            init_ws_connection(&core).await
+        }
+    });
+
+    Ok(())
+}
+
+async fn reinit_players(core: &AppCore) -> Result<(), TauriPlayerError> {
+    let mut players_map = core.active_players.write().await;
+
+    for x in players_map.iter_mut() {
+        *x = recreate_player(core, x).await?;
+    }
+
+    Ok(())
+}
+
+/// Rebuilds `player` with the current credentials and keeps its playback. Other players are
+/// left alone.
+async fn reinit_player(
+    core: &AppCore,
+    player: &PlaybackHandler,
+) -> Result<PlaybackHandler, TauriPlayerError> {
+    let mut players_map = core.active_players.write().await;
+
+    let Some(x) = players_map.iter_mut().find(|x| x.player.id == player.id) else {
+        return Ok(player.clone());
+    };
+
+    *x = recreate_player(core, x).await?;
+
+    Ok(x.player.clone())
+}
+
+async fn recreate_player(
+    core: &AppCore,
+    existing: &PlaybackTargetSessionPlayer,
+) -> Result<PlaybackTargetSessionPlayer, TauriPlayerError> {
+    let PlaybackTargetSessionPlayer {
+        playback_target,
+        session_id,
+        player,
+        player_type: ptype,
+    } = existing;
+    let session_id = *session_id;
+
+    let output = player.output.as_ref().unwrap().lock().unwrap().clone();
+    log::debug!("reinit_players: playback_target={playback_target:?} session_id={session_id} output={output:?}");
+    let mut created_player = new_player(
+        core,
+        session_id,
+        playback_target.clone(),
+        output,
+        ptype.clone(),
+    )
+    .await?;
+
+    let playback = player.playback.read().unwrap().clone();
+
+    if let Some(playback) = playback {
+        created_player
+            .update_playback(
+                false,
+                None,
+                None,
+                Some(playback.playing),
+                Some(playback.position),
+                Some(playback.progress),
+                Some(playback.volume.load(std::sync::atomic::Ordering::SeqCst)),
+                Some(playback.tracks.clone()),
+                Some(playback.quality),
+                Some(playback.session_id),
+                Some(playback.profile),
+                Some(playback_target.clone().into()),
+                false,
+                None,
+            )
+            .await?;
+    }
+
+    Ok(PlaybackTargetSessionPlayer {
+        playback_target: playback_target.clone(),
+        session_id,
+        player: created_player,
+        player_type: ptype.clone(),
+    })
+}
+
+async fn set_audio_zone_active_players(
+    core: &AppCore,
+    session_id: u64,
+    audio_zone_id: u64,
+    players: Vec<(ApiPlayer, PlayerType, AudioOutputFactory)>,
+) -> Result<(), TauriPlayerError> {
+    log::debug!(
+        "set_audio_zone_active_players: session_id={session_id} audio_zone_id={audio_zone_id} {:?}",
+        players.iter().map(|(x, _, _)| x).collect::<Vec<_>>()
+    );
+
+    let mut api_players_map = core.audio_zone_active_api_players.write().await;
+    api_players_map.insert(audio_zone_id, players.clone());
+
+    {
+        let mut players_map = core.active_players.write().await;
+        for (player, ptype, output) in players.iter() {
+            if let Some(existing) = players_map.iter().find(|x| match x.playback_target {
+                ApiPlaybackTarget::AudioZone { audio_zone_id: id } => id == audio_zone_id,
+                _ => false,
+            }) {
+                let different_session = {
+                    !existing
+                        .player
+                        .playback
+                        .read()
+                        .unwrap()
+                        .as_ref()
+                        .is_some_and(|p| p.session_id == session_id)
+                };
+
+                let same_output = existing
+                    .player
+                    .output
+                    .as_ref()
+                    .is_some_and(|output| output.lock().unwrap().id == player.audio_output_id);
+
+                if !different_session && same_output {
+                    log::debug!(
+                        "Skipping existing player for audio_zone_id={audio_zone_id} audio_output_id={}",
+                        player.audio_output_id
+                    );
+                    continue;
+                }
+            }
+
+            let playback_target = ApiPlaybackTarget::AudioZone { audio_zone_id };
+            let player = new_player(
+                core,
+                session_id,
+                playback_target.clone(),
+                output.clone(),
+                ptype.clone(),
+            )
+            .await?;
+            log::debug!(
+                "set_audio_zone_active_players: audio_zone_id={audio_zone_id} session_id={session_id:?}"
+            );
+            let playback_target_session_player = PlaybackTargetSessionPlayer {
+                playback_target,
+                session_id,
+                player,
+                player_type: ptype.clone(),
+            };
+            if let Some((i, _)) =
+                players_map
+                    .iter()
+                    .enumerate()
+                    .find(|(_, x)| match x.playback_target {
+                        ApiPlaybackTarget::AudioZone { audio_zone_id: id } => {
+                            id == audio_zone_id && x.session_id == session_id
+                        }
+                        _ => false,
+                    })
+            {
+                players_map[i] = playback_target_session_player;
+            } else {
+                players_map.push(playback_target_session_player);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn update_audio_zones(core: &AppCore) -> Result<(), TauriPlayerError> {
+    let audio_zones_binding = core.current_audio_zones.read().await;
+    let audio_zones: &[ApiAudioZoneWithSession] = audio_zones_binding.as_ref();
+    let players_binding = core.current_players.read().await;
+    let players: &[(ApiPlayer, PlayerType, AudioOutputFactory)] = players_binding.as_ref();
+
+    log::debug!(
+        "\
+        Updating audio zones\n\t\
+        audio_zones={audio_zones:?}\n\t\
+        players={:?}\n\t\
+        ",
+        players.iter().map(|(x, _, _)| x).collect::<Vec<_>>()
+    );
+
+    for audio_zone in audio_zones {
+        let players = audio_zone
+            .players
+            .clone()
+            .into_iter()
+            .filter_map(|x| {
+                players
+                    .iter()
+                    .find(|(p, _, _)| p.player_id == x.player_id)
+                    .map(|(_, ptype, output)| (x, ptype.clone(), output.clone()))
+            })
+            .collect::<Vec<_>>();
+
+        if !players.is_empty() {
+            set_audio_zone_active_players(core, audio_zone.session_id, audio_zone.id, players)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn update_connection_outputs(
+    core: &AppCore,
+    session_ids: &[u64],
+) -> Result<(), TauriPlayerError> {
+    let Some(current_connection_id) = ({ core.connection_id.read().await.clone() }) else {
+        return Ok(());
+    };
+
+    let local_outputs = moosicbox_audio_output::output_factories().await;
+    let upnp_outputs = core
+        .upnp_av_transport_services
+        .read()
+        .await
+        .iter()
+        .cloned()
+        .map(|x| x.try_into())
+        .collect::<Result<Vec<AudioOutputFactory>, AudioOutputError>>()?;
+
+    let outputs = [local_outputs, upnp_outputs].concat();
 
     for output in outputs {
         let playback_target = ApiPlaybackTarget::ConnectionOutput {
@@ -787,7 +1364,7 @@ async fn update_connection_outputs(session_ids: &[u64]) -> Result<(), TauriPlaye
         let output_id = &output.id;
         log::debug!("update_connection_outputs: ApiPlaybackTarget::ConnectionOutput current_connection_id={current_connection_id} output_id={output_id}");
 
-        let binding = CURRENT_PLAYERS.read().await;
+        let binding = core.current_players.read().await;
         let current_players: &[(ApiPlayer, PlayerType, AudioOutputFactory)] = binding.as_ref();
 
         if let Some((_player, ptype, output)) = current_players.iter().find(|(x, _, _)| {
@@ -802,6 +1379,7 @@ async fn update_connection_outputs(session_ids: &[u64]) -> Result<(), TauriPlaye
                 log::debug!("update_connection_outputs: ApiPlaybackTarget::ConnectionOutput creating player for output_id={output_id} session_id={session_id} playback_target={playback_target:?}");
 
                 let player = new_player(
+                    core,
                     session_id,
                     playback_target.clone(),
                     output.clone(),
@@ -820,7 +1398,7 @@ async fn update_connection_outputs(session_ids: &[u64]) -> Result<(), TauriPlaye
                     player_type: ptype.clone(),
                 };
 
-                let mut players = ACTIVE_PLAYERS.write().await;
+                let mut players = core.active_players.write().await;
 
                 if !players.iter().any(|x| x.session_id == session_id && x.playback_target == playback_target) {
                     players.push(player);
@@ -833,12 +1411,13 @@ async fn update_connection_outputs(session_ids: &[u64]) -> Result<(), TauriPlaye
 }
 
 async fn get_players(
+    core: &AppCore,
     session_id: u64,
     playback_target: Option<&ApiPlaybackTarget>,
 ) -> Result<Vec<PlaybackHandler>, TauriPlayerError> {
     let players = {
         let mut playback_handlers = vec![];
-        let active_players = ACTIVE_PLAYERS.read().await;
+        let active_players = core.active_players.read().await;
 
         for player in active_players.iter() {
             let target = &player.playback_target;
@@ -911,15 +1490,19 @@ impl From<TrackIdWithApiSource> for UpdateSessionPlaylistTrack {
 }
 
 #[tauri::command]
-async fn set_playback_quality(quality: PlaybackQuality) -> Result<(), TauriPlayerError> {
+async fn set_playback_quality(
+    core: tauri::State<'_, AppCore>,
+    quality: PlaybackQuality,
+) -> Result<(), TauriPlayerError> {
     log::debug!("Setting playback quality: {quality:?}");
 
-    PLAYBACK_QUALITY.write().await.replace(quality);
+    core.playback_quality.write().await.replace(quality);
+    persist_settings(&core).await;
 
-    let mut binding = ACTIVE_PLAYERS.write().await;
+    let mut binding = core.active_players.write().await;
     let players = binding.iter_mut();
 
-    let profile = { PROFILE.read().await.clone() };
+    let profile = { core.profile.read().await.clone() };
 
     for x in players {
         x.player
@@ -932,7 +1515,7 @@ async fn set_playback_quality(quality: PlaybackQuality) -> Result<(), TauriPlaye
                 None,
                 None,
                 None,
-                *PLAYBACK_QUALITY.read().await,
+                *core.playback_quality.read().await,
                 Some(x.session_id),
                 profile.clone(),
                 Some(x.playback_target.clone().into()),
@@ -951,57 +1534,165 @@ pub enum SendWsMessageError {
     WebsocketSend(#[from] WebsocketSendError),
     #[error(transparent)]
     HandleWsMessage(#[from] HandleWsMessageError),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+fn ws_message_priority(message: &InboundPayload) -> Priority {
+    match message {
+        InboundPayload::GetConnectionId(_) | InboundPayload::SetSeek(_) => Priority::High,
+        _ => Priority::Normal,
+    }
 }
 
 async fn send_ws_message(
+    core: &AppCore,
     handle: &WsHandle,
     message: InboundPayload,
     handle_update: bool,
-) -> Result<(), SendWsMessageError> {
-    log::debug!("send_ws_message: handle_update={handle_update} message={message:?}");
-
-    if handle_update {
-        let message = message.clone();
-        moosicbox_task::spawn("send_ws_message: handle_update", async move {
-            match &message {
-                InboundPayload::UpdateSession(payload) => {
-                    handle_playback_update(&payload.payload.clone().into()).await?;
-                }
-                InboundPayload::SetSeek(payload) => {
-                    handle_playback_update(&ApiUpdateSession {
-                        session_id: payload.payload.session_id,
-                        profile: payload.payload.profile.clone(),
-                        playback_target: payload.payload.playback_target.clone(),
-                        play: None,
-                        stop: None,
-                        name: None,
-                        active: None,
-                        playing: None,
-                        position: None,
-                        seek: Some(payload.payload.seek as f64),
-                        volume: None,
-                        playlist: None,
-                        quality: None,
-                    })
-                    .await?;
-                }
-                _ => {}
-            }
+) -> Result<DeliveryReceipt, SendWsMessageError> {
+    log::debug!(
+        "send_ws_message: handle_update={handle_update} message={:?}",
+        Redacted(&message)
+    );
 
-            Ok::<_, HandleWsMessageError>(())
-        });
+    let update = match &message {
+        InboundPayload::UpdateSession(payload) if handle_update => {
+            Some(payload.payload.clone().into())
+        }
+        InboundPayload::SetSeek(payload) if handle_update => Some(ApiUpdateSession {
+            session_id: payload.payload.session_id,
+            profile: payload.payload.profile.clone(),
+            playback_target: payload.payload.playback_target.clone(),
+            play: None,
+            stop: None,
+            name: None,
+            active: None,
+            playing: None,
+            position: None,
+            seek: Some(payload.payload.seek as f64),
+            volume: None,
+            playlist: None,
+            quality: None,
+        }),
+        _ => None,
+    };
+
+    // Applied in the session's lane, in order with the updates of it from the server
+    if let Some(update) = update {
+        core.ws_message_dispatcher
+            .dispatch(WsMessageKey::Session(update.session_id), {
+                let core = core.clone();
+                async move {
+                    if let Err(e) = handle_playback_update(&core, &update).await {
+                        log::error!("send_ws_message: Failed to handle update: {e:?}");
+                    }
+                }
+            });
     }
 
-    handle
-        .send(&serde_json::to_string(&message).unwrap())
+    let priority = ws_message_priority(&message);
+
+    Ok(handle
+        .send_message(
+            WsMessage::TextMessage(serde_json::to_string(&message)?),
+            priority,
+        )
+        .await?)
+}
+
+#[derive(Debug, Error)]
+pub enum WsRequestError {
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Request(#[from] RequestError),
+}
+
+/// Sends `payload` tagged with a `requestId` and waits for the matching response.
+///
+/// Responses that echo the `requestId` are matched on it. Otherwise the first response for
+/// which `is_response` returns `true` is used.
+async fn ws_request(
+    handle: &WsHandle,
+    payload: InboundPayload,
+    is_response: fn(&OutboundPayload) -> bool,
+    timeout: std::time::Duration,
+) -> Result<OutboundPayload, WsRequestError> {
+    log::debug!("ws_request: payload={:?}", Redacted(&payload));
+
+    let response = handle
+        .request_or_match(
+            &payload,
+            move |value| OutboundPayload::deserialize(value).is_ok_and(|x| is_response(&x)),
+            timeout,
+        )
         .await?;
 
+    Ok(serde_json::from_value(response)?)
+}
+
+async fn on_ws_connected(core: &AppCore, handle: &WsHandle) -> Result<(), AppError> {
+    *core.ws_connect_pending.write().await = false;
+
+    log::debug!("Sending GetConnectionId");
+    let response = match ws_request(
+        handle,
+        InboundPayload::GetConnectionId(EmptyPayload {}),
+        |x| matches!(x, OutboundPayload::ConnectionId(_)),
+        WS_REQUEST_TIMEOUT,
+    )
+    .await
+    {
+        Ok(response) => response,
+        Err(e) => {
+            // `handle_ws_message` emits `ws-connect` if the connection id still arrives
+            *core.ws_connect_pending.write().await = true;
+            return Err(e.into());
+        }
+    };
+
+    let OutboundPayload::ConnectionId(payload) = response else {
+        return Err(AppError::UnexpectedWsResponse(format!(
+            "GetConnectionId got {response:?}"
+        )));
+    };
+
+    core.ws_connection_id
+        .write()
+        .await
+        .replace(payload.connection_id.to_owned());
+
+    log::debug!("Sending GetSessions");
+    if let Err(e) = ws_request(
+        handle,
+        InboundPayload::GetSessions(EmptyPayload {}),
+        |x| matches!(x, OutboundPayload::Sessions(_)),
+        WS_REQUEST_TIMEOUT,
+    )
+    .await
+    {
+        log::error!("Failed to get sessions: {e:?}");
+    }
+
+    emit_ws_connect(core, payload.connection_id).await?;
+
     Ok(())
 }
 
-async fn flush_ws_message_buffer() -> Result<(), SendWsMessageError> {
-    if let Some(handle) = WS_HANDLE.read().await.as_ref() {
-        let mut binding = WS_MESSAGE_BUFFER.write().await;
+async fn emit_ws_connect(core: &AppCore, connection_id: String) -> Result<(), tauri::Error> {
+    core.emit(
+        "ws-connect",
+        WsConnectMessage {
+            connection_id,
+            ws_url: core.ws_url.read().await.to_owned().unwrap_or_default(),
+        },
+    )
+}
+
+async fn flush_ws_message_buffer(core: &AppCore) -> Result<(), SendWsMessageError> {
+    if let Some(handle) = core.ws_handle.read().await.as_ref() {
+        let mut binding = core.ws_message_buffer.write().await;
         log::debug!(
             "flush_ws_message_buffer: Flushing {} ws messages from buffer",
             binding.len()
@@ -1010,7 +1701,7 @@ async fn flush_ws_message_buffer() -> Result<(), SendWsMessageError> {
         let messages = binding.drain(..);
 
         for message in messages {
-            send_ws_message(handle, message, true).await?;
+            send_ws_message(core, handle, message, true).await?;
         }
     } else {
         log::debug!("flush_ws_message_buffer: No WS_HANDLE");
@@ -1020,39 +1711,72 @@ async fn flush_ws_message_buffer() -> Result<(), SendWsMessageError> {
 }
 
 #[tauri::command]
-async fn propagate_ws_message(message: InboundPayload) -> Result<(), TauriPlayerError> {
+async fn ws_compression_stats(
+    core: tauri::State<'_, AppCore>,
+) -> Result<Option<WsCompressionStats>, TauriPlayerError> {
+    Ok(core
+        .ws_handle
+        .read()
+        .await
+        .as_ref()
+        .map(|handle| handle.compression_stats().into()))
+}
+
+#[tauri::command]
+async fn propagate_ws_message(
+    core: tauri::State<'_, AppCore>,
+    message: InboundPayload,
+) -> Result<(), TauriPlayerError> {
     moosicbox_logging::debug_or_trace!(
-        ("propagate_ws_message: received ws message from frontend: {message}"),
-        ("propagate_ws_message: received ws message from frontend: {message:?}")
+        (
+            "propagate_ws_message: received ws message from frontend: {}",
+            Redacted(&message)
+        ),
+        (
+            "propagate_ws_message: received ws message from frontend: {:?}",
+            Redacted(&message)
+        )
     );
 
-    moosicbox_task::spawn("propagate_ws_message", async move {
-        let handle = { WS_HANDLE.read().await.clone() };
+    // Queued before returning, so messages are sent and applied in the order they were
+    // propagated. Only waiting for the delivery happens in the background.
+    let handle = { core.ws_handle.read().await.clone() };
 
-        if let Some(handle) = handle {
-            send_ws_message(&handle, message, true).await?;
-        } else {
-            moosicbox_logging::debug_or_trace!(
-                ("propagate_ws_message: pushing message to buffer: {message}"),
-                ("propagate_ws_message: pushing message to buffer: {message:?}")
-            );
-            WS_MESSAGE_BUFFER.write().await.push(message);
-        }
+    if let Some(handle) = handle {
+        let receipt = send_ws_message(&core, &handle, message, true).await?;
 
-        Ok::<_, SendWsMessageError>(())
-    });
+        moosicbox_task::spawn("propagate_ws_message: delivery", async move {
+            if let Err(e) = receipt.delivered().await {
+                log::error!("propagate_ws_message: Failed to deliver ws message: {e:?}");
+            }
+        });
+    } else {
+        moosicbox_logging::debug_or_trace!(
+            (
+                "propagate_ws_message: pushing message to buffer: {}",
+                Redacted(&message)
+            ),
+            (
+                "propagate_ws_message: pushing message to buffer: {:?}",
+                Redacted(&message)
+            )
+        );
+        core.ws_message_buffer.write().await.push(message);
+    }
 
     Ok(())
 }
 
 async fn send_request_builder(
+    core: &AppCore,
     builder: RequestBuilder,
 ) -> Result<serde_json::Value, TauriPlayerError> {
     log::debug!("send_request_builder: Sending request");
-    match builder.send().await {
+    match http::send(core, builder).await {
         Ok(resp) => {
             log::debug!("send_request_builder: status_code={}", resp.status());
-            let success = resp.status().is_success();
+            let status = resp.status();
+            let success = status.is_success();
             match resp.text().await {
                 Ok(text) => {
                     if success {
@@ -1063,97 +1787,300 @@ async fn send_request_builder(
                             }
                             Err(e) => {
                                 log::error!("Failed to parse request response: {e:?} ({text:?})");
-                                Err(TauriPlayerError::Unknown(format!("Json failed: {e:?}")))
+                                Err(e.into())
                             }
                         }
+                    } else if auth::is_unauthorized_status(status) {
+                        log::error!("Unauthorized response: status={status} ({text:?})");
+                        Err(TauriPlayerError::Unauthorized(status.as_u16()))
                     } else {
                         log::error!("Failure response: ({text:?})");
-                        Err(TauriPlayerError::Unknown(format!(
-                            "Request failed: {text:?}"
-                        )))
+                        Err(TauriPlayerError::Http {
+                            status: status.as_u16(),
+                            body: text,
+                        })
                     }
                 }
                 Err(e) => {
-                    log::error!("Failed to parse request response: {e:?}");
-                    Err(TauriPlayerError::Unknown(format!("Json failed: {e:?}")))
+                    log::error!("Failed to read request response: {e:?}");
+                    Err(e.into())
                 }
             }
         }
         Err(e) => {
             log::error!("Failed to send request: {e:?}");
-            Err(TauriPlayerError::Unknown(format!("Json failed: {e:?}")))
+            Err(e)
         }
     }
 }
 
+/// Served through the HTTP cache. `request_id` allows aborting the request with
+/// `cancel_request`.
 #[tauri::command]
 async fn api_proxy_get(
+    core: tauri::State<'_, AppCore>,
+    url: String,
+    headers: Option<serde_json::Value>,
+    request_id: Option<String>,
+) -> Result<serde_json::Value, TauriPlayerError> {
+    let response = http::cancellable(&core, request_id, cache::get(&core, url, headers)).await?;
+
+    Ok(response.body)
+}
+
+/// Like `api_proxy_get`, but also tells whether the body is a stale cached copy
+#[tauri::command]
+async fn api_proxy_get_cached(
+    core: tauri::State<'_, AppCore>,
+    url: String,
+    headers: Option<serde_json::Value>,
+    request_id: Option<String>,
+) -> Result<CachedProxyResponse, TauriPlayerError> {
+    http::cancellable(&core, request_id, cache::get(&core, url, headers)).await
+}
+
+/// A `MoosicBoxClient` for the current connection. Requests go through `http::send`, sharing the
+/// pooled HTTP client and its timeouts and retries.
+async fn api_client(core: &AppCore) -> Result<MoosicBoxClient, TauriPlayerError> {
+    let api_url = core
+        .api_url
+        .read()
+        .await
+        .clone()
+        .ok_or(TauriPlayerError::ApiUrlNotSet)?;
+
+    let mut client = MoosicBoxClient::new(api_url)
+        .with_http_client(http::client(core).await?)
+        .with_sender({
+            let core = core.clone();
+            move |builder| {
+                let core = core.clone();
+                async move {
+                    http::send(&core, builder)
+                        .await
+                        .map_err(|e| ClientError::Sender(Box::new(e)))
+                }
+            }
+        });
+
+    if let Some(profile) = core.profile.read().await.clone() {
+        client = client.with_profile(profile);
+    }
+    if let Some(api_token) = core.api_token.read().await.clone() {
+        client = client.with_api_token(api_token);
+    }
+    if let Some(client_id) = core.client_id.read().await.clone() {
+        client = client.with_client_id(client_id);
+    }
+
+    Ok(client)
+}
+
+/// Sends the request, refreshing the credentials and replaying it once if the server rejects them
+async fn api_get(
+    core: &AppCore,
     url: String,
     headers: Option<serde_json::Value>,
+) -> Result<serde_json::Value, TauriPlayerError> {
+    let secrets = current_secrets(core).await;
+
+    match send_api_get(core, &url, headers.as_ref()).await {
+        Err(TauriPlayerError::Unauthorized(_))
+            if auth::refresh_credentials(core, &secrets).await =>
+        {
+            send_api_get(core, &url, headers.as_ref()).await
+        }
+        resp => resp,
+    }
+}
+
+async fn send_api_get(
+    core: &AppCore,
+    url: &str,
+    headers: Option<&serde_json::Value>,
 ) -> Result<serde_json::Value, TauriPlayerError> {
     let url = format!(
         "{}/{url}",
-        API_URL
+        core.api_url
             .read()
             .await
             .clone()
-            .ok_or_else(|| TauriPlayerError::Unknown(format!("API_URL not set ({url})")))?
+            .ok_or(TauriPlayerError::ApiUrlNotSet)?
     );
-    info!("Fetching url from proxy: {url}");
-    let client = reqwest::Client::new();
+    info!("Fetching url from proxy: {}", Redacted(&url));
+    let client = http::client(core).await?;
 
-    let mut builder = client.get(url);
+    let builder = with_proxy_headers(core, client.get(url), json_headers(headers)).await;
 
-    if let Some(headers) = headers {
-        for header in headers.as_object().unwrap() {
-            builder = builder.header(header.0, header.1.as_str().unwrap().to_string());
-        }
+    send_request_builder(core, builder).await
+}
+
+/// `request_id` allows aborting the request with `cancel_request`
+#[tauri::command]
+async fn api_proxy_post(
+    core: tauri::State<'_, AppCore>,
+    url: String,
+    body: Option<serde_json::Value>,
+    headers: Option<serde_json::Value>,
+    request_id: Option<String>,
+) -> Result<serde_json::Value, TauriPlayerError> {
+    http::cancellable(&core, request_id, api_post(&core, url, body, headers)).await
+}
+
+/// Aborts the in-flight request that was sent with `request_id`. Its command fails with
+/// `CANCELLED`. Returns whether the request was still in flight.
+#[tauri::command]
+fn cancel_request(core: tauri::State<'_, AppCore>, request_id: String) -> bool {
+    log::debug!("cancel_request: request_id={request_id}");
+    core.http_requests.cancel(&request_id)
+}
+
+#[tauri::command]
+async fn http_config(core: tauri::State<'_, AppCore>) -> Result<HttpConfig, TauriPlayerError> {
+    Ok(core.http_config.read().await.clone())
+}
+
+/// Replaces the timeouts and retry policy. The next request builds a new client with them.
+#[tauri::command]
+async fn set_http_config(
+    core: tauri::State<'_, AppCore>,
+    config: HttpConfig,
+) -> Result<(), TauriPlayerError> {
+    log::debug!("set_http_config: {config:?}");
+
+    *core.http_config.write().await = config;
+    persist_settings(&core).await;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn http_cache_config(
+    core: tauri::State<'_, AppCore>,
+) -> Result<HttpCacheConfig, TauriPlayerError> {
+    Ok(core.http_cache_config.read().await.clone())
+}
+
+/// Replaces the cache settings, evicting responses if the cache is over the new size cap
+#[tauri::command]
+async fn set_http_cache_config(
+    core: tauri::State<'_, AppCore>,
+    config: HttpCacheConfig,
+) -> Result<(), TauriPlayerError> {
+    log::debug!("set_http_cache_config: {config:?}");
+
+    if let Some(cache) = core.http_cache() {
+        let max_size = config.max_size_bytes;
+        cache::blocking(cache, move |cache| cache.set_max_size(max_size)).await?;
     }
 
-    send_request_builder(builder).await
+    *core.http_cache_config.write().await = config;
+    persist_settings(&core).await;
+
+    Ok(())
 }
 
+/// Removes every cached response
 #[tauri::command]
-async fn api_proxy_post(
+async fn clear_http_cache(core: tauri::State<'_, AppCore>) -> Result<(), TauriPlayerError> {
+    log::debug!("clear_http_cache");
+
+    if let Some(cache) = core.http_cache() {
+        cache::blocking(cache, |cache| cache.clear(None)).await?;
+    }
+
+    Ok(())
+}
+
+/// Sends the request, refreshing the credentials and replaying it once if the server rejects them
+async fn api_post(
+    core: &AppCore,
     url: String,
     body: Option<serde_json::Value>,
     headers: Option<serde_json::Value>,
+) -> Result<serde_json::Value, TauriPlayerError> {
+    let secrets = current_secrets(core).await;
+
+    match send_api_post(core, &url, body.as_ref(), headers.as_ref()).await {
+        Err(TauriPlayerError::Unauthorized(_))
+            if auth::refresh_credentials(core, &secrets).await =>
+        {
+            send_api_post(core, &url, body.as_ref(), headers.as_ref()).await
+        }
+        resp => resp,
+    }
+}
+
+async fn send_api_post(
+    core: &AppCore,
+    url: &str,
+    body: Option<&serde_json::Value>,
+    headers: Option<&serde_json::Value>,
 ) -> Result<serde_json::Value, TauriPlayerError> {
     let url = format!(
         "{}/{url}",
-        API_URL
+        core.api_url
             .read()
             .await
             .clone()
-            .ok_or_else(|| TauriPlayerError::Unknown(format!("API_URL not set ({url})")))?
+            .ok_or(TauriPlayerError::ApiUrlNotSet)?
     );
-    info!("Posting url from proxy: {url}");
-    let client = reqwest::Client::new();
+    info!("Posting url from proxy: {}", Redacted(&url));
+    let client = http::client(core).await?;
 
-    let mut builder = client.post(url);
+    let mut builder = with_proxy_headers(core, client.post(url), json_headers(headers)).await;
 
-    if let Some(headers) = headers {
-        for header in headers.as_object().unwrap() {
-            builder = builder.header(header.0, header.1.as_str().unwrap().to_string());
-        }
+    if let Some(body) = body {
+        builder = builder.json(body);
     }
 
-    if let Some(body) = body {
-        builder = builder.json(&body);
+    send_request_builder(core, builder).await
+}
+
+/// Adds the caller's `headers`. A caller supplied `Authorization` header is replaced with the
+/// current API token, so replayed requests don't resend rejected credentials.
+async fn with_proxy_headers<'a>(
+    core: &AppCore,
+    mut builder: RequestBuilder,
+    headers: impl IntoIterator<Item = (&'a str, &'a str)> + Send,
+) -> RequestBuilder {
+    let api_token = { core.api_token.read().await.clone() };
+
+    for (name, value) in headers {
+        let value = match &api_token {
+            Some(api_token) if name.eq_ignore_ascii_case("authorization") => {
+                format!("bearer {api_token}")
+            }
+            _ => value.to_string(),
+        };
+        builder = builder.header(name, value);
     }
 
-    send_request_builder(builder).await
+    builder
+}
+
+/// The string valued entries of a JSON object of headers
+fn json_headers(headers: Option<&serde_json::Value>) -> impl Iterator<Item = (&str, &str)> + Send {
+    headers
+        .and_then(serde_json::Value::as_object)
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, value)| Some((name.as_str(), value.as_str()?)))
 }
 
-async fn propagate_playback_event(update: UpdateSession, to_plugin: bool) -> Result<(), AppError> {
+async fn propagate_playback_event(
+    core: &AppCore,
+    update: UpdateSession,
+    to_plugin: bool,
+) -> Result<(), AppError> {
     if to_plugin {
-        propagate_state_to_plugin(&update.clone().into()).await;
+        propagate_state_to_plugin(core, &update.clone().into()).await;
     }
 
-    if let Some(handle) = WS_HANDLE.read().await.as_ref() {
+    if let Some(handle) = core.ws_handle.read().await.as_ref() {
         log::debug!("on_playback_event: Sending update session: update={update:?}");
 
-        APP.get().unwrap().emit(
+        core.emit(
             "ws-message",
             OutboundPayload::SessionUpdated(SessionUpdatedPayload {
                 payload: update.clone().into(),
@@ -1161,6 +2088,7 @@ async fn propagate_playback_event(update: UpdateSession, to_plugin: bool) -> Res
         )?;
 
         send_ws_message(
+            core,
             handle,
             InboundPayload::UpdateSession(UpdateSessionPayload { payload: update }),
             false,
@@ -1173,13 +2101,60 @@ async fn propagate_playback_event(update: UpdateSession, to_plugin: bool) -> Res
     Ok(())
 }
 
+/// `moosicbox_player` only accepts a plain `fn` for playback events, so the instances listening
+/// for them have to be reachable from one. Entries are removed when their
+/// `PlaybackEventListener` is dropped.
+static PLAYBACK_EVENT_CORES: LazyLock<std::sync::RwLock<Vec<AppCore>>> =
+    LazyLock::new(|| std::sync::RwLock::new(vec![]));
+
+/// Keeps an `AppCore` receiving playback events until dropped
+pub struct PlaybackEventListener {
+    core: AppCore,
+}
+
+impl Drop for PlaybackEventListener {
+    fn drop(&mut self) {
+        PLAYBACK_EVENT_CORES
+            .write()
+            .unwrap()
+            .retain(|x| !x.is_same_instance(&self.core));
+    }
+}
+
+/// Forwards playback events from `core`'s players to its WS connection and the player plugin
+pub fn listen_for_playback_events(core: &AppCore) -> PlaybackEventListener {
+    PLAYBACK_EVENT_CORES.write().unwrap().push(core.clone());
+
+    PlaybackEventListener { core: core.clone() }
+}
+
+/// The listening instances whose players include the session `session_id`, i.e. the ones that
+/// built a player the event can have come from
+async fn playback_event_cores(session_id: u64) -> Vec<AppCore> {
+    let cores = PLAYBACK_EVENT_CORES.read().unwrap().clone();
+    let mut matching = vec![];
+
+    for core in cores {
+        if core.has_session_player(session_id).await {
+            matching.push(core);
+        }
+    }
+
+    matching
+}
+
 pub fn on_playback_event(update: &UpdateSession, _current: &Playback) {
     log::debug!("on_playback_event: received update, spawning task to handle update={update:?}");
 
-    moosicbox_task::spawn(
-        "moosicbox_app: on_playback_event",
-        propagate_playback_event(update.to_owned(), true),
-    );
+    let update = update.to_owned();
+
+    moosicbox_task::spawn("moosicbox_app: on_playback_event", async move {
+        for core in playback_event_cores(update.session_id).await {
+            propagate_playback_event(&core, update.clone(), true).await?;
+        }
+
+        Ok::<_, AppError>(())
+    });
 }
 
 #[cfg(feature = "aptabase")]
@@ -1240,11 +2215,10 @@ pub enum ScanOutputsError {
     RegisterPlayers(#[from] RegisterPlayersError),
 }
 
-async fn scan_outputs() -> Result<(), ScanOutputsError> {
+async fn scan_outputs(core: &AppCore) -> Result<(), ScanOutputsError> {
     log::debug!("scan_outputs: attempting to scan outputs");
     {
-        if API_URL.as_ref().read().await.is_none() || CONNECTION_ID.as_ref().read().await.is_none()
-        {
+        if core.api_url.read().await.is_none() || core.connection_id.read().await.is_none() {
             log::debug!("scan_outputs: missing API_URL or CONNECTION_ID, not scanning");
             return Ok(());
         }
@@ -1265,7 +2239,7 @@ async fn scan_outputs() -> Result<(), ScanOutputsError> {
         })
         .collect::<Vec<_>>();
 
-    let players = register_players(&players).await?;
+    let players = register_players(core, &players).await?;
 
     log::debug!("scan_outputs: players={players:?}");
 
@@ -1279,24 +2253,27 @@ async fn scan_outputs() -> Result<(), ScanOutputsError> {
         })
         .collect::<Vec<_>>();
 
-    add_players_to_current_players(players).await;
+    add_players_to_current_players(core, players).await;
 
-    update_audio_zones().await?;
+    update_audio_zones(core).await?;
     let ids = {
-        CURRENT_SESSIONS
+        core.current_sessions
             .read()
             .await
             .iter()
             .map(|x| x.session_id)
             .collect::<Vec<_>>()
     };
-    update_connection_outputs(&ids).await?;
+    update_connection_outputs(core, &ids).await?;
 
     Ok(())
 }
 
-async fn add_players_to_current_players(players: Vec<(ApiPlayer, PlayerType, AudioOutputFactory)>) {
-    let mut existing_players = CURRENT_PLAYERS.write().await;
+async fn add_players_to_current_players(
+    core: &AppCore,
+    players: Vec<(ApiPlayer, PlayerType, AudioOutputFactory)>,
+) {
+    let mut existing_players = core.current_players.write().await;
 
     let new_players = players
         .into_iter()
@@ -1318,7 +2295,7 @@ async fn add_players_to_current_players(players: Vec<(ApiPlayer, PlayerType, Aud
 #[derive(Debug, Error)]
 pub enum RegisterPlayersError {
     #[error(transparent)]
-    Serde(#[from] serde_json::Error),
+    Client(#[from] ClientError),
     #[error(transparent)]
     TauriPlayer(#[from] TauriPlayerError),
     #[error("Missing profile")]
@@ -1326,103 +2303,90 @@ pub enum RegisterPlayersError {
 }
 
 async fn register_players(
+    core: &AppCore,
     players: &[RegisterPlayer],
 ) -> Result<Vec<ApiPlayer>, RegisterPlayersError> {
-    let connection_id = CONNECTION_ID.read().await.clone().unwrap();
-    let api_token = API_TOKEN.read().await.clone();
-    let client_id = CLIENT_ID
-        .read()
-        .await
-        .clone()
-        .map(|x| format!("&clientId={x}"))
-        .unwrap_or_default();
+    let connection_id = core.connection_id.read().await.clone().unwrap();
 
-    let profile = { PROFILE.read().await.clone() };
-    let Some(profile) = profile else {
+    if core.profile.read().await.is_none() {
         return Err(RegisterPlayersError::MissingProfile);
-    };
-
-    let mut headers = serde_json::Map::new();
-
-    headers.insert(
-        "moosicbox-profile".to_string(),
-        serde_json::Value::String(profile),
-    );
-
-    if let Some(api_token) = api_token {
-        headers.insert(
-            "Authorization".to_string(),
-            serde_json::Value::String(format!("bearer {api_token}")),
-        );
     }
 
-    let response = api_proxy_post(
-        format!("session/register-players?connectionId={connection_id}{client_id}",),
-        Some(serde_json::to_value(players)?),
-        Some(serde_json::Value::Object(headers)),
-    )
-    .await?;
+    let secrets = current_secrets(core).await;
 
-    Ok(serde_json::from_value(response)?)
+    let response = match api_client(core)
+        .await?
+        .register_players(&connection_id, players)
+        .await
+    {
+        Err(ClientError::Unauthorized(_)) if auth::refresh_credentials(core, &secrets).await => {
+            api_client(core)
+                .await?
+                .register_players(&connection_id, players)
+                .await
+        }
+        resp => resp,
+    }?;
+
+    Ok(response)
 }
 
 #[derive(Debug, Error)]
 pub enum FetchAudioZonesError {
     #[error(transparent)]
-    Serde(#[from] serde_json::Error),
+    Client(#[from] ClientError),
     #[error(transparent)]
     TauriPlayer(#[from] TauriPlayerError),
     #[error("Missing profile")]
     MissingProfile,
 }
 
-async fn fetch_audio_zones() -> Result<(), FetchAudioZonesError> {
-    let api_token = API_TOKEN.read().await.clone();
-    let client_id = CLIENT_ID
-        .read()
-        .await
-        .clone()
-        .filter(|x| !x.is_empty())
-        .map(|x| format!("?clientId={x}"))
-        .unwrap_or_default();
+/// `cancel_request` id of the audio zone fetch. Starting a new fetch cancels the one in flight.
+pub const FETCH_AUDIO_ZONES_REQUEST_ID: &str = "fetch-audio-zones";
 
-    let profile = { PROFILE.read().await.clone() };
-    let Some(profile) = profile else {
+async fn fetch_audio_zones(core: &AppCore) -> Result<(), FetchAudioZonesError> {
+    if core.profile.read().await.is_none() {
         return Err(FetchAudioZonesError::MissingProfile);
-    };
-
-    let mut headers = serde_json::Map::new();
-
-    headers.insert(
-        "moosicbox-profile".to_string(),
-        serde_json::Value::String(profile),
-    );
-
-    if let Some(api_token) = api_token {
-        headers.insert(
-            "Authorization".to_string(),
-            serde_json::Value::String(format!("bearer {api_token}")),
-        );
     }
 
-    let response = api_proxy_get(
-        format!("audio-zone/with-session{client_id}",),
-        Some(serde_json::Value::Object(headers)),
+    let secrets = current_secrets(core).await;
+
+    let zones = http::cancellable(
+        core,
+        Some(FETCH_AUDIO_ZONES_REQUEST_ID.to_string()),
+        async {
+            Ok(match all_audio_zones(&api_client(core).await?).await {
+                Err(ClientError::Unauthorized(_))
+                    if auth::refresh_credentials(core, &secrets).await =>
+                {
+                    all_audio_zones(&api_client(core).await?).await
+                }
+                resp => resp,
+            }?)
+        },
     )
     .await?;
 
-    log::debug!("fetch_audio_zones: audio_zones={response}");
-
-    let zones: Page<ApiAudioZoneWithSession> = serde_json::from_value(response)?;
+    log::debug!("fetch_audio_zones: audio_zones={zones:?}");
 
-    *CURRENT_AUDIO_ZONES.write().await = zones.items();
+    *core.current_audio_zones.write().await = zones;
 
-    update_audio_zones().await?;
+    update_audio_zones(core).await?;
 
     Ok(())
 }
 
+async fn all_audio_zones(
+    client: &MoosicBoxClient,
+) -> Result<Vec<ApiAudioZoneWithSession>, ClientError> {
+    all_pages(DEFAULT_PAGE_SIZE, |offset, limit| {
+        client.audio_zones_with_session(offset, limit)
+    })
+    .await
+}
+
 async fn get_session_playback_for_player(
+    core: &AppCore,
     mut update: ApiUpdateSession,
     player: &PlaybackHandler,
 ) -> ApiUpdateSession {
@@ -1438,7 +2402,7 @@ async fn get_session_playback_for_player(
     if let Some(session_id) = session_id {
         if session_id != update.session_id {
             let session = {
-                CURRENT_SESSIONS
+                core.current_sessions
                     .read()
                     .await
                     .iter()
@@ -1471,44 +2435,48 @@ async fn get_session_playback_for_player(
     update
 }
 
-async fn propagate_state_to_plugin(update: &ApiUpdateSession) {
-    let current_session_id = { *CURRENT_SESSION_ID.read().await };
+async fn propagate_state_to_plugin(core: &AppCore, update: &ApiUpdateSession) {
+    let Some(app) = core.app_handle() else {
+        return;
+    };
+
+    let current_session_id = { *core.current_session_id.read().await };
 
     if current_session_id.is_some_and(|id| update.session_id == id) {
-        if let Some((url, query)) = get_url_and_query().await {
-            use tauri_plugin_player::PlayerExt;
-
-            let player = APP.get().unwrap().player();
-
-            log::debug!("propagate_state_to_plugin: update={update:?}");
-            if let Err(e) = player.update_state(tauri_plugin_player::UpdateState {
-                playing: update.playing,
-                position: update.position,
-                seek: update.seek,
-                volume: update.volume,
-                playlist: update
-                    .playlist
-                    .as_ref()
-                    .map(|x| tauri_plugin_player::Playlist {
-                        tracks: x
-                            .tracks
-                            .iter()
-                            .filter_map(|x| convert_track(x.clone(), &url, &query))
-                            .collect::<Vec<_>>(),
-                    }),
-            }) {
-                log::error!("Failed to update_state: {e:?}");
-            }
+        use tauri_plugin_player::PlayerExt;
+
+        log::debug!("propagate_state_to_plugin: update={update:?}");
+
+        let playlist = match &update.playlist {
+            Some(x) => Some(tauri_plugin_player::Playlist {
+                tracks: convert_tracks(core, x.tracks.iter().cloned()).await,
+            }),
+            None => None,
+        };
+
+        let player = app.player();
+
+        if let Err(e) = player.update_state(tauri_plugin_player::UpdateState {
+            playing: update.playing,
+            position: update.position,
+            seek: update.seek,
+            volume: update.volume,
+            playlist,
+        }) {
+            log::error!("Failed to update_state: {e:?}");
         }
     }
 }
 
-async fn handle_playback_update(update: &ApiUpdateSession) -> Result<(), HandleWsMessageError> {
+async fn handle_playback_update(
+    core: &AppCore,
+    update: &ApiUpdateSession,
+) -> Result<(), HandleWsMessageError> {
     log::debug!("handle_playback_update: {update:?}");
 
-    propagate_state_to_plugin(update).await;
+    propagate_state_to_plugin(core, update).await;
 
-    let players = get_players(update.session_id, Some(&update.playback_target)).await?;
+    let players = get_players(core, update.session_id, Some(&update.playback_target)).await?;
 
     moosicbox_logging::debug_or_trace!(
         ("handle_playback_update: player count={}", players.len()),
@@ -1518,61 +2486,99 @@ async fn handle_playback_update(update: &ApiUpdateSession) -> Result<(), HandleW
         )
     );
 
+    let secrets = current_secrets(core).await;
+
     for mut player in players {
-        let update = get_session_playback_for_player(update.to_owned(), &player).await;
+        let update = get_session_playback_for_player(core, update.to_owned(), &player).await;
 
         log::debug!("handle_playback_update: player={}", player.id);
 
         if let Some(quality) = update.quality {
-            PLAYBACK_QUALITY.write().await.replace(quality);
+            core.playback_quality.write().await.replace(quality);
         }
 
-        player
-            .update_playback(
-                true,
-                update.play,
-                update.stop,
-                update.playing,
-                update.position,
-                update.seek,
-                update.volume,
-                update.playlist.map(|x| {
-                    x.tracks
-                        .iter()
-                        .map(|track| Track {
-                            id: track.track_id(),
-                            source: track.api_source(),
-                            data: track.data(),
-                        })
-                        .collect()
-                }),
-                update.quality,
-                Some(update.session_id),
-                Some(update.profile.clone()),
-                Some(update.playback_target.into()),
-                false,
-                Some(DEFAULT_PLAYBACK_RETRY_OPTIONS),
-            )
-            .await?;
+        let mut refreshed = false;
+
+        loop {
+            let resp = player
+                .update_playback(
+                    true,
+                    update.play,
+                    update.stop,
+                    update.playing,
+                    update.position,
+                    update.seek,
+                    update.volume,
+                    update.playlist.as_ref().map(|x| {
+                        x.tracks
+                            .iter()
+                            .map(|track| Track {
+                                id: track.track_id(),
+                                source: track.api_source(),
+                                data: track.data(),
+                            })
+                            .collect()
+                    }),
+                    update.quality,
+                    Some(update.session_id),
+                    Some(update.profile.clone()),
+                    Some(update.playback_target.clone().into()),
+                    false,
+                    Some(DEFAULT_PLAYBACK_RETRY_OPTIONS),
+                )
+                .await;
+
+            match resp {
+                // The player's source still has the rejected signature, so only this player
+                // is rebuilt before replaying the update
+                Err(e)
+                    if !refreshed
+                        && auth::unauthorized_status(&e).is_some()
+                        && auth::refresh_credentials(core, &secrets).await =>
+                {
+                    log::debug!("handle_playback_update: refreshed credentials after {e:?}");
+                    refreshed = true;
+                    player = reinit_player(core, &player).await?;
+                }
+                resp => break resp?,
+            }
+        }
     }
     Ok(())
 }
 
-fn album_cover_url(album_id: &str, source: ApiSource, url: &str, query: &str) -> String {
-    format!("{url}/files/albums/{album_id}/300x300?source={source}{query}")
+/// The tracks for the player plugin. Album covers are given as local file paths.
+async fn convert_tracks(
+    core: &AppCore,
+    tracks: impl IntoIterator<Item = moosicbox_library::models::ApiTrack>,
+) -> Vec<tauri_plugin_player::Track> {
+    let mut converted = vec![];
+
+    for track in tracks {
+        if let Some(track) = convert_track(core, track).await {
+            converted.push(track);
+        }
+    }
+
+    converted
 }
 
-fn convert_track(
+async fn convert_track(
+    core: &AppCore,
     value: moosicbox_library::models::ApiTrack,
-    url: &str,
-    query: &str,
 ) -> Option<tauri_plugin_player::Track> {
     let api_source = value.api_source();
 
     match value {
         moosicbox_library::models::ApiTrack::Library { track_id, data } => {
             let album_cover = if data.contains_cover {
-                Some(album_cover_url(&data.album_id.as_string(), api_source, url, query))
+                art::album_art_path(
+                    core,
+                    &data.album_id.as_string(),
+                    api_source,
+                    art::DEFAULT_ART_SIZE,
+                )
+                .await
             } else {
                 None
             };
@@ -1584,90 +2590,79 @@ fn convert_track(
                 album_cover,
                 artist: data.artist,
                 artist_cover: None,
-                duration: data.duration
+                duration: data.duration,
             })
         }
         _ => {
-            value.data().map(|x| {
-                let album_id = x
-                    .get("albumId")
-                    .and_then(|x| {
-                        if x.is_string() {
-                            x.as_str().map(|x| x.to_string())
-                        } else if x.is_number() {
-                            x.as_u64().map(|x| x.to_string())
-                        } else {
-                            None
-                        }
-                    })
-                    .unwrap_or_default();
+            let x = value.data()?;
+
+            let album_id = x
+                .get("albumId")
+                .and_then(|x| {
+                    if x.is_string() {
+                        x.as_str().map(|x| x.to_string())
+                    } else if x.is_number() {
+                        x.as_u64().map(|x| x.to_string())
+                    } else {
+                        None
+                    }
+                })
+                .unwrap_or_default();
 
-                let contains_cover = x
-                    .get("containsCover")
-                    .and_then(|x| x.as_bool())
-                    .unwrap_or_default();
+            let contains_cover = x
+                .get("containsCover")
+                .and_then(|x| x.as_bool())
+                .unwrap_or_default();
 
-                let album_cover = if contains_cover {
-                    Some(album_cover_url(&album_id, api_source, url, query))
-                } else {
-                    None
-                };
+            let album_cover = if contains_cover {
+                art::album_art_path(core, &album_id, api_source, art::DEFAULT_ART_SIZE).await
+            } else {
+                None
+            };
 
-                log::trace!("handle_ws_message: Converting track data={x} contains_cover={contains_cover} album_cover={album_cover:?}");
-
-                tauri_plugin_player::Track {
-                    id: value.track_id().to_string(),
-                    number: x.get("number")
-                        .and_then(|x| x.as_u64())
-                        .unwrap_or_default() as u32,
-                    title: x
-                        .get("title")
-                        .and_then(|x| x.as_str())
-                        .unwrap_or_default()
-                        .to_string(),
-                    album: x
-                        .get("album")
-                        .and_then(|x| x.as_str())
-                        .unwrap_or_default()
-                        .to_string(),
-                    album_cover,
-                    artist: x
-                        .get("artist")
-                        .and_then(|x| x.as_str().map(|x| x.to_string()))
-                        .unwrap_or_default(),
-                    artist_cover: x
-                        .get("artistCover")
-                        .and_then(|x| x.as_str().map(|x| x.to_string())),
-                    duration: x
-                        .get("duration")
-                        .and_then(|x| x.as_f64())
-                        .unwrap_or_default(),
-                }
+            log::trace!("handle_ws_message: Converting track data={x} contains_cover={contains_cover} album_cover={album_cover:?}");
+
+            Some(tauri_plugin_player::Track {
+                id: value.track_id().to_string(),
+                number: x.get("number").and_then(|x| x.as_u64()).unwrap_or_default() as u32,
+                title: x
+                    .get("title")
+                    .and_then(|x| x.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                album: x
+                    .get("album")
+                    .and_then(|x| x.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                album_cover,
+                artist: x
+                    .get("artist")
+                    .and_then(|x| x.as_str().map(|x| x.to_string()))
+                    .unwrap_or_default(),
+                artist_cover: x
+                    .get("artistCover")
+                    .and_then(|x| x.as_str().map(|x| x.to_string())),
+                duration: x
+                    .get("duration")
+                    .and_then(|x| x.as_f64())
+                    .unwrap_or_default(),
             })
         }
     }
 }
 
-async fn get_url_and_query() -> Option<(String, String)> {
-    let url = { API_URL.read().await.clone() }?;
-
-    let mut query = String::new();
-    if let Some(client_id) = CLIENT_ID.read().await.clone() {
-        query.push_str(&format!("&clientId={client_id}"));
-    }
-    if let Some(signature_token) = SIGNATURE_TOKEN.read().await.clone() {
-        query.push_str(&format!("&signature={signature_token}"));
-    }
-
-    Some((url, query))
-}
-
-async fn update_playlist() -> Result<(), HandleWsMessageError> {
+async fn update_playlist(core: &AppCore) -> Result<(), HandleWsMessageError> {
     use tauri_plugin_player::PlayerExt;
 
     log::trace!("update_playlist");
 
-    let current_session_id = { *CURRENT_SESSION_ID.read().await };
+    let Some(app) = core.app_handle() else {
+        log::debug!("update_playlist: no app handle");
+        return Ok(());
+    };
+
+    let current_session_id = { *core.current_session_id.read().await };
     let Some(current_session_id) = current_session_id else {
         log::debug!("update_playlist: no CURRENT_SESSION_ID");
         return Ok(());
@@ -1676,7 +2671,7 @@ async fn update_playlist() -> Result<(), HandleWsMessageError> {
     log::trace!("update_playlist: current_session_id={current_session_id}");
 
     let session = {
-        let binding = CURRENT_SESSIONS.read().await;
+        let binding = core.current_sessions.read().await;
         let sessions: &[ApiSession] = &binding;
         sessions
             .iter()
@@ -1691,28 +2686,15 @@ async fn update_playlist() -> Result<(), HandleWsMessageError> {
 
     log::debug!("update_playlist: session={session:?}");
 
-    let Some((url, query)) = get_url_and_query().await else {
-        return Ok(());
-    };
+    let tracks = convert_tracks(core, session.playlist.tracks).await;
 
-    match APP
-        .get()
-        .unwrap()
-        .player()
-        .update_state(tauri_plugin_player::UpdateState {
-            playing: Some(session.playing),
-            position: session.position,
-            seek: session.seek.map(|x| x as f64),
-            volume: session.volume,
-            playlist: Some(tauri_plugin_player::Playlist {
-                tracks: session
-                    .playlist
-                    .tracks
-                    .into_iter()
-                    .filter_map(|x| convert_track(x, &url, &query))
-                    .collect::<Vec<_>>(),
-            }),
-        }) {
+    match app.player().update_state(tauri_plugin_player::UpdateState {
+        playing: Some(session.playing),
+        position: session.position,
+        seek: session.seek.map(|x| x as f64),
+        volume: session.volume,
+        playlist: Some(tauri_plugin_player::Playlist { tracks }),
+    }) {
         Ok(_resp) => {
             log::debug!("Successfully set state");
         }
@@ -1736,131 +2718,176 @@ pub enum HandleWsMessageError {
     Tauri(#[from] TauriPlayerError),
 }
 
-async fn handle_ws_message(message: OutboundPayload) -> Result<(), HandleWsMessageError> {
-    log::debug!("handle_ws_message: {message:?}");
-    moosicbox_task::spawn("handle_ws_message", {
-        let message = message.clone();
-        async move {
-            match &message {
-                OutboundPayload::SessionUpdated(payload) => {
-                    handle_playback_update(&payload.payload).await?
-                }
-                OutboundPayload::SetSeek(payload) => {
-                    handle_playback_update(&ApiUpdateSession {
-                        session_id: payload.payload.session_id,
-                        profile: payload.payload.profile.clone(),
-                        playback_target: payload.payload.playback_target.clone(),
-                        play: None,
-                        stop: None,
-                        name: None,
-                        active: None,
-                        playing: None,
-                        position: None,
-                        seek: Some(payload.payload.seek as f64),
-                        volume: None,
-                        playlist: None,
-                        quality: None,
-                    })
-                    .await?
-                }
-                OutboundPayload::ConnectionId(payload) => {
-                    {
-                        WS_CONNECTION_ID
-                            .write()
-                            .await
-                            .replace(payload.connection_id.to_owned());
-                    }
-                    APP.get().unwrap().emit(
-                        "ws-connect",
-                        WsConnectMessage {
-                            connection_id: payload.connection_id.to_owned(),
-                            ws_url: WS_URL.read().await.to_owned().unwrap_or_default(),
-                        },
-                    )?;
-                }
-                OutboundPayload::Connections(payload) => {
-                    *CURRENT_CONNECTIONS.write().await = payload.payload.clone();
+/// Max number of sessions whose inbound messages are applied concurrently
+const WS_MESSAGE_CONCURRENCY: usize = 4;
+
+/// Inbound messages with the same key are applied one at a time in the order they arrived
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum WsMessageKey {
+    Connection,
+    Session(u64),
+}
+
+impl WsMessageKey {
+    /// A `Sessions` list has the key of every session it replaces, so it's applied between the
+    /// updates of those sessions that arrived before and after it
+    fn of(message: &OutboundPayload) -> Vec<Self> {
+        match message {
+            OutboundPayload::Sessions(payload) => payload
+                .payload
+                .iter()
+                .map(|x| Self::Session(x.session_id))
+                .collect(),
+            OutboundPayload::SessionUpdated(payload) => {
+                vec![Self::Session(payload.payload.session_id)]
+            }
+            OutboundPayload::SetSeek(payload) => vec![Self::Session(payload.payload.session_id)],
+            _ => vec![Self::Connection],
+        }
+    }
+}
 
-                    update_audio_zones().await?;
+fn dispatch_ws_message(
+    core: &AppCore,
+    message: OutboundPayload,
+) -> Result<(), HandleWsMessageError> {
+    log::debug!("dispatch_ws_message: {:?}", Redacted(&message));
+
+    core.ws_message_dispatcher
+        .dispatch_all(WsMessageKey::of(&message), {
+            let core = core.clone();
+            let message = message.clone();
+            async move {
+                if let Err(e) = handle_ws_message(&core, &message).await {
+                    log::error!("Failed to handle_ws_message: {e:?}");
                 }
-                OutboundPayload::Sessions(payload) => {
-                    let player_ids = {
-                        let mut player_ids = vec![];
-                        let player_sessions = PENDING_PLAYER_SESSIONS
-                            .read()
-                            .await
-                            .iter()
-                            .map(|(x, y)| (*x, *y))
-                            .collect::<Vec<_>>();
+            }
+        });
+
+    core.emit("ws-message", message)?;
+
+    Ok(())
+}
+
+async fn handle_ws_message(
+    core: &AppCore,
+    message: &OutboundPayload,
+) -> Result<(), HandleWsMessageError> {
+    log::debug!("handle_ws_message: {:?}", Redacted(&message));
+
+    match message {
+        OutboundPayload::SessionUpdated(payload) => {
+            handle_playback_update(core, &payload.payload).await?
+        }
+        OutboundPayload::SetSeek(payload) => {
+            handle_playback_update(
+                core,
+                &ApiUpdateSession {
+                    session_id: payload.payload.session_id,
+                    profile: payload.payload.profile.clone(),
+                    playback_target: payload.payload.playback_target.clone(),
+                    play: None,
+                    stop: None,
+                    name: None,
+                    active: None,
+                    playing: None,
+                    position: None,
+                    seek: Some(payload.payload.seek as f64),
+                    volume: None,
+                    playlist: None,
+                    quality: None,
+                },
+            )
+            .await?
+        }
+        OutboundPayload::ConnectionId(payload) => {
+            core.ws_connection_id
+                .write()
+                .await
+                .replace(payload.connection_id.to_owned());
 
-                        let profile = { PROFILE.read().await.clone() };
+            if std::mem::take(&mut *core.ws_connect_pending.write().await) {
+                emit_ws_connect(core, payload.connection_id.to_owned()).await?;
+            }
+        }
+        OutboundPayload::Connections(payload) => {
+            *core.current_connections.write().await = payload.payload.clone();
 
-                        if let Some(profile) = profile {
-                            for (player_id, session_id) in player_sessions {
-                                if let Some(session) =
-                                    payload.payload.iter().find(|x| x.session_id == session_id)
+            update_audio_zones(core).await?;
+        }
+        OutboundPayload::Sessions(payload) => {
+            let player_ids = {
+                let mut player_ids = vec![];
+                let player_sessions = core
+                    .pending_player_sessions
+                    .read()
+                    .await
+                    .iter()
+                    .map(|(x, y)| (*x, *y))
+                    .collect::<Vec<_>>();
+
+                let profile = { core.profile.read().await.clone() };
+
+                if let Some(profile) = profile {
+                    for (player_id, session_id) in player_sessions {
+                        if let Some(session) =
+                            payload.payload.iter().find(|x| x.session_id == session_id)
+                        {
+                            if let Some(player) = core
+                                .active_players
+                                .write()
+                                .await
+                                .iter_mut()
+                                .find(|x| x.player.id as u64 == player_id)
+                                .map(|x| &mut x.player)
+                            {
+                                log::debug!(
+                                    "handle_ws_message: init_from_api_session session={session:?}"
+                                );
+                                if let Err(e) = player
+                                    .init_from_api_session(profile.clone(), session.clone())
+                                    .await
                                 {
-                                    if let Some(player) = ACTIVE_PLAYERS
-                                        .write()
-                                        .await
-                                        .iter_mut()
-                                        .find(|x| x.player.id as u64 == player_id)
-                                        .map(|x| &mut x.player)
-                                    {
-                                        log::debug!(
-                                        "handle_ws_message: init_from_api_session session={session:?}"
-                                    );
-                                        if let Err(e) = player
-                                            .init_from_api_session(profile.clone(), session.clone())
-                                            .await
-                                        {
-                                            log::error!(
-                                                "Failed to init player from api session: {e:?}"
-                                            );
-                                        }
-                                        player_ids.push(player_id);
-                                    }
+                                    log::error!("Failed to init player from api session: {e:?}");
                                 }
+                                player_ids.push(player_id);
                             }
                         }
-
-                        player_ids
-                    };
-                    {
-                        PENDING_PLAYER_SESSIONS
-                            .write()
-                            .await
-                            .retain(|id, _| !player_ids.contains(id));
                     }
-                    {
-                        *CURRENT_SESSIONS.write().await = payload.payload.clone();
-                    }
-
-                    update_audio_zones().await?;
-                    update_connection_outputs(
-                        &payload
-                            .payload
-                            .iter()
-                            .map(|x| x.session_id)
-                            .collect::<Vec<_>>(),
-                    )
-                    .await?;
-                    update_playlist().await?;
                 }
 
-                OutboundPayload::AudioZoneWithSessions(payload) => {
-                    *CURRENT_AUDIO_ZONES.write().await = payload.payload.clone();
-
-                    update_audio_zones().await?;
-                }
-                _ => {}
+                player_ids
+            };
+            {
+                core.pending_player_sessions
+                    .write()
+                    .await
+                    .retain(|id, _| !player_ids.contains(id));
+            }
+            {
+                *core.current_sessions.write().await = payload.payload.clone();
             }
 
-            Ok::<_, HandleWsMessageError>(())
+            update_audio_zones(core).await?;
+            update_connection_outputs(
+                core,
+                &payload
+                    .payload
+                    .iter()
+                    .map(|x| x.session_id)
+                    .collect::<Vec<_>>(),
+            )
+            .await?;
+            update_playlist(core).await?;
         }
-    });
 
-    APP.get().unwrap().emit("ws-message", message)?;
+        OutboundPayload::AudioZoneWithSessions(payload) => {
+            *core.current_audio_zones.write().await = payload.payload.clone();
+
+            update_audio_zones(core).await?;
+        }
+        _ => {}
+    }
 
     Ok(())
 }
@@ -1875,117 +2902,246 @@ pub enum InitWsError {
     TauriPlayer(#[from] TauriPlayerError),
     #[error(transparent)]
     CloseWs(#[from] CloseWsError),
+    #[error(transparent)]
+    Tls(#[from] TlsError),
     #[error("Missing profile")]
     MissingProfile,
 }
 
-async fn init_ws_connection() -> Result<(), InitWsError> {
-    close_ws_connection().await?;
+/// How long the ws status loop waits for the client to publish its final state after the
+/// connection is cancelled
+const WS_STATUS_CLOSE_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(1_000);
+
+/// States after which the client doesn't publish any more changes
+fn is_final_ws_state(state: &ConnectionState) -> bool {
+    matches!(
+        state,
+        ConnectionState::GaveUp | ConnectionState::Unauthorized | ConnectionState::Closed
+    )
+}
+
+async fn emit_ws_status(core: &AppCore, state: &ConnectionState) {
+    let status: WsStatusMessage = state.clone().into();
+    log::debug!("init_ws_connection: ws status={status:?}");
+    diagnostics::record_ws_status(core, &status).await;
+
+    if let Err(e) = core.emit("ws-status", status) {
+        log::error!("Failed to emit ws-status: {e:?}");
+    }
+}
+
+/// Whether WS connections offer permessage-deflate. On by default, `WS_COMPRESSION=0` turns it
+/// off for servers or proxies that mishandle the extension.
+pub(crate) fn ws_compression_enabled() -> bool {
+    std::env::var("WS_COMPRESSION").map_or(true, |x| x != "0")
+}
+
+/// The WS endpoint of the server at `api_url`
+pub(crate) fn ws_url(api_url: &str) -> Result<Url, TauriPlayerError> {
+    let mut url = Url::parse(api_url).map_err(|e| TauriPlayerError::InvalidUrl(e.to_string()))?;
+
+    let scheme = match url.scheme() {
+        "http" => "ws",
+        "https" => "wss",
+        scheme => {
+            return Err(TauriPlayerError::InvalidUrl(format!(
+                "Expected an http or https URL, got '{scheme}'"
+            )))
+        }
+    };
+    url.set_scheme(scheme)
+        .map_err(|()| TauriPlayerError::InvalidUrl(format!("Failed to set scheme '{scheme}'")))?;
+    url.set_query(None);
+    url.set_fragment(None);
+
+    // Without the trailing slash, joining would replace the last path segment
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+
+    url.join("ws")
+        .map_err(|e| TauriPlayerError::InvalidUrl(e.to_string()))
+}
+
+async fn init_ws_connection(core: &AppCore) -> Result<(), InitWsError> {
+    close_ws_connection(core).await?;
 
     log::debug!("init_ws_connection: attempting to connect to ws");
     {
-        if API_URL.as_ref().read().await.is_none() {
+        if core.api_url.read().await.is_none() {
             log::debug!("init_ws_connection: missing API_URL");
             return Ok(());
         }
     }
     {
-        if let Some(token) = WS_TOKEN.read().await.as_ref() {
+        if let Some(token) = core.ws_token.read().await.as_ref() {
             token.cancel();
         }
     }
     let token = {
         let token = CancellationToken::new();
-        WS_TOKEN.write().await.replace(token.clone());
+        core.ws_token.write().await.replace(token.clone());
         token
     };
 
-    let api_url = API_URL.read().await.clone().unwrap();
-    let profile = PROFILE
+    let api_url = core.api_url.read().await.clone().unwrap();
+    let profile = core
+        .profile
         .read()
         .await
         .clone()
         .ok_or_else(|| InitWsError::MissingProfile)?;
 
-    let client_id = CLIENT_ID.read().await.clone();
-    let signature_token = SIGNATURE_TOKEN.read().await.clone();
+    let secrets = current_secrets(core).await;
+    let client_id = secrets.client_id.clone();
+    let signature_token = secrets.signature_token.clone();
+
+    let mut headers = HashMap::new();
+
+    if let Some(api_token) = secrets.api_token.clone() {
+        headers.insert("Authorization".to_string(), format!("bearer {api_token}"));
+    }
 
-    let ws_url = format!("ws{}/ws", &api_url[4..]);
+    let ws_url = ws_url(&api_url)?.to_string();
     {
-        *WS_URL.write().await = Some(ws_url.clone());
+        *core.ws_url.write().await = Some(ws_url.clone());
     }
+    let connector = tls_client_config(core).await?.map(Connector::Rustls);
+
     let (client, handle) = WsClient::new(ws_url);
+    let client = client
+        .with_handshake_headers(headers)
+        .with_query_auth_fallback(*core.ws_query_auth.read().await)
+        .with_tls_connector(connector)
+        .with_compression(ws_compression_enabled());
 
-    WS_HANDLE.write().await.replace(handle.clone());
+    core.ws_handle.write().await.replace(handle.clone());
 
-    let mut client = client.with_cancellation_token(token.clone());
+    let mut client = client.with_linked_cancellation_token(token.clone());
 
-    WS_JOIN_HANDLE
-        .write()
-        .await
-        .replace(moosicbox_task::spawn("moosicbox_app: ws", async move {
-            let mut rx = client.start(client_id, signature_token, profile, {
-                let handle = handle.clone();
-                move || {
-                    tauri::async_runtime::spawn({
-                        let handle = handle.clone();
-                        async move {
-                            log::debug!("Sending GetConnectionId");
-                            if let Err(e) = send_ws_message(
-                                &handle,
-                                InboundPayload::GetConnectionId(EmptyPayload {}),
-                                true,
-                            )
-                            .await
-                            {
-                                log::error!("Failed to send GetConnectionId WS message: {e:?}");
-                            }
-                            if let Err(e) = flush_ws_message_buffer().await {
-                                log::error!("Failed to flush WS message buffer: {e:?}");
-                            }
-                        }
+    moosicbox_task::spawn("moosicbox_app: ws status", {
+        let mut connection_state = client.connection_state();
+        let core = core.clone();
+        let token = token.clone();
+
+        async move {
+            let mut emitted = None;
+
+            loop {
+                let state = connection_state.borrow_and_update().clone();
+
+                if state == ConnectionState::Unauthorized {
+                    moosicbox_task::spawn("moosicbox_app: ws refresh_credentials", {
+                        let core = core.clone();
+                        let secrets = secrets.clone();
+                        async move { auth::refresh_credentials(&core, &secrets).await }
                     });
                 }
-            });
 
-            while let Some(m) = tokio::select! {
-                resp = rx.recv() => {
-                    resp
-                }
-                _ = token.cancelled() => {
-                    None
+                emit_ws_status(&core, &state).await;
+                emitted = Some(state);
+
+                tokio::select! {
+                    resp = connection_state.changed() => {
+                        if resp.is_err() {
+                            break;
+                        }
+                    }
+                    _ = token.cancelled() => {
+                        // The client publishes `Closed` once it notices the cancellation
+                        let _ = tokio::time::timeout(
+                            WS_STATUS_CLOSE_TIMEOUT,
+                            connection_state.wait_for(is_final_ws_state),
+                        )
+                        .await;
+                        break;
+                    }
                 }
-            } {
-                match m {
-                    WsMessage::TextMessage(message) => {
-                        if let Ok(message) = serde_json::from_str::<OutboundPayload>(&message) {
-                            if let Err(e) = handle_ws_message(message).await {
-                                log::error!("Failed to handle_ws_message: {e:?}");
+            }
+
+            let state = connection_state.borrow().clone();
+            let state = if token.is_cancelled() && !is_final_ws_state(&state) {
+                ConnectionState::Closed
+            } else {
+                state
+            };
+
+            if emitted.as_ref() != Some(&state) {
+                emit_ws_status(&core, &state).await;
+            }
+        }
+    });
+
+    core.ws_join_handle
+        .write()
+        .await
+        .replace(moosicbox_task::spawn("moosicbox_app: ws", {
+            let core = core.clone();
+            async move {
+                let mut rx = client.start(client_id, signature_token, profile, {
+                    let core = core.clone();
+                    let handle = handle.clone();
+                    move || {
+                        tauri::async_runtime::spawn({
+                            let core = core.clone();
+                            let handle = handle.clone();
+                            async move {
+                                if let Err(e) = on_ws_connected(&core, &handle).await {
+                                    log::error!("Failed to initialize WS connection: {e:?}");
+                                }
+                                if let Err(e) = flush_ws_message_buffer(&core).await {
+                                    log::error!("Failed to flush WS message buffer: {e:?}");
+                                }
                             }
-                        } else {
-                            log::error!("got invalid message: {message}");
-                        }
+                        });
+                    }
+                });
+
+                while let Some(m) = tokio::select! {
+                    resp = rx.recv() => {
+                        resp
                     }
-                    WsMessage::Message(bytes) => match String::from_utf8(bytes.into()) {
-                        Ok(message) => {
+                    _ = token.cancelled() => {
+                        None
+                    }
+                } {
+                    match m {
+                        WsMessage::TextMessage(message) => {
                             if let Ok(message) = serde_json::from_str::<OutboundPayload>(&message) {
-                                if let Err(e) = handle_ws_message(message).await {
-                                    log::error!("Failed to handle_ws_message: {e:?}");
+                                if let Err(e) = dispatch_ws_message(&core, message) {
+                                    log::error!("Failed to dispatch_ws_message: {e:?}");
                                 }
                             } else {
                                 log::error!("got invalid message: {message}");
                             }
                         }
-                        Err(e) => {
-                            log::error!("Failed to read ws message: {e:?}");
+                        WsMessage::Message(bytes) => match String::from_utf8(bytes.into()) {
+                            Ok(message) => {
+                                if let Ok(message) =
+                                    serde_json::from_str::<OutboundPayload>(&message)
+                                {
+                                    if let Err(e) = dispatch_ws_message(&core, message) {
+                                        log::error!("Failed to dispatch_ws_message: {e:?}");
+                                    }
+                                } else {
+                                    log::error!("got invalid message: {message}");
+                                }
+                            }
+                            Err(e) => {
+                                log::error!("Failed to read ws message: {e:?}");
+                            }
+                        },
+                        WsMessage::Ping => {
+                            log::debug!("got ping");
+                        }
+                        WsMessage::Close { code, reason } => {
+                            log::debug!("got close code={code:?} reason={reason}");
                         }
-                    },
-                    WsMessage::Ping => {
-                        log::debug!("got ping");
                     }
                 }
+                log::debug!("Exiting ws message loop");
             }
-            log::debug!("Exiting ws message loop");
         }));
 
     Ok(())
@@ -2001,14 +3157,14 @@ pub enum CloseWsError {
     Join(#[from] JoinError),
 }
 
-async fn close_ws_connection() -> Result<(), CloseWsError> {
+async fn close_ws_connection(core: &AppCore) -> Result<(), CloseWsError> {
     log::debug!("close_ws_connection: attempting to close ws connection");
 
-    if let Some(handle) = WS_HANDLE.read().await.as_ref() {
+    if let Some(handle) = core.ws_handle.read().await.as_ref() {
         handle.close().await?;
     }
 
-    if let Some(handle) = WS_JOIN_HANDLE.write().await.take() {
+    if let Some(handle) = core.ws_join_handle.write().await.take() {
         handle.abort();
     }
 
@@ -2030,8 +3186,6 @@ impl SourceToMusicApi for SourceToRemoteLibrary {
     }
 }
 
-static UPNP_LISTENER_HANDLE: OnceLock<moosicbox_upnp::listener::Handle> = OnceLock::new();
-
 #[derive(Debug, Error)]
 pub enum InitUpnpError {
     #[error(transparent)]
@@ -2044,11 +3198,16 @@ pub enum InitUpnpError {
     RegisterPlayers(#[from] RegisterPlayersError),
 }
 
-async fn init_upnp_players() -> Result<(), InitUpnpError> {
+async fn init_upnp_players(core: &AppCore) -> Result<(), InitUpnpError> {
+    let Some(upnp_listener_handle) = core.upnp_listener_handle.clone() else {
+        log::debug!("init_upnp_players: no UPnP listener");
+        return Ok(());
+    };
+
     moosicbox_upnp::scan_devices().await?;
 
     let services = {
-        let mut av_transport_services = UPNP_AV_TRANSPORT_SERVICES.write().await;
+        let mut av_transport_services = core.upnp_av_transport_services.write().await;
         av_transport_services.clear();
 
         for device in moosicbox_upnp::devices().await {
@@ -2065,7 +3224,7 @@ async fn init_upnp_players() -> Result<(), InitUpnpError> {
 
     let mut outputs = Vec::with_capacity(services.len());
 
-    let url_string = { API_URL.read().await.clone() };
+    let url_string = { core.api_url.read().await.clone() };
     let url = url_string.as_deref();
 
     let Some(url) = url else {
@@ -2079,7 +3238,7 @@ async fn init_upnp_players() -> Result<(), InitUpnpError> {
             })),
             device: service.device.clone(),
             service: service.service.clone(),
-            handle: UPNP_LISTENER_HANDLE.get().unwrap().clone(),
+            handle: upnp_listener_handle.clone(),
         };
         let output: AudioOutputFactory = service.try_into()?;
 
@@ -2094,7 +3253,7 @@ async fn init_upnp_players() -> Result<(), InitUpnpError> {
         })
         .collect::<Vec<_>>();
 
-    let api_players = register_players(&register_players_payload).await?;
+    let api_players = register_players(core, &register_players_payload).await?;
 
     log::debug!("init_upnp_players: players={api_players:?}");
 
@@ -2112,10 +3271,10 @@ async fn init_upnp_players() -> Result<(), InitUpnpError> {
         })
         .collect::<Vec<_>>();
 
-    add_players_to_current_players(api_players).await;
+    add_players_to_current_players(core, api_players).await;
 
     let ids = {
-        CURRENT_SESSIONS
+        core.current_sessions
             .read()
             .await
             .iter()
@@ -2123,32 +3282,35 @@ async fn init_upnp_players() -> Result<(), InitUpnpError> {
             .collect::<Vec<_>>()
     };
 
-    update_connection_outputs(&ids).await?;
+    update_connection_outputs(core, &ids).await?;
 
     Ok(())
 }
 
 #[cfg(target_os = "android")]
 async fn handle_media_event(
+    core: &AppCore,
     event: tauri_plugin_player::MediaEvent,
 ) -> Result<(), TauriPlayerError> {
     log::trace!("handle_media_event: event={event:?}");
-    let Some(current_session_id) = ({ *CURRENT_SESSION_ID.read().await }) else {
+    let Some(current_session_id) = ({ *core.current_session_id.read().await }) else {
         log::debug!("handle_media_event: No current_session_id");
         return Ok(());
     };
 
-    let Some(current_profile) = ({ PROFILE.read().await.clone() }) else {
+    let Some(current_profile) = ({ core.profile.read().await.clone() }) else {
         log::debug!("handle_media_event: No current_profile");
         return Ok(());
     };
 
-    let Some(current_playback_target) = ({ CURRENT_PLAYBACK_TARGET.read().await.clone() }) else {
+    let Some(current_playback_target) = ({ core.current_playback_target.read().await.clone() })
+    else {
         log::debug!("handle_media_event: No current_playback_target");
         return Ok(());
     };
 
     let players = get_players(
+        core,
         current_session_id,
         Some(&current_playback_target.clone().into()),
     )
@@ -2168,6 +3330,7 @@ async fn handle_media_event(
                 return Ok(());
             };
             propagate_playback_event(
+                core,
                 UpdateSession {
                     session_id: current_session_id,
                     profile: current_profile.clone(),
@@ -2185,12 +3348,8 @@ async fn handle_media_event(
                 },
                 false,
             )
-            .await
-            .map_err(|e| TauriPlayerError::Unknown(e.to_string()))?;
-            player
-                .next_track(None, None)
-                .await
-                .map_err(|e| TauriPlayerError::Unknown(e.to_string()))?;
+            .await?;
+            player.next_track(None, None).await?;
         }
         if let Some(true) = event.prev_track {
             let Some(position) = ({
@@ -2204,6 +3363,7 @@ async fn handle_media_event(
                 return Ok(());
             };
             propagate_playback_event(
+                core,
                 UpdateSession {
                     session_id: current_session_id,
                     profile: current_profile.clone(),
@@ -2221,15 +3381,12 @@ async fn handle_media_event(
                 },
                 false,
             )
-            .await
-            .map_err(|e| TauriPlayerError::Unknown(e.to_string()))?;
-            player
-                .previous_track(None, None)
-                .await
-                .map_err(|e| TauriPlayerError::Unknown(e.to_string()))?;
+            .await?;
+            player.previous_track(None, None).await?;
         }
         if let Some(true) = event.play {
             propagate_playback_event(
+                core,
                 UpdateSession {
                     session_id: current_session_id,
                     profile: current_profile.clone(),
@@ -2247,14 +3404,11 @@ async fn handle_media_event(
                 },
                 false,
             )
-            .await
-            .map_err(|e| TauriPlayerError::Unknown(e.to_string()))?;
-            player
-                .resume(None)
-                .await
-                .map_err(|e| TauriPlayerError::Unknown(e.to_string()))?;
+            .await?;
+            player.resume(None).await?;
         } else if let Some(false) = event.play {
             propagate_playback_event(
+                core,
                 UpdateSession {
                     session_id: current_session_id,
                     profile: current_profile.clone(),
@@ -2272,38 +3426,50 @@ async fn handle_media_event(
                 },
                 false,
             )
-            .await
-            .map_err(|e| TauriPlayerError::Unknown(e.to_string()))?;
-            player
-                .pause(None)
-                .await
-                .map_err(|e| TauriPlayerError::Unknown(e.to_string()))?;
+            .await?;
+            player.pause(None).await?;
         }
     }
 
     Ok(())
 }
 
+/// Initializes FreeLog with a `RedactingLayer`, so every event, whichever crate logged it, is
+/// redacted before FreeLog's file and remote writers see it
+fn init_logging(filename: Option<&str>) -> Result<LogLayer, impl std::fmt::Debug> {
+    let redacting = RedactingLayer::new();
+    let layers: Vec<moosicbox_logging::free_log_client::DynLayer> =
+        vec![Box::new(redacting.clone())];
+
+    moosicbox_logging::init(filename, Some(layers)).map(|layer| {
+        tracing::dispatcher::get_default(|dispatch| redacting.attach(dispatch));
+        RedactingLogLayer::new(layer)
+    })
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    let mut log_layer = None;
+
     if std::env::var("TOKIO_CONSOLE") == Ok("1".to_string()) {
         console_subscriber::init();
     } else {
         #[cfg(target_os = "android")]
         let filename = None;
         #[cfg(not(target_os = "android"))]
-        let filename = Some("moosicbox_app.log");
+        let filename = Some(diagnostics::LOG_FILENAME);
 
-        let layer = moosicbox_logging::init(filename).expect("Failed to initialize FreeLog");
-        LOG_LAYER.set(layer).expect("Failed to set LOG_LAYER");
+        if filename.is_some() {
+            diagnostics::rotate_logs();
+        }
+
+        log_layer = Some(init_logging(filename).expect("Failed to initialize FreeLog"));
     }
 
     let tauri::async_runtime::RuntimeHandle::Tokio(tokio_handle) = tauri::async_runtime::handle();
 
-    #[cfg(feature = "client")]
-    {
-        moosicbox_app_client::init();
-    }
+    #[cfg(not(target_os = "android"))]
+    diagnostics::spawn_log_rotation(&tokio_handle);
 
     #[cfg(feature = "bundled")]
     let (join_app_server, app_server_handle) = {
@@ -2340,19 +3506,74 @@ pub fn run() {
     let upnp_service_handle = upnp_service.handle();
     let join_upnp_service = upnp_service.start_on(&tokio_handle);
 
-    UPNP_LISTENER_HANDLE
-        .set(upnp_service_handle.clone())
-        .unwrap_or_else(|_| panic!("Failed to set UPNP_LISTENER_HANDLE"));
+    let core = AppCore::new().with_upnp_listener_handle(upnp_service_handle);
+    if let Some(layer) = log_layer {
+        core.set_log_layer(layer);
+    }
+    let playback_events = listen_for_playback_events(&core);
 
-    let (mdns_handle, join_mdns_service) = mdns::spawn_mdns_scanner();
+    let (mdns_handle, join_mdns_service) = mdns::spawn_mdns_scanner(&core);
 
     #[allow(unused_mut)]
     let mut app_builder = tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_player::init())
-        .setup(|app| {
-            APP.get_or_init(|| app.handle().clone());
+        .manage(core.clone())
+        .manage(playback_events)
+        .register_asynchronous_uri_scheme_protocol(art::ART_SCHEME, {
+            let core = core.clone();
+            move |_ctx, request, responder| {
+                let core = core.clone();
+                tauri::async_runtime::spawn(async move {
+                    responder.respond(art::handle(&core, request).await);
+                });
+            }
+        })
+        .setup(move |app| {
+            use tauri::Manager as _;
+
+            core.set_app_handle(app.handle().clone());
+
+            match app.path().app_data_dir() {
+                Ok(app_data_dir) => {
+                    core.set_settings_path(settings::settings_path(&app_data_dir));
+
+                    match FileBackend::open(&app_data_dir) {
+                        Ok(backend) => core.set_credential_store(CredentialStore::new(backend)),
+                        Err(e) => log::error!("Failed to open credential store: {e:?}"),
+                    }
+                }
+                Err(e) => log::error!("Failed to resolve app data dir: {e:?}"),
+            }
+
+            let mut http_cache_dir = None;
+
+            match app.path().app_cache_dir() {
+                Ok(app_cache_dir) => {
+                    http_cache_dir = Some(app_cache_dir.join(cache::HTTP_CACHE_DIR));
+
+                    let art_dir = app_cache_dir.join(art::ART_CACHE_DIR);
+                    match HttpCache::open(art_dir, art::ART_CACHE_MAX_SIZE) {
+                        Ok(cache) => core.set_art_cache(cache),
+                        Err(e) => log::error!("Failed to open album art cache: {e:?}"),
+                    }
+                }
+                Err(e) => log::error!("Failed to resolve app cache dir: {e:?}"),
+            }
+
+            tauri::async_runtime::spawn({
+                let core = core.clone();
+                async move {
+                    if let Err(e) = restore_settings(&core).await {
+                        log::error!("Failed to restore settings: {e:?}");
+                    }
+                    if let Some(dir) = http_cache_dir {
+                        cache::open(&core, dir).await;
+                    }
+                    core.set_settings_restored();
+                }
+            });
 
             #[cfg(target_os = "android")]
             {
@@ -2360,14 +3581,17 @@ pub fn run() {
 
                 let player = app.player();
 
-                let channel = tauri::ipc::Channel::new(|event| {
+                let channel = tauri::ipc::Channel::new(move |event| {
+                    let core = core.clone();
                     tauri::async_runtime::spawn(async move {
                         log::trace!("Received event from channel: {event:?}");
                         let event: tauri_plugin_player::MediaEvent =
                             event.deserialize().map_err(|x| x.to_string())?;
                         log::debug!("Received media event from channel: {event:?}");
 
-                        handle_media_event(event).await.map_err(|x| x.to_string())?;
+                        handle_media_event(&core, event)
+                            .await
+                            .map_err(|x| x.to_string())?;
 
                         Ok::<_, String>(())
                     });
@@ -2389,9 +3613,31 @@ pub fn run() {
             set_playback_quality,
             set_state,
             propagate_ws_message,
+            ws_compression_stats,
             api_proxy_get,
+            api_proxy_get_cached,
             api_proxy_post,
+            proxy::api_proxy_request,
+            proxy::api_proxy_stream,
+            proxy::pick_upload_file,
+            cancel_request,
+            http_config,
+            set_http_config,
+            http_cache_config,
+            set_http_cache_config,
+            clear_http_cache,
+            trust_certificate,
+            list_connections,
+            active_connection,
+            add_connection,
+            rename_connection,
+            remove_connection,
+            activate_connection,
+            credential_status,
+            clear_credentials,
             mdns::fetch_moosicbox_servers,
+            diagnostics::export_diagnostics,
+            doctor::diagnose_connection,
         ]);
 
     #[cfg(feature = "aptabase")]
@@ -2505,3 +3751,148 @@ pub fn run() {
         log::error!("Failed to join mdns service: {e:?}");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logging_init_keeps_the_free_log_layer() {
+        let core = AppCore::new();
+
+        core.set_log_layer(init_logging(None).unwrap());
+
+        assert!(core.log_layer().is_some());
+    }
+
+    #[test]
+    fn ws_url_is_built_from_the_parsed_api_url() {
+        let ws = |x: &str| ws_url(x).unwrap().to_string();
+
+        assert_eq!(ws("http://host:8000"), "ws://host:8000/ws");
+        assert_eq!(ws("HTTPS://Host"), "wss://host/ws");
+        assert_eq!(ws("https://host/api"), "wss://host/api/ws");
+        assert_eq!(ws("https://host/api/?x=1#y"), "wss://host/api/ws");
+        assert_eq!(
+            ws_url("ftp://host").unwrap_err().code(),
+            ErrorCode::InvalidUrl
+        );
+        assert_eq!(ws_url("host").unwrap_err().code(), ErrorCode::InvalidUrl);
+    }
+
+    fn listening(core: &AppCore) -> bool {
+        PLAYBACK_EVENT_CORES
+            .read()
+            .unwrap()
+            .iter()
+            .any(|x| x.is_same_instance(core))
+    }
+
+    #[test]
+    fn app_core_instances_do_not_share_state() {
+        let a = AppCore::new();
+        let b = AppCore::new();
+
+        assert!(a.is_same_instance(&a.clone()));
+        assert!(!a.is_same_instance(&b));
+
+        tauri::async_runtime::block_on(async {
+            a.api_url.write().await.replace("http://a".to_string());
+            a.current_session_id.write().await.replace(1);
+            a.mdns_servers.write().await.push(mdns::MoosicBoxServer {
+                id: "a".to_string(),
+                name: "a".to_string(),
+                host: "http://a".to_string(),
+                dns: "a.local".to_string(),
+            });
+
+            assert_eq!(a.clone().api_url.read().await.as_deref(), Some("http://a"));
+            assert_eq!(b.api_url.read().await.as_deref(), None);
+            assert_eq!(*b.current_session_id.read().await, None);
+            assert_eq!(mdns::servers(&a).await.len(), 1);
+            assert!(mdns::servers(&b).await.is_empty());
+        });
+    }
+
+    #[test]
+    fn playback_events_stop_when_listener_is_dropped() {
+        let a = AppCore::new();
+        let b = AppCore::new();
+
+        let listener_a = listen_for_playback_events(&a);
+        let listener_b = listen_for_playback_events(&b);
+        assert!(listening(&a));
+        assert!(listening(&b));
+
+        drop(listener_a);
+        assert!(!listening(&a));
+        assert!(listening(&b));
+
+        drop(listener_b);
+        assert!(!listening(&b));
+    }
+
+    #[test]
+    fn playback_events_skip_instances_without_the_session() {
+        let a = AppCore::new();
+        let b = AppCore::new();
+        let _listener_a = listen_for_playback_events(&a);
+        let _listener_b = listen_for_playback_events(&b);
+
+        tauri::async_runtime::block_on(async {
+            assert!(playback_event_cores(1).await.is_empty());
+        });
+    }
+
+    #[test]
+    fn api_client_requests_are_retried_by_http_send() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use moosicbox_app_http_test_utils::{MockResponse, MockServer};
+
+        tauri::async_runtime::block_on(async {
+            let server = MockServer::start().await;
+            let calls = Arc::new(AtomicUsize::new(0));
+            server.route("GET", "/audio-zone/with-session", {
+                let calls = calls.clone();
+                move |_| match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => MockResponse::status(503, ""),
+                    _ => MockResponse::json(
+                        &serde_json::json!({"items": [], "offset": 0, "limit": 100, "total": 0}),
+                    ),
+                }
+            });
+            let core = AppCore::new();
+            core.api_url.write().await.replace(server.url());
+            core.http_config.write().await.retry_initial_delay_ms = 1;
+
+            let zones = all_audio_zones(&api_client(&core).await.unwrap())
+                .await
+                .unwrap();
+
+            assert!(zones.is_empty());
+            assert_eq!(server.requests().len(), 2);
+        });
+    }
+
+    #[test]
+    fn api_client_send_errors_keep_their_code() {
+        tauri::async_runtime::block_on(async {
+            let core = AppCore::new();
+            core.api_url
+                .write()
+                .await
+                .replace("http://127.0.0.1:1".to_string());
+            core.http_config.write().await.max_retries = 0;
+
+            let err = api_client(&core)
+                .await
+                .unwrap()
+                .session(1)
+                .await
+                .unwrap_err();
+
+            assert_eq!(TauriPlayerError::from(err).code(), ErrorCode::Network);
+        });
+    }
+}
@@ -1,10 +1,8 @@
-use std::sync::{Arc, LazyLock};
-
 use serde::Serialize;
 use tauri::async_runtime::RuntimeHandle;
-use tokio::{sync::RwLock, task::JoinHandle};
+use tokio::task::JoinHandle;
 
-use crate::TauriPlayerError;
+use crate::{AppCore, TauriPlayerError};
 
 #[derive(Debug, Clone, Serialize)]
 pub struct MoosicBoxServer {
@@ -25,17 +23,24 @@ impl From<moosicbox_mdns::scanner::MoosicBoxServer> for MoosicBoxServer {
     }
 }
 
-static MOOSICBOX_SERVERS: LazyLock<Arc<RwLock<Vec<MoosicBoxServer>>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(vec![])));
-
 #[tauri::command]
-pub async fn fetch_moosicbox_servers() -> Result<Vec<MoosicBoxServer>, TauriPlayerError> {
+pub async fn fetch_moosicbox_servers(
+    core: tauri::State<'_, AppCore>,
+) -> Result<Vec<MoosicBoxServer>, TauriPlayerError> {
     log::debug!("fetch_moosicbox_servers");
 
-    Ok(MOOSICBOX_SERVERS.read().await.clone())
+    Ok(servers(&core).await)
+}
+
+/// The servers discovered so far
+pub async fn servers(core: &AppCore) -> Vec<MoosicBoxServer> {
+    core.mdns_servers.read().await.clone()
 }
 
-pub fn spawn_mdns_scanner() -> (
+/// Scans for servers and adds them to `core`'s discovered servers
+pub fn spawn_mdns_scanner(
+    core: &AppCore,
+) -> (
     moosicbox_mdns::scanner::service::Handle,
     JoinHandle<Result<(), moosicbox_mdns::scanner::service::Error>>,
 ) {
@@ -46,10 +51,11 @@ pub fn spawn_mdns_scanner() -> (
 
     let handle = service.handle();
     let RuntimeHandle::Tokio(runtime_handle) = tauri::async_runtime::handle();
+    let servers = core.mdns_servers.clone();
 
     moosicbox_task::spawn_on("mdns_scanner", &runtime_handle, async move {
         while let Ok(server) = rx.recv().await {
-            let mut servers = MOOSICBOX_SERVERS.write().await;
+            let mut servers = servers.write().await;
 
             if !servers.iter().any(|x| x.dns == server.dns) {
                 servers.push(server.into());
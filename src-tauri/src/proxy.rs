@@ -0,0 +1,370 @@
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use moosicbox_app_logging::Redacted;
+use reqwest::{header::HeaderMap, Method, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use tauri::{
+    ipc::{self, Channel, InvokeResponseBody},
+    AppHandle,
+};
+use tauri_plugin_dialog::DialogExt as _;
+use tokio_util::io::ReaderStream;
+
+use crate::{auth, current_secrets, http, with_proxy_headers, AppCore, TauriPlayerError};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ProxyMethod {
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    Head,
+    Options,
+}
+
+impl From<ProxyMethod> for Method {
+    fn from(value: ProxyMethod) -> Self {
+        match value {
+            ProxyMethod::Get => Self::GET,
+            ProxyMethod::Post => Self::POST,
+            ProxyMethod::Put => Self::PUT,
+            ProxyMethod::Patch => Self::PATCH,
+            ProxyMethod::Delete => Self::DELETE,
+            ProxyMethod::Head => Self::HEAD,
+            ProxyMethod::Options => Self::OPTIONS,
+        }
+    }
+}
+
+/// Request body. `file` streams a local file, e.g. a cover upload, instead of passing its bytes
+/// through IPC. It takes the id returned by `pick_upload_file`, so only files the user picked can
+/// be sent.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub enum ProxyBody {
+    Json(serde_json::Value),
+    Text(String),
+    Bytes(Vec<u8>),
+    Form(BTreeMap<String, String>),
+    File(String),
+}
+
+/// How the response body is returned. A JSON body that fails to parse on an error response is
+/// returned as text.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProxyResponseType {
+    #[default]
+    Json,
+    Text,
+    Bytes,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyRequest {
+    pub method: ProxyMethod,
+    /// Path relative to the API URL
+    pub url: String,
+    #[serde(default)]
+    pub query: BTreeMap<String, String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<ProxyBody>,
+    #[serde(default)]
+    pub response_type: ProxyResponseType,
+    /// Allows aborting the request with `cancel_request`
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyResponse {
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: ProxyResponseBody,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub enum ProxyResponseBody {
+    Empty,
+    Json(serde_json::Value),
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+/// Sent as JSON through the channel passed to `api_proxy_stream`. Body chunks are sent between
+/// `Started` and `Finished` as raw bytes, which arrive as an `ArrayBuffer`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(
+    tag = "event",
+    content = "data",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ProxyStreamEvent {
+    Started {
+        status: u16,
+        headers: BTreeMap<String, String>,
+        content_length: Option<u64>,
+    },
+    Finished {
+        bytes: u64,
+    },
+}
+
+/// Opens a file dialog and returns an id to send the picked file with as a `file` body, or `None`
+/// if the dialog was cancelled
+#[tauri::command]
+pub async fn pick_upload_file(
+    app: AppHandle,
+    core: tauri::State<'_, AppCore>,
+    filters: Option<BTreeMap<String, Vec<String>>>,
+) -> Result<Option<String>, TauriPlayerError> {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let mut dialog = app.dialog().file();
+
+    for (name, extensions) in filters.iter().flatten() {
+        let extensions = extensions.iter().map(String::as_str).collect::<Vec<_>>();
+        dialog = dialog.add_filter(name, &extensions);
+    }
+
+    dialog.pick_file(move |path| {
+        let _ = tx.send(path);
+    });
+
+    let Some(path) = rx
+        .await
+        .ok()
+        .flatten()
+        .and_then(|x| x.as_path().map(Path::to_path_buf))
+    else {
+        log::debug!("pick_upload_file: cancelled");
+        return Ok(None);
+    };
+
+    let id = format!("upload-{}", NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    log::debug!("pick_upload_file: id={id}");
+    core.upload_files.write().await.insert(id.clone(), path);
+
+    Ok(Some(id))
+}
+
+/// Sends a request to the API with any method and body. Error statuses are returned in the
+/// response rather than as an error.
+#[tauri::command]
+pub async fn api_proxy_request(
+    core: tauri::State<'_, AppCore>,
+    request: ProxyRequest,
+) -> Result<ProxyResponse, TauriPlayerError> {
+    log::debug!(
+        "api_proxy_request: method={:?} url={}",
+        request.method,
+        Redacted(&request.url)
+    );
+
+    http::cancellable(
+        &core,
+        request.request_id.clone(),
+        proxy_request(&core, &request),
+    )
+    .await
+}
+
+async fn proxy_request(
+    core: &AppCore,
+    request: &ProxyRequest,
+) -> Result<ProxyResponse, TauriPlayerError> {
+    let response = send(core, request, false).await?;
+    let status = response.status();
+    let headers = response_headers(response.headers());
+    let bytes = response.bytes().await?;
+
+    let body = if bytes.is_empty() {
+        ProxyResponseBody::Empty
+    } else {
+        match request.response_type {
+            ProxyResponseType::Json => match serde_json::from_slice(&bytes) {
+                Ok(value) => ProxyResponseBody::Json(value),
+                Err(e) if status.is_success() => return Err(e.into()),
+                Err(_) => ProxyResponseBody::Text(String::from_utf8_lossy(&bytes).into_owned()),
+            },
+            ProxyResponseType::Text => {
+                ProxyResponseBody::Text(String::from_utf8_lossy(&bytes).into_owned())
+            }
+            ProxyResponseType::Bytes => ProxyResponseBody::Bytes(bytes.to_vec()),
+        }
+    };
+
+    Ok(ProxyResponse {
+        status: status.as_u16(),
+        headers,
+        body,
+    })
+}
+
+/// Like `api_proxy_request`, but sends the response body through `on_event` as it arrives, for
+/// large downloads such as exports. Returns the number of body bytes received.
+#[tauri::command]
+pub async fn api_proxy_stream(
+    core: tauri::State<'_, AppCore>,
+    request: ProxyRequest,
+    on_event: Channel<ipc::Response>,
+) -> Result<u64, TauriPlayerError> {
+    log::debug!(
+        "api_proxy_stream: method={:?} url={}",
+        request.method,
+        Redacted(&request.url)
+    );
+
+    http::cancellable(
+        &core,
+        request.request_id.clone(),
+        proxy_stream(&core, &request, &on_event),
+    )
+    .await
+}
+
+async fn proxy_stream(
+    core: &AppCore,
+    request: &ProxyRequest,
+    on_event: &Channel<ipc::Response>,
+) -> Result<u64, TauriPlayerError> {
+    let mut response = send(core, request, true).await?;
+
+    send_event(
+        on_event,
+        &ProxyStreamEvent::Started {
+            status: response.status().as_u16(),
+            headers: response_headers(response.headers()),
+            content_length: response.content_length(),
+        },
+    )?;
+
+    let mut bytes = 0;
+
+    while let Some(chunk) = response.chunk().await? {
+        bytes += chunk.len() as u64;
+        on_event.send(ipc::Response::new(chunk.to_vec()))?;
+    }
+
+    log::debug!("api_proxy_stream: finished bytes={bytes}");
+    send_event(on_event, &ProxyStreamEvent::Finished { bytes })?;
+
+    Ok(bytes)
+}
+
+fn send_event(
+    on_event: &Channel<ipc::Response>,
+    event: &ProxyStreamEvent,
+) -> Result<(), TauriPlayerError> {
+    let json =
+        serde_json::to_string(event).map_err(|e| TauriPlayerError::Serialization(e.to_string()))?;
+
+    Ok(on_event.send(ipc::Response::new(InvokeResponseBody::Json(json)))?)
+}
+
+/// Sends `request`, refreshing the credentials and replaying it once if the server rejects them.
+/// `streaming` responses have no total timeout.
+async fn send(
+    core: &AppCore,
+    request: &ProxyRequest,
+    streaming: bool,
+) -> Result<Response, TauriPlayerError> {
+    let upload = match &request.body {
+        Some(ProxyBody::File(id)) => Some(
+            core.upload_files
+                .write()
+                .await
+                .remove(id)
+                .ok_or_else(|| TauriPlayerError::UploadFileNotFound(id.clone()))?,
+        ),
+        _ => None,
+    };
+
+    let secrets = current_secrets(core).await;
+    let send = |builder: RequestBuilder| async move {
+        if streaming {
+            http::send_streaming(core, builder).await
+        } else {
+            http::send(core, builder).await
+        }
+    };
+
+    let response = send(build(core, request, upload.as_deref()).await?).await?;
+
+    if auth::is_unauthorized_status(response.status())
+        && auth::refresh_credentials(core, &secrets).await
+    {
+        return send(build(core, request, upload.as_deref()).await?).await;
+    }
+
+    Ok(response)
+}
+
+/// `upload` is the path of a `file` body
+async fn build(
+    core: &AppCore,
+    request: &ProxyRequest,
+    upload: Option<&Path>,
+) -> Result<RequestBuilder, TauriPlayerError> {
+    let api_url = core
+        .api_url
+        .read()
+        .await
+        .clone()
+        .ok_or(TauriPlayerError::ApiUrlNotSet)?;
+
+    let client = http::client(core).await?;
+    let mut builder = client.request(request.method.into(), format!("{api_url}/{}", request.url));
+
+    if !request.query.is_empty() {
+        builder = builder.query(&request.query);
+    }
+
+    let headers = request
+        .headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()));
+    builder = with_proxy_headers(core, builder, headers).await;
+
+    Ok(match &request.body {
+        None => builder,
+        Some(ProxyBody::Json(value)) => builder.json(value),
+        Some(ProxyBody::Text(text)) => builder.body(text.clone()),
+        Some(ProxyBody::Bytes(bytes)) => builder.body(bytes.clone()),
+        Some(ProxyBody::Form(form)) => builder.form(form),
+        Some(ProxyBody::File(id)) => {
+            let path = upload.ok_or_else(|| TauriPlayerError::UploadFileNotFound(id.clone()))?;
+            let file = tokio::fs::File::open(path).await?;
+            builder.body(reqwest::Body::wrap_stream(ReaderStream::new(file)))
+        }
+    })
+}
+
+/// Repeated headers are joined with `, `
+fn response_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    let mut values = BTreeMap::<String, String>::new();
+
+    for (name, value) in headers {
+        let value = String::from_utf8_lossy(value.as_bytes());
+        values
+            .entry(name.to_string())
+            .and_modify(|x| {
+                x.push_str(", ");
+                x.push_str(&value);
+            })
+            .or_insert_with(|| value.into_owned());
+    }
+
+    values
+}
@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use moosicbox_core::types::PlaybackQuality;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{cache::HttpCacheConfig, connections::ConnectionRegistry, http::HttpConfig, AppState};
+
+/// Written with the settings so a later format change can tell which layout it reads
+pub const SETTINGS_VERSION: u32 = 1;
+
+const SETTINGS_FILE_NAME: &str = "settings.json";
+
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error("Unsupported settings version {0}")]
+    UnsupportedVersion(u32),
+}
+
+/// State persisted across app restarts
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Settings {
+    pub version: u32,
+    pub state: AppState,
+    pub playback_quality: Option<PlaybackQuality>,
+    #[serde(default)]
+    pub connections: ConnectionRegistry,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub cache: HttpCacheConfig,
+}
+
+#[derive(Deserialize)]
+struct SettingsVersion {
+    version: u32,
+}
+
+pub fn settings_path(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join(SETTINGS_FILE_NAME)
+}
+
+/// Reads the settings at `path`, or `None` if nothing was saved yet
+pub fn load(path: &Path) -> Result<Option<Settings>, SettingsError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let SettingsVersion { version } = serde_json::from_str(&contents)?;
+
+    if version != SETTINGS_VERSION {
+        return Err(SettingsError::UnsupportedVersion(version));
+    }
+
+    Ok(Some(serde_json::from_str(&contents)?))
+}
+
+/// Writes `settings` to `path` through a temporary file so a crash can't leave it half written
+pub fn save(path: &Path, settings: &Settings) -> Result<(), SettingsError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, serde_json::to_vec_pretty(settings)?)?;
+    std::fs::rename(&tmp_path, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &tempfile::TempDir, contents: &str) -> PathBuf {
+        let path = settings_path(dir.path());
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_rejects_unknown_versions() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for version in [0, SETTINGS_VERSION + 1] {
+            let path = write(
+                &dir,
+                &format!(r#"{{"version":{version},"state":{{}},"playbackQuality":null}}"#),
+            );
+
+            assert!(matches!(
+                load(&path),
+                Err(SettingsError::UnsupportedVersion(x)) if x == version
+            ));
+        }
+    }
+
+    #[test]
+    fn saved_settings_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = settings_path(dir.path());
+
+        save(
+            &path,
+            &Settings {
+                version: SETTINGS_VERSION,
+                state: AppState::default(),
+                playback_quality: None,
+                connections: ConnectionRegistry::default(),
+                http: HttpConfig::default(),
+                cache: HttpCacheConfig::default(),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(load(&path).unwrap().unwrap().version, SETTINGS_VERSION);
+    }
+
+    #[test]
+    fn load_returns_none_without_file() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert!(load(&settings_path(dir.path())).unwrap().is_none());
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+};
+
+use moosicbox_app_credentials::CredentialStore;
+use moosicbox_app_http_cache::HttpCache;
+use moosicbox_app_tls::TlsConfig;
+use moosicbox_app_ws::{OrderedDispatcher, WsHandle};
+use moosicbox_audio_output::AudioOutputFactory;
+use moosicbox_audio_zone::models::{ApiAudioZoneWithSession, ApiPlayer};
+use moosicbox_core::types::PlaybackQuality;
+use moosicbox_session::models::{ApiConnection, ApiSession, PlaybackTarget};
+use moosicbox_upnp::player::UpnpAvTransportService;
+use moosicbox_ws::models::InboundPayload;
+use reqwest::Client;
+use serde::Serialize;
+use tauri::{
+    async_runtime::{Mutex, RwLock},
+    AppHandle, Emitter,
+};
+use tokio::{sync::watch, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    cache::HttpCacheConfig,
+    connections::ConnectionRegistry,
+    credentials::ConnectionSecrets,
+    diagnostics::WsHistoryEntry,
+    http::{HttpClientKey, HttpConfig, RequestRegistry},
+    mdns::MoosicBoxServer,
+    settings::{self, Settings, SettingsError},
+    ApiPlayersMap, LogLayer, PlaybackTargetSessionPlayer, PlayerType, WsMessageKey,
+    WS_MESSAGE_CONCURRENCY,
+};
+
+/// Connection, session and player state for one app instance.
+///
+/// Registered with `tauri::Manager::manage` and passed to the player, WS and UPnP logic.
+/// Clones share the same state.
+#[derive(Clone)]
+pub struct AppCore {
+    app: Arc<OnceLock<AppHandle>>,
+    settings_path: Arc<OnceLock<PathBuf>>,
+    settings_lock: Arc<std::sync::Mutex<()>>,
+    /// `true` once `restore_settings` finished
+    settings_restored: Arc<watch::Sender<bool>>,
+    credentials: Arc<OnceLock<CredentialStore>>,
+    http_cache: Arc<OnceLock<Arc<HttpCache>>>,
+    art_cache: Arc<OnceLock<Arc<HttpCache>>>,
+    log_layer: Arc<OnceLock<LogLayer>>,
+    /// Held while refreshing credentials. Holds the last refreshed credentials.
+    pub(crate) auth_refresh: Arc<Mutex<Option<ConnectionSecrets>>>,
+    /// Held while switching connections so two switches can't interleave
+    pub(crate) connection_switch: Arc<Mutex<()>>,
+    pub(crate) upnp_listener_handle: Option<moosicbox_upnp::listener::Handle>,
+    pub(crate) api_url: Arc<RwLock<Option<String>>>,
+    pub(crate) profile: Arc<RwLock<Option<String>>>,
+    pub(crate) ws_url: Arc<RwLock<Option<String>>>,
+    pub(crate) ws_connection_id: Arc<RwLock<Option<String>>>,
+    /// Set when `on_ws_connected` gave up waiting for the connection id, so that `ws-connect`
+    /// is emitted once it arrives
+    pub(crate) ws_connect_pending: Arc<RwLock<bool>>,
+    pub(crate) connections: Arc<RwLock<ConnectionRegistry>>,
+    pub(crate) connection_id: Arc<RwLock<Option<String>>>,
+    pub(crate) connection_name: Arc<RwLock<Option<String>>>,
+    pub(crate) signature_token: Arc<RwLock<Option<String>>>,
+    pub(crate) client_id: Arc<RwLock<Option<String>>>,
+    pub(crate) api_token: Arc<RwLock<Option<String>>>,
+    pub(crate) tls_config: Arc<RwLock<Option<TlsConfig>>>,
+    /// Whether the WS handshake may fall back to query string authentication
+    pub(crate) ws_query_auth: Arc<RwLock<bool>>,
+    pub(crate) http_config: Arc<RwLock<HttpConfig>>,
+    /// Shared by every API request so connections are pooled
+    pub(crate) http_client: Arc<RwLock<Option<(HttpClientKey, Client)>>>,
+    pub(crate) http_requests: Arc<RequestRegistry>,
+    pub(crate) http_cache_config: Arc<RwLock<HttpCacheConfig>>,
+    pub(crate) ws_token: Arc<RwLock<Option<CancellationToken>>>,
+    pub(crate) ws_handle: Arc<RwLock<Option<WsHandle>>>,
+    pub(crate) ws_join_handle: Arc<RwLock<Option<JoinHandle<()>>>>,
+    pub(crate) ws_message_buffer: Arc<RwLock<Vec<InboundPayload>>>,
+    pub(crate) ws_message_dispatcher: OrderedDispatcher<WsMessageKey>,
+    /// The most recent WS status changes, oldest first
+    pub(crate) ws_history: Arc<RwLock<VecDeque<WsHistoryEntry>>>,
+    pub(crate) audio_zone_active_api_players: Arc<RwLock<ApiPlayersMap>>,
+    pub(crate) active_players: Arc<RwLock<Vec<PlaybackTargetSessionPlayer>>>,
+    pub(crate) playback_quality: Arc<RwLock<Option<PlaybackQuality>>>,
+    pub(crate) current_playback_target: Arc<RwLock<Option<PlaybackTarget>>>,
+    pub(crate) current_connections: Arc<RwLock<Vec<ApiConnection>>>,
+    pub(crate) pending_player_sessions: Arc<RwLock<HashMap<u64, u64>>>,
+    pub(crate) current_sessions: Arc<RwLock<Vec<ApiSession>>>,
+    pub(crate) current_session_id: Arc<RwLock<Option<u64>>>,
+    pub(crate) current_audio_zones: Arc<RwLock<Vec<ApiAudioZoneWithSession>>>,
+    #[allow(clippy::type_complexity)]
+    pub(crate) current_players: Arc<RwLock<Vec<(ApiPlayer, PlayerType, AudioOutputFactory)>>>,
+    pub(crate) upnp_av_transport_services: Arc<RwLock<Vec<UpnpAvTransportService>>>,
+    /// Servers found by the mDNS scanner
+    pub(crate) mdns_servers: Arc<RwLock<Vec<MoosicBoxServer>>>,
+    /// Files picked with `pick_upload_file`, by the id returned to the webview
+    pub(crate) upload_files: Arc<RwLock<HashMap<String, PathBuf>>>,
+    /// Events emitted without an app handle
+    #[cfg(test)]
+    pub(crate) emitted: Arc<std::sync::Mutex<Vec<(String, serde_json::Value)>>>,
+}
+
+impl Default for AppCore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AppCore {
+    pub fn new() -> Self {
+        Self {
+            app: Arc::new(OnceLock::new()),
+            settings_path: Arc::new(OnceLock::new()),
+            settings_lock: Arc::new(std::sync::Mutex::new(())),
+            settings_restored: Arc::new(watch::channel(false).0),
+            credentials: Arc::new(OnceLock::new()),
+            http_cache: Arc::new(OnceLock::new()),
+            art_cache: Arc::new(OnceLock::new()),
+            log_layer: Arc::new(OnceLock::new()),
+            auth_refresh: Arc::new(Mutex::new(None)),
+            connection_switch: Arc::new(Mutex::new(())),
+            upnp_listener_handle: None,
+            api_url: Arc::new(RwLock::new(None)),
+            profile: Arc::new(RwLock::new(None)),
+            ws_url: Arc::new(RwLock::new(None)),
+            ws_connection_id: Arc::new(RwLock::new(None)),
+            ws_connect_pending: Arc::new(RwLock::new(false)),
+            connections: Arc::new(RwLock::new(ConnectionRegistry::default())),
+            connection_id: Arc::new(RwLock::new(None)),
+            connection_name: Arc::new(RwLock::new(None)),
+            signature_token: Arc::new(RwLock::new(None)),
+            client_id: Arc::new(RwLock::new(None)),
+            api_token: Arc::new(RwLock::new(None)),
+            tls_config: Arc::new(RwLock::new(None)),
+            ws_query_auth: Arc::new(RwLock::new(false)),
+            http_config: Arc::new(RwLock::new(HttpConfig::default())),
+            http_client: Arc::new(RwLock::new(None)),
+            http_requests: Arc::new(RequestRegistry::default()),
+            http_cache_config: Arc::new(RwLock::new(HttpCacheConfig::default())),
+            ws_token: Arc::new(RwLock::new(None)),
+            ws_handle: Arc::new(RwLock::new(None)),
+            ws_join_handle: Arc::new(RwLock::new(None)),
+            ws_message_buffer: Arc::new(RwLock::new(vec![])),
+            ws_message_dispatcher: OrderedDispatcher::new(WS_MESSAGE_CONCURRENCY),
+            ws_history: Arc::new(RwLock::new(VecDeque::new())),
+            audio_zone_active_api_players: Arc::new(RwLock::new(HashMap::new())),
+            active_players: Arc::new(RwLock::new(vec![])),
+            playback_quality: Arc::new(RwLock::new(None)),
+            current_playback_target: Arc::new(RwLock::new(None)),
+            current_connections: Arc::new(RwLock::new(vec![])),
+            pending_player_sessions: Arc::new(RwLock::new(HashMap::new())),
+            current_sessions: Arc::new(RwLock::new(vec![])),
+            current_session_id: Arc::new(RwLock::new(None)),
+            current_audio_zones: Arc::new(RwLock::new(vec![])),
+            current_players: Arc::new(RwLock::new(vec![])),
+            upnp_av_transport_services: Arc::new(RwLock::new(vec![])),
+            mdns_servers: Arc::new(RwLock::new(vec![])),
+            upload_files: Arc::new(RwLock::new(HashMap::new())),
+            #[cfg(test)]
+            emitted: Arc::new(std::sync::Mutex::new(vec![])),
+        }
+    }
+
+    /// UPnP players are only created when a listener handle is set
+    pub fn with_upnp_listener_handle(mut self, handle: moosicbox_upnp::listener::Handle) -> Self {
+        self.upnp_listener_handle = Some(handle);
+        self
+    }
+
+    /// Sets the app handle used to emit events and reach the player plugin.
+    ///
+    /// Only the first call has an effect.
+    pub fn set_app_handle(&self, app: AppHandle) {
+        let _ = self.app.set(app);
+    }
+
+    pub fn app_handle(&self) -> Option<&AppHandle> {
+        self.app.get()
+    }
+
+    /// Sets the file state is persisted to. Nothing is persisted until this is called.
+    ///
+    /// Only the first call has an effect.
+    pub fn set_settings_path(&self, path: PathBuf) {
+        let _ = self.settings_path.set(path);
+    }
+
+    pub fn settings_path(&self) -> Option<&PathBuf> {
+        self.settings_path.get()
+    }
+
+    /// Sets the store connection tokens are kept in. Without one, tokens are not persisted.
+    ///
+    /// Only the first call has an effect.
+    pub fn set_credential_store(&self, store: CredentialStore) {
+        let _ = self.credentials.set(store);
+    }
+
+    pub fn credentials(&self) -> Option<&CredentialStore> {
+        self.credentials.get()
+    }
+
+    /// Sets the cache proxied GET responses are kept in.
+    ///
+    /// Only the first call has an effect.
+    pub fn set_http_cache(&self, cache: HttpCache) {
+        let _ = self.http_cache.set(Arc::new(cache));
+    }
+
+    /// `None` until the cache is opened after the settings are restored, or if the cache dir
+    /// couldn't be opened, in which case requests aren't cached
+    pub fn http_cache(&self) -> Option<Arc<HttpCache>> {
+        self.http_cache.get().cloned()
+    }
+
+    /// Sets the cache album covers served through `moosicbox-art` are kept in.
+    ///
+    /// Only the first call has an effect.
+    pub fn set_art_cache(&self, cache: HttpCache) {
+        let _ = self.art_cache.set(Arc::new(cache));
+    }
+
+    /// `None` if the cache dir couldn't be opened, in which case covers are fetched every time
+    pub fn art_cache(&self) -> Option<Arc<HttpCache>> {
+        self.art_cache.get().cloned()
+    }
+
+    /// Sets the remote log layer that connection details are attached to as properties.
+    ///
+    /// Only the first call has an effect.
+    pub fn set_log_layer(&self, layer: LogLayer) {
+        let _ = self.log_layer.set(layer);
+    }
+
+    /// `None` if remote logging isn't initialized, e.g. with `TOKIO_CONSOLE=1`
+    pub fn log_layer(&self) -> Option<&LogLayer> {
+        self.log_layer.get()
+    }
+
+    pub(crate) fn set_settings_restored(&self) {
+        self.settings_restored.send_replace(true);
+    }
+
+    /// Waits until the persisted settings were applied, so that state set by the frontend isn't
+    /// overwritten by the restore or saved over the file before it was read
+    pub(crate) async fn settings_restored(&self) {
+        let _ = self
+            .settings_restored
+            .subscribe()
+            .wait_for(|restored| *restored)
+            .await;
+    }
+
+    /// The persisted settings, or `None` if there is no settings path or nothing was saved yet
+    pub fn load_settings(&self) -> Result<Option<Settings>, SettingsError> {
+        let Some(path) = self.settings_path.get() else {
+            return Ok(None);
+        };
+
+        let _lock = self.settings_lock.lock().unwrap();
+        settings::load(path)
+    }
+
+    /// Does nothing if there is no settings path
+    pub fn save_settings(&self, settings: &Settings) -> Result<(), SettingsError> {
+        let Some(path) = self.settings_path.get() else {
+            return Ok(());
+        };
+
+        let _lock = self.settings_lock.lock().unwrap();
+        settings::save(path, settings)
+    }
+
+    /// Emits `event` to the frontend. Does nothing if no app handle is set.
+    pub fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) -> Result<(), tauri::Error> {
+        match self.app.get() {
+            Some(app) => app.emit(event, payload),
+            None => {
+                log::trace!("emit: no app handle, dropping event={event}");
+                #[cfg(test)]
+                self.emitted
+                    .lock()
+                    .unwrap()
+                    .push((event.to_string(), serde_json::to_value(payload).unwrap()));
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether `other` is a clone of this instance
+    pub fn is_same_instance(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.active_players, &other.active_players)
+    }
+
+    /// Whether any of this instance's players belong to the session `session_id`
+    pub(crate) async fn has_session_player(&self, session_id: u64) -> bool {
+        self.active_players.read().await.iter().any(|x| {
+            x.session_id == session_id
+                || x.player
+                    .playback
+                    .read()
+                    .unwrap()
+                    .as_ref()
+                    .is_some_and(|p| p.session_id == session_id)
+        })
+    }
+}